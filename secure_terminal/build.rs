@@ -0,0 +1,127 @@
+//! Challenge pack compiler
+//! Loads the TOML manifest describing the default filesystem, encrypts each
+//! file with its cipher and generates `$OUT_DIR/challenge.rs`, which
+//! `src/challenge.rs` includes. Encrypted contents are written next to it and
+//! embedded with `include_bytes!`.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+#[macro_use]
+extern crate lazy_static;
+
+// Same cipher code, key and manifest rules as the library and `ctf-author`
+#[allow(dead_code)]
+#[path = "src/encryption.rs"]
+mod encryption;
+#[allow(dead_code)]
+#[path = "src/pack.rs"]
+mod pack;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/encryption.rs");
+    println!("cargo:rerun-if-changed=src/pack.rs");
+    println!("cargo:rerun-if-env-changed=SECURE_TERMINAL_CHALLENGE");
+    println!("cargo:rerun-if-env-changed=SECURE_TERMINAL_PASSPHRASE");
+
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let manifest = manifest_dir.join(
+        env::var("SECURE_TERMINAL_CHALLENGE").unwrap_or_else(|_| pack::DEFAULT_MANIFEST.into()),
+    );
+    println!("cargo:rerun-if-changed={}", manifest.display());
+
+    let pack = pack::load(&manifest).unwrap_or_else(|err| panic!("{}", err));
+    for source in &pack.sources {
+        println!("cargo:rerun-if-changed={}", source.display());
+    }
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let blob_dir = out_dir.join("challenge");
+    fs::create_dir_all(&blob_dir).unwrap();
+
+    let mut code = String::from("// Generated by build.rs from the challenge manifest\n\n");
+
+    code.push_str("pub static DIRECTORIES: &[DirSpec] = &[\n");
+    for dir in &pack.dirs {
+        writeln!(
+            code,
+            "    DirSpec {{ path: {:?}, mode: 0o{:o}, uid: {}, gid: {} }},",
+            dir.path, dir.mode, dir.uid, dir.gid
+        )
+        .unwrap();
+    }
+    code.push_str("];\n\n");
+
+    code.push_str("pub static FILES: &[FileSpec] = &[\n");
+    for (index, file) in pack.files.iter().enumerate() {
+        let blob = blob_dir.join(format!("{}.bin", index));
+        fs::write(&blob, file.cipher.encrypt(&file.plaintext)).unwrap();
+        writeln!(
+            code,
+            "    FileSpec {{ path: {:?}, cipher: Cipher::{:?}, mode: 0o{:o}, uid: {}, gid: {}, \
+             content: include_bytes!({:?}) }},",
+            file.path,
+            file.cipher,
+            file.mode,
+            file.uid,
+            file.gid,
+            blob.display().to_string()
+        )
+        .unwrap();
+    }
+    code.push_str("];\n\n");
+
+    code.push_str("pub static SYMLINKS: &[SymlinkSpec] = &[\n");
+    for link in &pack.symlinks {
+        writeln!(
+            code,
+            "    SymlinkSpec {{ path: {:?}, target: {:?}, uid: {}, gid: {} }},",
+            link.path, link.target, link.uid, link.gid
+        )
+        .unwrap();
+    }
+    code.push_str("];\n\n");
+
+    writeln!(
+        code,
+        "pub static PLAYER_PASSWORD_HASH: &str = {:?};\n",
+        pack.player.password_hash
+    )
+    .unwrap();
+
+    code.push_str("pub static CHALLENGES: &[ChallengeSpec] = &[\n");
+    for (index, challenge) in pack.challenges.iter().enumerate() {
+        writeln!(
+            code,
+            "    ChallengeSpec {{ id: {:?}, name: {:?}, points: {}, flag_hash: {:?}, \
+             description: {:?}, hints: &[",
+            challenge.id,
+            challenge.name,
+            challenge.points,
+            challenge.flag_hash,
+            challenge.description
+        )
+        .unwrap();
+
+        // Hints are AES-encrypted like file content, so they cannot be grepped out
+        for (tier, hint) in challenge.hints.iter().enumerate() {
+            let blob = blob_dir.join(format!("hint-{}-{}.bin", index, tier));
+            fs::write(&blob, encryption::Cipher::Aes.encrypt(hint.text.as_bytes())).unwrap();
+            writeln!(
+                code,
+                "        HintSpec {{ cost: {}, delay_minutes: {:?}, text: include_bytes!({:?}) }},",
+                hint.cost,
+                hint.delay_minutes,
+                blob.display().to_string()
+            )
+            .unwrap();
+        }
+        code.push_str("    ] },\n");
+    }
+    code.push_str("];\n");
+
+    fs::write(out_dir.join("challenge.rs"), code).unwrap();
+}
@@ -0,0 +1,282 @@
+//! Challenge authoring tool
+//! Encrypts and decrypts content with the engine's ciphers, validates
+//! challenge packs, dumps the embedded filesystem and hashes flags
+//!
+//! Usage: ctf-author <command> [options]
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use secure_terminal::pack::{self, DEFAULT_MANIFEST};
+use secure_terminal::{
+    base64_decode, base64_encode, hash_string, random_salt, Cipher, FileSystem, FileType,
+};
+
+const USAGE: &str = "\
+Usage: ctf-author <command> [options]
+
+Commands:
+  encrypt [-c CIPHER] [-f FORMAT] [-o FILE] (FILE | -s TEXT)
+      Encrypt a file (- for stdin) or a string
+  decrypt [-c CIPHER] [-f FORMAT] [-o FILE] (FILE | -s DATA)
+      Decrypt data produced by encrypt
+  validate [MANIFEST]
+      Check a challenge pack (default: challenges/default.toml)
+  tree [--contents]
+      List the embedded filesystem, optionally with decrypted text
+  hash-flag [--salt SALT] FLAG
+      Print the salted hash stored for a flag
+
+CIPHER is aes (default), xor or plain. FORMAT is how ciphertext is written
+or read: base64 (default), bytes (a [1, 2, 3] list) or raw.";
+
+/// How ciphertext is represented on the command line or in files
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Base64,
+    Bytes,
+    Raw,
+}
+
+/// Options shared by `encrypt` and `decrypt`
+struct CipherArgs {
+    cipher: Cipher,
+    format: Format,
+    output: Option<String>,
+    input: Vec<u8>,
+}
+
+/// A command-line error, printed with the usage text when `usage` is set
+struct Failure {
+    message: String,
+    usage: bool,
+}
+
+impl Failure {
+    fn usage(message: impl Into<String>) -> Self {
+        Failure {
+            message: message.into(),
+            usage: true,
+        }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Failure {
+            message: message.into(),
+            usage: false,
+        }
+    }
+}
+
+fn parse_cipher_args(args: &[String]) -> Result<CipherArgs, Failure> {
+    let mut cipher = Cipher::Aes;
+    let mut format = Format::Base64;
+    let mut output = None;
+    let mut input = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |flag: &str| {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| Failure::usage(format!("option {} needs a value", flag)))
+        };
+        match arg.as_str() {
+            "-c" | "--cipher" => {
+                let name = value(arg)?;
+                cipher = pack::parse_cipher(&name)
+                    .ok_or_else(|| Failure::usage(format!("unknown cipher '{}'", name)))?;
+            }
+            "-f" | "--format" => {
+                format = match value(arg)?.as_str() {
+                    "base64" => Format::Base64,
+                    "bytes" => Format::Bytes,
+                    "raw" => Format::Raw,
+                    other => return Err(Failure::usage(format!("unknown format '{}'", other))),
+                };
+            }
+            "-o" | "--output" => output = Some(value(arg)?),
+            "-s" | "--string" => input = Some(value(arg)?.into_bytes()),
+            path if input.is_none() => input = Some(read_input(path)?),
+            _ => return Err(Failure::usage(format!("unexpected argument '{}'", arg))),
+        }
+    }
+
+    let input = input.ok_or_else(|| Failure::usage("missing input file or -s"))?;
+    Ok(CipherArgs {
+        cipher,
+        format,
+        output,
+        input,
+    })
+}
+
+/// Reads a file, or stdin for `-`
+fn read_input(path: &str) -> Result<Vec<u8>, Failure> {
+    let read = if path == "-" {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes).map(|_| bytes)
+    } else {
+        fs::read(path)
+    };
+    read.map_err(|err| Failure::error(format!("{}: {}", path, err)))
+}
+
+/// Writes to `-o FILE`, or stdout
+fn write_output(output: Option<&str>, bytes: &[u8]) -> Result<(), Failure> {
+    let written = match output {
+        Some(path) => fs::write(path, bytes),
+        None => io::stdout().write_all(bytes),
+    };
+    written.map_err(|err| Failure::error(format!("{}: {}", output.unwrap_or("stdout"), err)))
+}
+
+/// Parses `[1, 2, 3]` (optionally written as `vec![...]`) into bytes
+fn parse_byte_list(text: &str) -> Option<Vec<u8>> {
+    let inner = text.trim().trim_start_matches("vec!");
+    let inner = inner.strip_prefix('[')?.strip_suffix(']')?;
+    inner
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| item.parse().ok())
+        .collect()
+}
+
+fn encrypt(args: &[String]) -> Result<(), Failure> {
+    let args = parse_cipher_args(args)?;
+    let ciphertext = args.cipher.encrypt(&args.input);
+
+    let encoded = match args.format {
+        Format::Base64 => format!("{}\n", base64_encode(&ciphertext)).into_bytes(),
+        Format::Bytes => format!("{:?}\n", ciphertext).into_bytes(),
+        Format::Raw => ciphertext,
+    };
+    write_output(args.output.as_deref(), &encoded)
+}
+
+fn decrypt(args: &[String]) -> Result<(), Failure> {
+    let args = parse_cipher_args(args)?;
+
+    let ciphertext = match args.format {
+        Format::Raw => Some(args.input),
+        Format::Base64 => base64_decode(String::from_utf8_lossy(&args.input).trim()),
+        Format::Bytes => parse_byte_list(&String::from_utf8_lossy(&args.input)),
+    }
+    .ok_or_else(|| Failure::error("input is not valid for the chosen format"))?;
+
+    let plaintext = args
+        .cipher
+        .decrypt(&ciphertext)
+        .ok_or_else(|| Failure::error("decryption failed (wrong cipher or key?)"))?;
+    write_output(args.output.as_deref(), &plaintext)
+}
+
+fn validate(args: &[String]) -> Result<(), Failure> {
+    let manifest = match args {
+        [] => DEFAULT_MANIFEST,
+        [path] => path.as_str(),
+        _ => return Err(Failure::usage("validate takes at most one manifest")),
+    };
+
+    let pack = pack::load(Path::new(manifest)).map_err(Failure::error)?;
+    println!(
+        "{}: ok ({} directories, {} files, {} symlinks, {} challenges)",
+        manifest,
+        pack.dirs.len(),
+        pack.files.len(),
+        pack.symlinks.len(),
+        pack.challenges.len()
+    );
+    Ok(())
+}
+
+fn tree(args: &[String]) -> Result<(), Failure> {
+    let contents = match args {
+        [] => false,
+        [flag] if flag == "--contents" => true,
+        _ => return Err(Failure::usage("tree only accepts --contents")),
+    };
+
+    let fs = FileSystem::new();
+    let paths = fs.walk("/").map_err(|err| Failure::error(err.to_string()))?;
+    let mut listing = String::new();
+    for path in paths {
+        let Ok(node) = fs.symlink_metadata(&path) else {
+            continue;
+        };
+        let plaintext = fs.read_file(&path).ok().filter(|_| node.ftype == FileType::File);
+        let size = plaintext.as_ref().map_or(0, Vec::len);
+        let cipher = format!("{:?}", node.cipher).to_lowercase();
+
+        listing.push_str(&format!(
+            "{} {:>5} {:>5} {:<5} {:>8} {}",
+            node.mode_string(),
+            node.uid,
+            node.gid,
+            cipher,
+            size,
+            path
+        ));
+        if let Ok(target) = fs.read_link(&path) {
+            listing.push_str(&format!(" -> {}", target));
+        }
+        listing.push('\n');
+
+        if contents && let Some(text) = plaintext.and_then(|bytes| String::from_utf8(bytes).ok()) {
+            for line in text.lines() {
+                listing.push_str(&format!("    | {}\n", line));
+            }
+        }
+    }
+    write_output(None, listing.as_bytes())
+}
+
+fn hash_flag(args: &[String]) -> Result<(), Failure> {
+    let (salt, flag) = match args {
+        [flag] => (random_salt(), flag),
+        [option, salt, flag] if option == "--salt" => (salt.clone(), flag),
+        _ => return Err(Failure::usage("hash-flag takes [--salt SALT] FLAG")),
+    };
+    if salt.is_empty() || salt.contains('$') {
+        return Err(Failure::usage("salt must be non-empty and must not contain '$'"));
+    }
+
+    println!("{}", hash_string(flag, &salt));
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some((command, rest)) = args.split_first() else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+
+    let result = match command.as_str() {
+        "encrypt" => encrypt(rest),
+        "decrypt" => decrypt(rest),
+        "validate" => validate(rest),
+        "tree" => tree(rest),
+        "hash-flag" => hash_flag(rest),
+        "-h" | "--help" | "help" => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        other => Err(Failure::usage(format!("unknown command '{}'", other))),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) if failure.usage => {
+            eprintln!("ctf-author: {}\n\n{}", failure.message, USAGE);
+            ExitCode::from(2)
+        }
+        Err(failure) => {
+            eprintln!("ctf-author: {}", failure.message);
+            ExitCode::from(1)
+        }
+    }
+}
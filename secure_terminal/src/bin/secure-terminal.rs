@@ -0,0 +1,254 @@
+//! Secure Terminal REPL
+//! Runs the command engine on stdin/stdout so challenges can be played and
+//! debugged from a Linux shell
+//!
+//! Usage: secure-terminal [--fixed-time <ms>] [--config <json>] [--state <file>]
+//!                        [-c <command>]
+
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::process::{self, Command, ExitCode};
+
+use secure_terminal::{
+    base64_decode, Clock, CommandResponse, Effect, FixedClock, SystemClock, Terminal,
+    TerminalConfig,
+};
+
+const USAGE: &str = "Usage: secure-terminal [--fixed-time <ms>] [--config <json>] \
+                     [--state <file>] [-c <command>]";
+
+/// A terminal session plus the host terminal the REPL talks to
+struct Repl {
+    terminal: Terminal,
+    interactive: bool,
+}
+
+impl Repl {
+    fn run(&mut self, line: &str) -> CommandResponse {
+        self.terminal.execute(line)
+    }
+
+    /// Whether `rm -i` is waiting for a yes/no answer on the next line
+    fn awaiting_answer(&self) -> bool {
+        self.terminal.state().pending_removal.is_some()
+    }
+
+    /// Prompt text such as `CSE23@admin:/home$ `, or a pending question
+    fn prompt(&mut self) -> String {
+        format!("{} ", self.terminal.prompt())
+    }
+
+    /// Prints output and applies the effects a text terminal can show
+    ///
+    /// # Returns
+    /// The hidden-input prompt to show next, if the command asked for a password
+    fn show(&self, response: &CommandResponse) -> Option<String> {
+        if !response.stdout.is_empty() {
+            println!("{}", response.stdout);
+        }
+        if !response.stderr.is_empty() {
+            eprintln!("{}", response.stderr);
+        }
+
+        let mut password_prompt = None;
+        for effect in &response.effects {
+            match effect {
+                Effect::Clear if self.interactive => print!("\x1b[2J\x1b[H"),
+                Effect::Download { filename, data } => save_download(filename, data),
+                Effect::PasswordPrompt { prompt } => password_prompt = Some(prompt.clone()),
+                // Themes only exist in the browser; reboot already reset the state
+                _ => {}
+            }
+        }
+        password_prompt
+    }
+}
+
+/// Writes a `downld` file into the host's current directory, never overwriting
+fn save_download(filename: &str, data: &str) {
+    let Some(bytes) = base64_decode(data) else {
+        eprintln!("downld: {}: corrupt download", filename);
+        return;
+    };
+
+    let path = Path::new(filename);
+    if path.exists() {
+        eprintln!("downld: {}: already exists on the host, not overwritten", filename);
+        return;
+    }
+    match fs::write(path, &bytes) {
+        Ok(()) => println!("Saved {} ({} bytes)", filename, bytes.len()),
+        Err(err) => eprintln!("downld: {}: {}", filename, err),
+    }
+}
+
+/// Width of the host terminal: `$COLUMNS`, else `stty size` when stdout is a terminal
+fn host_columns() -> Option<u32> {
+    if let Some(columns) = std::env::var("COLUMNS").ok().and_then(|value| value.parse().ok()) {
+        return Some(columns);
+    }
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    let output = Command::new("stty")
+        .arg("size")
+        .stdin(process::Stdio::inherit())
+        .output()
+        .ok()?;
+    // `stty size` prints "rows columns"
+    let size = String::from_utf8_lossy(&output.stdout);
+    size.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Turns terminal echo on or off while a password is typed
+fn set_echo(enabled: bool) {
+    let flag = if enabled { "echo" } else { "-echo" };
+    let _ = Command::new("stty").arg(flag).stdin(process::Stdio::inherit()).status();
+}
+
+/// Reads one line, hiding it when it is a password typed on a terminal
+///
+/// # Returns
+/// The line without its newline, or `None` at end of input
+fn read_line(input: &mut impl BufRead, hidden: bool) -> Option<String> {
+    let hide = hidden && io::stdin().is_terminal();
+    if hide {
+        set_echo(false);
+    }
+
+    let mut line = String::new();
+    let read = input.read_line(&mut line);
+
+    if hide {
+        set_echo(true);
+        println!();
+    }
+    match read {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end_matches(['\n', '\r']).to_string()),
+    }
+}
+
+fn main() -> ExitCode {
+    let mut clock: Box<dyn Clock> = Box::new(SystemClock);
+    let mut config = TerminalConfig::default();
+    let mut command = None;
+    let mut state_file = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fixed-time" => match args.next().and_then(|ms| ms.parse().ok()) {
+                Some(ms) => clock = Box::new(FixedClock(ms)),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::from(2);
+                }
+            },
+            "--config" => match args.next().map(|json| TerminalConfig::from_json(&json)) {
+                Some(Ok(parsed)) => config = parsed,
+                Some(Err(err)) => {
+                    eprintln!("secure-terminal: {}", err);
+                    return ExitCode::from(2);
+                }
+                None => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::from(2);
+                }
+            },
+            "--state" => match args.next() {
+                Some(path) => state_file = Some(path),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::from(2);
+                }
+            },
+            "-c" => match args.next() {
+                Some(line) => command = Some(line),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::from(2);
+                }
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ => {
+                eprintln!("secure-terminal: unknown option '{}'\n{}", arg, USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    let stdin = io::stdin();
+    let mut repl = Repl {
+        terminal: Terminal::with_config(config).with_clock(clock),
+        interactive: command.is_none() && stdin.is_terminal(),
+    };
+    let mut input = stdin.lock();
+    if let Some(columns) = host_columns() {
+        repl.terminal.set_terminal_width(columns);
+    }
+
+    // --state resumes from a snapshot when the file exists and saves on exit
+    if let Some(path) = &state_file
+        && let Ok(snapshot) = fs::read_to_string(path)
+        && let Err(err) = repl.terminal.restore(&snapshot)
+    {
+        eprintln!("secure-terminal: {}: {}", path, err);
+        return ExitCode::from(1);
+    }
+
+    // With -c, the command runs once; password prompts and `rm -i` questions
+    // still read from stdin
+    let one_shot = command.is_some();
+    let mut pending = command;
+    let mut password_prompt: Option<String> = None;
+
+    loop {
+        let line = match pending.take() {
+            Some(line) => line,
+            None => {
+                if let Some(prompt) = &password_prompt {
+                    print!("{}", prompt);
+                } else if repl.interactive || repl.awaiting_answer() {
+                    print!("{}", repl.prompt());
+                }
+                let _ = io::stdout().flush();
+
+                match read_line(&mut input, password_prompt.is_some()) {
+                    Some(line) => line,
+                    None => break,
+                }
+            }
+        };
+
+        // `exit` outside a nested `su` session leaves the REPL
+        let trimmed = line.trim();
+        if password_prompt.is_none()
+            && !repl.awaiting_answer()
+            && matches!(trimmed, "exit" | "logout")
+            && repl.terminal.state().sessions.is_empty()
+        {
+            break;
+        }
+
+        let response = repl.run(&line);
+        password_prompt = repl.show(&response);
+        let _ = io::stdout().flush();
+
+        if one_shot && password_prompt.is_none() && !repl.awaiting_answer() {
+            break;
+        }
+    }
+
+    if let Some(path) = &state_file
+        && let Err(err) = fs::write(path, repl.terminal.export_state())
+    {
+        eprintln!("secure-terminal: {}: {}", path, err);
+    }
+
+    ExitCode::from(repl.terminal.state().last_status.clamp(0, 255) as u8)
+}
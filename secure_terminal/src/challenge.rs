@@ -0,0 +1,87 @@
+//! Challenge module
+//! The bundled challenge pack: directories, files, symlinks, the flag
+//! registry and the player's password hash (`PLAYER_PASSWORD_HASH`), compiled
+//! by `build.rs` from `challenges/default.toml`, with file contents and hints
+//! already encrypted and flags and the password kept only as salted hashes
+
+use crate::encryption::Cipher;
+use crate::filesystem::{FileSystem, FsError};
+
+/// A directory to create (or, if it exists, to re-own and chmod)
+pub struct DirSpec {
+    pub path: &'static str,
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// A file with its encrypted content
+pub struct FileSpec {
+    pub path: &'static str,
+    pub cipher: Cipher,
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    pub content: &'static [u8],
+}
+
+/// A symbolic link
+pub struct SymlinkSpec {
+    pub path: &'static str,
+    pub target: &'static str,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// A challenge in the registry; the flag itself never ships, only its hash
+pub struct ChallengeSpec {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub points: u32,
+    pub flag_hash: &'static str,
+    pub description: &'static str,
+    pub hints: &'static [HintSpec],
+}
+
+/// One hint tier; `text` is AES-encrypted and only decrypted when revealed
+pub struct HintSpec {
+    pub cost: u32,
+    pub delay_minutes: f64,
+    pub text: &'static [u8],
+}
+
+impl HintSpec {
+    /// Decrypted hint text
+    pub fn reveal(&self) -> String {
+        Cipher::Aes.decode(self.text)
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/challenge.rs"));
+
+/// Creates the pack's directories, then its files, then its symlinks
+///
+/// # Arguments
+/// * `fs` - Filesystem to populate
+/// * `time` - Timestamp given to every new node
+pub fn install(fs: &mut FileSystem, time: f64) -> Result<(), FsError> {
+    for dir in DIRECTORIES {
+        if !fs.is_dir(dir.path) {
+            fs.create_dir(dir.path, time)?;
+        }
+        fs.set_mode(dir.path, dir.mode)?;
+        fs.set_owner(dir.path, Some(dir.uid), Some(dir.gid))?;
+    }
+
+    for file in FILES {
+        fs.create_file(file.path, file.content.to_vec(), file.cipher, time)?;
+        fs.set_mode(file.path, file.mode)?;
+        fs.set_owner(file.path, Some(file.uid), Some(file.gid))?;
+    }
+
+    for link in SYMLINKS {
+        fs.create_symlink(link.path, link.target, time)?;
+        fs.lchown(link.path, Some(link.uid), Some(link.gid))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+//! Clock module
+//! Time source for sudo timeouts, file timestamps and `date`, injectable so
+//! the command engine runs the same in the browser and natively
+
+/// Source of the current time, in milliseconds since the Unix epoch
+pub trait Clock {
+    fn now(&self) -> f64;
+}
+
+/// Wall clock: `Date.now()` in the browser, `SystemTime` everywhere else
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    fn now(&self) -> f64 {
+        js_sys::Date::now()
+    }
+
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    fn now(&self) -> f64 {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs_f64() * 1000.0)
+            .unwrap_or(0.0)
+    }
+}
+
+/// Clock stopped at a fixed instant, for reproducible sessions
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FixedClock(pub f64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> f64 {
+        self.0
+    }
+}
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Formats a timestamp (ms since the epoch) like `date`: `Sat Oct 17 14:03:09 UTC 2026`
+pub fn format_date(ms: f64) -> String {
+    let secs = (ms / 1000.0).floor() as i64;
+    let days = secs.div_euclid(86_400);
+    let time = secs.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{} {} {:2} {:02}:{:02}:{:02} UTC {}",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        MONTHS[(month - 1) as usize],
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        year
+    )
+}
+
+/// Formats a timestamp like `stat`: `2026-10-17 14:03:09.250000000 +0000`
+pub fn format_timestamp(ms: f64) -> String {
+    let secs = (ms / 1000.0).floor() as i64;
+    let nanos = ((ms - secs as f64 * 1000.0) * 1_000_000.0).round() as i64;
+    let time = secs.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02}:{:02}.{:09} +0000",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        nanos.clamp(0, 999_999_999)
+    )
+}
+
+/// Formats a modification time like `ls -l`: `Oct 17 14:03` when it is from the
+/// last six months, otherwise `Oct 17  2025` (future times also show the year)
+///
+/// # Arguments
+/// * `ms` - Timestamp to format (ms since the epoch)
+/// * `now` - Current time (ms), deciding which form is used
+pub fn format_listing_time(ms: f64, now: f64) -> String {
+    const HALF_YEAR_MS: f64 = 365.2425 / 2.0 * 86_400_000.0;
+
+    let secs = (ms / 1000.0).floor() as i64;
+    let time = secs.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let month = MONTHS[(month - 1) as usize];
+
+    if ms <= now && now - ms < HALF_YEAR_MS {
+        format!("{} {:2} {:02}:{:02}", month, day, time / 3600, time % 3600 / 60)
+    } else {
+        format!("{} {:2} {:>5}", month, day, year)
+    }
+}
+
+/// Converts days since 1970-01-01 to a (year, month, day) Gregorian date
+///
+/// Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -1,15 +1,27 @@
-/// Command execution module
-/// Handles all shell command processing and execution
+//! Command execution module
+//! Handles all shell command processing and execution
 
-use crate::encryption::{decode, base64_encode};
-use crate::filesystem::FileSystem;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use crate::diff;
+use crate::encryption::{base64_encode, Cipher};
+use crate::filesystem::{
+    Credentials, FileNode, FileSystem, FileType, FsError, DEFAULT_DIR_MODE, DEFAULT_FILE_MODE,
+    MODE_SETGID, MODE_SETUID, MODE_STICKY, PERM_EXEC, PERM_READ, PERM_WRITE, BLOCK_SIZE,
+};
+use crate::regex::{Regex, Syntax};
+use crate::response::{CommandResponse, Effect};
+use crate::scoring::{self, HintReveal, Submission};
+use crate::shell::{self, Command, Connector, List, Pipeline, Redirect, RedirectKind, SimpleCommand};
+use crate::trash;
+use crate::users::{self, Account, PasswdStage, PendingAuth, Session, SUDO_GID};
+use crate::challenge::CHALLENGES;
+use crate::clock::{self, Clock};
 use crate::SudoState;
 use crate::TerminalState;
-use js_sys::Date;
-use wasm_bindgen::prelude::JsValue;
 
 const THEMES: [&str; 4] = ["matrix", "sunset", "dracula", "light"];
-const TEMP_DISABLED_COMMANDS: [&str; 2] = ["rm", "sudo"];
 
 fn is_binary_file(path: &str, content: &[u8]) -> bool {
     let lower = path.to_ascii_lowercase();
@@ -38,6 +50,26 @@ fn basename(path: &str) -> &str {
     path.rsplit('/').next().unwrap_or(path)
 }
 
+/// Joins a directory path and an entry name
+fn join_path(dir: &str, name: &str) -> String {
+    let name = name.trim_end_matches('/');
+    if dir == "/" {
+        format!("/{}", name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Reports a failed filesystem operation as `<context>: <errno message>`
+///
+/// The exit status comes from the error, so every command maps errors alike.
+fn fs_error(context: impl std::fmt::Display, err: FsError) -> CommandResponse {
+    CommandResponse {
+        status: err.status(),
+        ..CommandResponse::error(format!("{}: {}", context, err))
+    }
+}
+
 fn resolve_copy_move_destination(fs: &FileSystem, source: &str, destination_arg: &str) -> String {
     let destination = fs.resolve_path(destination_arg);
     if fs.exists(&destination) && fs.is_dir(&destination) {
@@ -58,380 +90,5882 @@ fn resolve_copy_move_destination(fs: &FileSystem, source: &str, destination_arg:
 /// * `fs` - Mutable reference to the filesystem
 /// * `sudo` - Mutable reference to sudo state
 /// * `term` - Mutable reference to terminal session state
+/// * `clock` - Time source for timestamps, sudo timeouts and `date`
 /// * `input` - The command string to execute
 ///
 /// # Returns
-/// Command output as a string
+/// Structured response with output streams, exit status and side effects
 pub fn execute_command(
     fs: &mut FileSystem,
     sudo: &mut SudoState,
     term: &mut TerminalState,
+    clock: &dyn Clock,
     input: &str,
-) -> String {
+) -> CommandResponse {
     let trimmed = input.trim();
+    let now = clock.now();
 
-    // Check if we're waiting for a password
-    if sudo.waiting_for_password {
-        sudo.waiting_for_password = false;
-        sudo.pending_command = None;
-
-        if trimmed == "__INTERRUPT__" || trimmed == "^C" {
-            return "^C".to_string();
+    // The line answers a prompt; once nothing else is asked, the parked command line goes on
+    if awaiting_input(sudo, term) {
+        term.stdout_redirected = term.parked.first().is_some_and(ParkedFrame::stdout_redirected);
+        let response = if sudo.waiting_for_password {
+            handle_sudo_password(fs, sudo, term, trimmed, now)
+        } else if term.pending_auth.is_some() {
+            handle_auth_password(fs, sudo, term, trimmed, now)
+        } else {
+            handle_removal_answer(fs, term, trimmed, now)
+        };
+        term.stdout_redirected = false;
+        if awaiting_input(sudo, term) {
+            return response;
         }
 
-        return "sudo: command temporarily disabled".to_string();
+        let frames = std::mem::take(&mut term.parked);
+        let response = resume(fs, sudo, term, frames, response, now);
+        term.last_status = response.status;
+        return response;
     }
 
-    let parts: Vec<&str> = input.trim().split_whitespace().collect();
-    if parts.is_empty() {
-        return "".to_string();
+    if trimmed.is_empty() {
+        return CommandResponse::empty();
     }
 
-    let cmd = parts[0];
-    let args = &parts[1..];
-    let now = Date::now();
+    term.history.push(trimmed.to_string());
+    term.progress.start(now);
+
+    let list = match shell::parse(trimmed) {
+        Ok(Some(list)) => list,
+        Ok(None) => return CommandResponse::empty(),
+        Err(err) => {
+            term.last_status = 2;
+            return CommandResponse {
+                status: 2,
+                ..CommandResponse::error(format!("sh: {}", err))
+            };
+        }
+    };
+
+    run_list(fs, sudo, term, &list, None, now)
+}
 
-    // Temporarily disable privileged/destructive commands.
-    if TEMP_DISABLED_COMMANDS.contains(&cmd) {
-        return format!("command not found: {}. Type 'help' for info.", cmd);
+/// Prompt for the next input line: `user@host:cwd$` (`#` for root), or the
+/// question of a pending `rm -i`
+pub fn prompt(fs: &FileSystem, sudo: &SudoState, term: &TerminalState) -> String {
+    if let Some(pending) = &term.pending_removal {
+        return pending.question.clone();
     }
 
-    // Internal command used by frontend to refresh prompt without polluting history
-    if cmd == "__pwd__" {
-        return handle_pwd(fs);
+    let creds = credentials(sudo, term);
+    let sigil = if creds.is_root() { '#' } else { '$' };
+    format!(
+        "{}@{}:{}{}",
+        users::user_name(fs, creds.uid),
+        term.config.hostname,
+        fs.current_path,
+        sigil
+    )
+}
+
+/// Names in the working directory for tab completion (directories end in
+/// `/`); empty when the current user may not list it
+pub fn completions(fs: &FileSystem, sudo: &SudoState, term: &TerminalState) -> Vec<String> {
+    let cwd = &fs.current_path;
+    match fs.access(cwd, &credentials(sudo, term), PERM_READ) {
+        Ok(()) => fs.list_directory(cwd).unwrap_or_default(),
+        Err(_) => Vec::new(),
     }
-    if cmd == "__ls__" {
-        return handle_ls(fs, &[]);
+}
+
+/// Cancels whatever is waiting for the next line (a sudo, su, login or
+/// passwd password prompt, or an `rm -i` question), like Ctrl-C; `$?`
+/// becomes 130
+///
+/// The rest of the command line is dropped, leaving any `( ... )` group it
+/// was inside.
+pub fn interrupt(fs: &mut FileSystem, sudo: &mut SudoState, term: &mut TerminalState) {
+    sudo.waiting_for_password = false;
+    sudo.pending_command = None;
+    sudo.pending_stdin = None;
+    term.pending_auth = None;
+    term.pending_removal = None;
+    for frame in std::mem::take(&mut term.parked) {
+        if let Some((path, env)) = frame.group {
+            leave_group(fs, term, path, env);
+        }
     }
+    term.last_status = 130;
+}
+
+/// Runs pipelines joined by `;`, `&&` and `||`, updating `$?` after each one
+///
+/// `stdin` feeds the first pipeline.
+fn run_list(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    list: &List,
+    stdin: Option<&str>,
+    now: f64,
+) -> CommandResponse {
+    let response = run_pipeline(fs, sudo, term, &list.first, stdin, now);
+    term.last_status = response.status;
+    continue_list(fs, sudo, term, &list.rest, response, now)
+}
 
-    term.history.push(input.trim().to_string());
+/// Runs the remaining pipelines of a list after one that ended with `response`
+///
+/// A pipeline that stops at a prompt parks the pipelines after it, since
+/// they cannot run until the prompt is answered.
+fn continue_list(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    rest: &[(Connector, Pipeline)],
+    mut response: CommandResponse,
+    now: f64,
+) -> CommandResponse {
+    for (idx, (connector, pipeline)) in rest.iter().enumerate() {
+        if awaiting_input(sudo, term) {
+            if let Some(frame) = term.parked.last_mut() {
+                frame.list_rest = rest[idx..].to_vec();
+            }
+            break;
+        }
 
-    // Handle sudo command: sudo <command> [args...]
-    if cmd == "sudo" && !args.is_empty() {
-        let actual_cmd = args[0];
-        let actual_args = &args[1..];
-        
-        // Build the full command to execute after password
-        let full_cmd = format!("{} {}", actual_cmd, actual_args.join(" "));
-        
-        // Ask for password
-        sudo.waiting_for_password = true;
-        sudo.pending_command = Some(full_cmd);
-        return "[sudo] password: ".to_string();
+        let run = match connector {
+            Connector::Sequence => true,
+            Connector::And => response.status == 0,
+            Connector::Or => response.status != 0,
+        };
+        if run {
+            response.append(run_pipeline(fs, sudo, term, pipeline, None, now));
+            term.last_status = response.status;
+        }
     }
 
-    match cmd {
-        "pwd" => handle_pwd(fs),
-        "cd" => handle_cd(fs, args),
-        "ls" => handle_ls(fs, args),
-        "cat" => handle_cat(fs, args),
-        "mkdir" => handle_mkdir(fs, args, now),
-        "touch" => handle_touch(fs, args, now),
-        "cp" => handle_cp(fs, args),
-        "mv" => handle_mv(fs, args),
-        "rm" => handle_rm(fs, sudo, args),
-        "date" => handle_date(now),
-        "echo" => handle_echo(args),
-        "whoami" => handle_whoami(),
-        "sudo" => handle_sudo_usage(),
-        "history" => handle_history(term),
-        "theme" => handle_theme(term, args),
-        "reboot" => handle_reboot(fs, sudo, term),
-        "help" => handle_help(),
-        "clear" => "CLEARED".to_string(),
-        "downld" => handle_download(fs, args),
-        _ => format!("command not found: {}. Type 'help' for info.", cmd),
+    response
+}
+
+/// Runs a pipeline, feeding each command's stdout into the next command's stdin
+///
+/// The result carries the last command's stdout and status, and the stderr
+/// and effects of every command in the pipeline.
+fn run_pipeline(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    pipeline: &Pipeline,
+    stdin: Option<&str>,
+    now: f64,
+) -> CommandResponse {
+    let input = stdin.map(str::to_string);
+    continue_pipeline(fs, sudo, term, &pipeline.commands, CommandResponse::empty(), input, now)
+}
+
+/// Runs the remaining commands of a pipeline
+///
+/// `combined` holds what the earlier commands produced and `input` is the
+/// stdout feeding the next one. A command that stops at a prompt parks the
+/// commands after it.
+fn continue_pipeline(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    commands: &[Command],
+    mut combined: CommandResponse,
+    mut input: Option<String>,
+    now: f64,
+) -> CommandResponse {
+    let last = commands.len().saturating_sub(1);
+    for (idx, command) in commands.iter().enumerate() {
+        // Output feeding the next command is not shown on the screen
+        let outer = term.stdout_redirected;
+        term.stdout_redirected = outer || idx < last;
+        let mut response = run_command(fs, sudo, term, command, input.take().as_deref(), now);
+        term.stdout_redirected = outer;
+        input = Some(std::mem::take(&mut response.stdout));
+        combined.append(response);
+
+        if awaiting_input(sudo, term) {
+            if let Some(frame) = term.parked.last_mut() {
+                frame.pipeline_rest = commands[idx + 1..].to_vec();
+                frame.outer_redirected = outer;
+            }
+            break;
+        }
     }
+
+    combined.stdout = input.unwrap_or_default();
+    combined
 }
 
-/// sudo - Usage helper when no subcommand is provided
-fn handle_sudo_usage() -> String {
-    "Usage: sudo <command> [args...]\nExample: sudo rm projects.txt".to_string()
+/// Whether a password prompt or an `rm -i` question is waiting for the next line
+fn awaiting_input(sudo: &SudoState, term: &TerminalState) -> bool {
+    sudo.waiting_for_password || term.pending_auth.is_some() || term.pending_removal.is_some()
 }
 
-/// pwd - Print working directory
-fn handle_pwd(fs: &FileSystem) -> String {
-    fs.current_path.clone()
+/// What is left of one nesting level of a command line when a command in it
+/// stops at a prompt
+///
+/// The command (or the `( ... )` group holding it) waits here with its output
+/// redirects, the commands after it in its pipeline and the pipelines after
+/// that in its list, until the prompt is answered.
+#[derive(Clone, Debug)]
+pub struct ParkedFrame {
+    creds: Credentials,
+    /// Already opened when the command parked, so the rest of its output appends
+    redirects: Vec<(RedirectKind, String)>,
+    /// Working directory and variables to restore when a group ends
+    group: Option<(String, HashMap<String, String>)>,
+    pipeline_rest: Vec<Command>,
+    list_rest: Vec<(Connector, Pipeline)>,
+    outer_redirected: bool, // Whether the pipeline's own output feeds a pipe or file
 }
 
-/// cd - Change directory
-fn handle_cd(fs: &mut FileSystem, args: &[&str]) -> String {
-    if args.is_empty() {
-        fs.current_path = "/home".to_string();
-        return "".to_string();
+impl ParkedFrame {
+    /// Parks a command or group that stopped at a prompt
+    ///
+    /// What it printed so far goes through its redirects now; later output is
+    /// appended to the same files.
+    fn park(
+        fs: &mut FileSystem,
+        term: &mut TerminalState,
+        creds: Credentials,
+        redirects: Vec<(RedirectKind, String)>,
+        group: Option<(String, HashMap<String, String>)>,
+        response: &mut CommandResponse,
+        now: f64,
+    ) {
+        apply_output_redirects(fs, &creds, term.umask, &redirects, response, now);
+        let redirects = redirects
+            .into_iter()
+            .map(|(kind, target)| {
+                let kind = match kind {
+                    RedirectKind::Output => RedirectKind::Append,
+                    RedirectKind::Error => RedirectKind::ErrorAppend,
+                    other => other,
+                };
+                (kind, target)
+            })
+            .collect();
+        term.parked.push(ParkedFrame {
+            creds,
+            redirects,
+            group,
+            pipeline_rest: Vec::new(),
+            list_rest: Vec::new(),
+            outer_redirected: term.stdout_redirected,
+        });
     }
 
-    let target = fs.resolve_path(args[0]);
-    if fs.exists(&target) && fs.is_dir(&target) {
-        fs.current_path = target;
-        "".to_string()
-    } else {
-        format!("cd: {}: No such file or directory", args[0])
+    /// Whether the parked command's stdout goes to a pipe or file rather than the screen
+    fn stdout_redirected(&self) -> bool {
+        self.outer_redirected || !self.pipeline_rest.is_empty() || redirects_stdout(&self.redirects)
     }
 }
 
-/// ls - List directory contents
-fn handle_ls(fs: &FileSystem, args: &[&str]) -> String {
-    let dir_path = if args.is_empty() {
-        fs.current_path.clone()
-    } else {
-        fs.resolve_path(args[0])
-    };
+/// Finishes a parked command line with the result of the command that asked
+///
+/// Each frame, innermost first, ends its group, applies its redirects and
+/// runs the rest of its pipeline and list. A prompt asked along the way parks
+/// what is still left.
+fn resume(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    frames: Vec<ParkedFrame>,
+    mut response: CommandResponse,
+    now: f64,
+) -> CommandResponse {
+    let mut frames = frames.into_iter();
+    while let Some(frame) = frames.next() {
+        let ParkedFrame {
+            creds,
+            redirects,
+            group,
+            pipeline_rest,
+            list_rest,
+            outer_redirected,
+        } = frame;
+        if let Some((path, env)) = group {
+            leave_group(fs, term, path, env);
+        }
+        apply_output_redirects(fs, &creds, term.umask, &redirects, &mut response, now);
 
-    if !fs.exists(&dir_path) {
-        return format!("ls: cannot access '{}': No such file or directory", args[0]);
-    }
+        let input = Some(std::mem::take(&mut response.stdout));
+        let outer = term.stdout_redirected;
+        term.stdout_redirected = outer_redirected;
+        response = continue_pipeline(fs, sudo, term, &pipeline_rest, response, input, now);
+        term.last_status = response.status;
+        if awaiting_input(sudo, term) {
+            // Parked again inside this pipeline: its list still follows
+            if let Some(parked) = term.parked.last_mut() {
+                parked.list_rest = list_rest;
+            }
+        } else {
+            response = continue_list(fs, sudo, term, &list_rest, response, now);
+        }
+        term.stdout_redirected = outer;
 
-    if !fs.is_dir(&dir_path) {
-        return format!("ls: {}: Not a directory", args[0]);
+        if awaiting_input(sudo, term) {
+            term.parked.extend(frames);
+            break;
+        }
     }
+    response
+}
 
-    let entries = fs.list_directory(&dir_path);
-    entries.join("    ")
+/// Runs a single pipeline element
+fn run_command(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    command: &Command,
+    stdin: Option<&str>,
+    now: f64,
+) -> CommandResponse {
+    match command {
+        Command::Simple(simple) => run_simple_command(fs, sudo, term, simple, stdin, now),
+        Command::Group { body, redirects } => {
+            run_group(fs, sudo, term, body, redirects, stdin, now)
+        }
+    }
 }
 
-/// cat - Display file contents
-fn handle_cat(fs: &FileSystem, args: &[&str]) -> String {
-    if args.is_empty() {
-        return "Usage: cat <filename>".to_string();
+/// Runs a `( ... )` group as a subshell: cwd and variables are restored afterwards
+fn run_group(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    body: &List,
+    redirects: &[Redirect],
+    stdin: Option<&str>,
+    now: f64,
+) -> CommandResponse {
+    let redirects = expand_redirects(fs, term, redirects);
+    let creds = credentials(sudo, term);
+    let stdin = match redirected_stdin(fs, &creds, &redirects, stdin) {
+        Ok(stdin) => stdin,
+        Err(response) => return response,
+    };
+
+    let saved_path = fs.current_path.clone();
+    let saved_env = term.env.clone();
+    let outer = term.stdout_redirected;
+    term.stdout_redirected = outer || redirects_stdout(&redirects);
+    let mut response = run_list(fs, sudo, term, body, stdin.as_deref(), now);
+    term.stdout_redirected = outer;
+
+    // The subshell lasts until the rest of its body has run after the prompt
+    if awaiting_input(sudo, term) {
+        let group = Some((saved_path, saved_env));
+        ParkedFrame::park(fs, term, creds, redirects, group, &mut response, now);
+        return response;
     }
 
-    let target = fs.resolve_path(args[0]);
+    leave_group(fs, term, saved_path, saved_env);
+
+    apply_output_redirects(fs, &creds, term.umask, &redirects, &mut response, now);
+    response
+}
 
-    if !fs.exists(&target) {
-        return format!("cat: {}: No such file", args[0]);
+/// Restores the working directory and variables a `( ... )` group started with
+fn leave_group(
+    fs: &mut FileSystem,
+    term: &mut TerminalState,
+    path: String,
+    env: HashMap<String, String>,
+) {
+    if fs.is_dir(&path) {
+        fs.current_path = path;
     }
+    term.env = env;
+}
 
-    if fs.is_dir(&target) {
-        return format!("cat: {}: Is a directory", args[0]);
+/// Looks up a shell variable for expansion
+fn lookup_variable(fs: &FileSystem, term: &TerminalState, name: &str) -> Option<String> {
+    match name {
+        "?" => Some(term.last_status.to_string()),
+        "PWD" => Some(fs.current_path.clone()),
+        _ => term.env.get(name).cloned(),
     }
+}
 
-    if let Some(content) = fs.get_file_content(&target) {
-        if is_binary_file(&target, content) {
-            return format!(
-                "cat: {}: binary file (use downld to download)",
-                args[0]
-            );
-        }
-        decode(content)
+/// Expands a parsed command, applies its redirections and runs it
+///
+/// Leading `NAME=value` assignments persist when no command follows;
+/// otherwise they only apply while the command runs.
+fn run_simple_command(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    command: &SimpleCommand,
+    stdin: Option<&str>,
+    now: f64,
+) -> CommandResponse {
+    let lookup = |name: &str| lookup_variable(fs, term, name);
+    let assignments: Vec<(String, String)> = command
+        .assignments
+        .iter()
+        .map(|(name, value)| (name.clone(), value.expand(&lookup)))
+        .collect();
+    let argv: Vec<String> = command.words.iter().map(|word| word.expand(&lookup)).collect();
+    let redirects = expand_redirects(fs, term, &command.redirects);
+    let creds = credentials(sudo, term);
+    let stdin = match redirected_stdin(fs, &creds, &redirects, stdin) {
+        Ok(stdin) => stdin,
+        Err(response) => return response,
+    };
+
+    let outer = term.stdout_redirected;
+    term.stdout_redirected = outer || redirects_stdout(&redirects);
+    let mut response = if argv.is_empty() {
+        term.env.extend(assignments);
+        CommandResponse::empty()
     } else {
-        "Error reading file".to_string()
+        run_with_assignments(fs, sudo, term, &argv, assignments, stdin.as_deref(), now)
+    };
+    term.stdout_redirected = outer;
+
+    if awaiting_input(sudo, term) {
+        ParkedFrame::park(fs, term, creds, redirects, None, &mut response, now);
+        return response;
     }
+
+    apply_output_redirects(fs, &creds, term.umask, &redirects, &mut response, now);
+    response
 }
 
-/// mkdir - Create directory
-fn handle_mkdir(fs: &mut FileSystem, args: &[&str], now: f64) -> String {
-    if args.is_empty() {
-        return "Usage: mkdir <directory>".to_string();
-    }
+/// Runs argv with temporary `NAME=value` assignments in the environment
+fn run_with_assignments(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    argv: &[String],
+    assignments: Vec<(String, String)>,
+    stdin: Option<&str>,
+    now: f64,
+) -> CommandResponse {
 
-    let target = fs.resolve_path(args[0]);
+    let saved: Vec<(String, Option<String>)> = assignments
+        .into_iter()
+        .map(|(name, value)| {
+            let previous = term.env.insert(name.clone(), value);
+            (name, previous)
+        })
+        .collect();
 
-    if fs.exists(&target) {
-        format!("mkdir: cannot create directory '{}': File exists", args[0])
-    } else {
-        fs.create_dir(&target, now);
-        "".to_string()
+    let args: Vec<&str> = argv[1..].iter().map(String::as_str).collect();
+    let response = dispatch(fs, sudo, term, &argv[0], &args, stdin, now);
+
+    for (name, previous) in saved.into_iter().rev() {
+        match previous {
+            Some(value) => term.env.insert(name, value),
+            None => term.env.remove(&name),
+        };
     }
+
+    response
 }
 
-/// touch - Create empty file or update timestamp
-fn handle_touch(fs: &mut FileSystem, args: &[&str], now: f64) -> String {
-    if args.is_empty() {
-        return "Usage: touch <filename>".to_string();
+/// Whether any redirect sends stdout to a file
+fn redirects_stdout(redirects: &[(RedirectKind, String)]) -> bool {
+    redirects
+        .iter()
+        .any(|(kind, _)| matches!(kind, RedirectKind::Output | RedirectKind::Append))
+}
+
+/// Expands redirect targets
+fn expand_redirects(
+    fs: &FileSystem,
+    term: &TerminalState,
+    redirects: &[Redirect],
+) -> Vec<(RedirectKind, String)> {
+    let lookup = |name: &str| lookup_variable(fs, term, name);
+    redirects
+        .iter()
+        .map(|redirect| (redirect.kind, redirect.target.expand(&lookup)))
+        .collect()
+}
+
+/// Resolves the stdin a command sees: the last `< file`, or the piped input
+fn redirected_stdin(
+    fs: &FileSystem,
+    creds: &Credentials,
+    redirects: &[(RedirectKind, String)],
+    stdin: Option<&str>,
+) -> Result<Option<String>, CommandResponse> {
+    let mut stdin = stdin.map(str::to_string);
+    for (kind, target) in redirects {
+        if *kind == RedirectKind::Input {
+            match read_redirect_input(fs, creds, target) {
+                Ok(text) => stdin = Some(text),
+                Err(err) => return Err(fs_error(format!("sh: {}", target), err)),
+            }
+        }
     }
+    Ok(stdin)
+}
 
-    let target = fs.resolve_path(args[0]);
+/// Reads a file used as `< file` input
+fn read_redirect_input(
+    fs: &FileSystem,
+    creds: &Credentials,
+    target: &str,
+) -> Result<String, FsError> {
+    let path = fs.resolve_path(target);
+    fs.access(&path, creds, PERM_READ)?;
+    fs.read_file(&path).map(|content| stream_text(&content))
+}
+
+/// Turns file content into stream text
+///
+/// Files end in a newline (`write_redirect_output` adds one), while stdout,
+/// pipes and `<` input never carry a final newline, so exactly one is dropped.
+fn stream_text(content: &[u8]) -> String {
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    String::from_utf8_lossy(content).into_owned()
+}
+
+/// Moves stdout/stderr into the files named by `>`, `>>`, `2>` and `2>>`
+///
+/// Like a real shell, every target is created, but only the last redirect
+/// of each stream receives the output.
+fn apply_output_redirects(
+    fs: &mut FileSystem,
+    creds: &Credentials,
+    umask: u16,
+    redirects: &[(RedirectKind, String)],
+    response: &mut CommandResponse,
+    now: f64,
+) {
+    let is_stdout = |kind: RedirectKind| matches!(kind, RedirectKind::Output | RedirectKind::Append);
+    let last_stdout = redirects.iter().rposition(|(kind, _)| is_stdout(*kind));
+    let last_stderr = redirects
+        .iter()
+        .rposition(|(kind, _)| matches!(kind, RedirectKind::Error | RedirectKind::ErrorAppend));
+
+    for (i, (kind, target)) in redirects.iter().enumerate() {
+        let text = match kind {
+            RedirectKind::Input => continue,
+            _ if Some(i) == last_stdout => std::mem::take(&mut response.stdout),
+            _ if Some(i) == last_stderr => std::mem::take(&mut response.stderr),
+            _ => String::new(),
+        };
+        let append = matches!(kind, RedirectKind::Append | RedirectKind::ErrorAppend);
 
-    if !fs.exists(&target) {
-        fs.create_file(&target, Vec::new(), now);
+        if let Err(err) = write_redirect_output(fs, creds, umask, target, &text, append, now) {
+            if !response.stderr.is_empty() {
+                response.stderr.push('\n');
+            }
+            response.stderr.push_str(&format!("sh: {}: {}", target, err));
+            response.status = err.status();
+        }
     }
+}
 
-    "".to_string()
+/// Writes redirected output to a file, keeping an existing file's cipher
+///
+/// Stream text gets its final newline back, so files end in one.
+fn write_redirect_output(
+    fs: &mut FileSystem,
+    creds: &Credentials,
+    umask: u16,
+    target: &str,
+    text: &str,
+    append: bool,
+    now: f64,
+) -> Result<(), FsError> {
+    let path = fs.resolve_path(target);
+    let mut content = if append && !fs.is_dir(&path) {
+        fs.read_file(&path).unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    content.extend_from_slice(text.as_bytes());
+    if !text.is_empty() && !text.ends_with('\n') {
+        content.push(b'\n');
+    }
+    write_output(fs, creds, umask, target, &content, now)
 }
 
-/// cp - Copy files and directories
-fn handle_cp(fs: &mut FileSystem, args: &[&str]) -> String {
-    if args.len() < 2 {
-        return "Usage: cp <source> <destination>".to_string();
+/// Replaces a file's content byte for byte, keeping an existing file's cipher
+///
+/// New files are owned by the writer and get the umask-filtered default mode.
+fn write_output(
+    fs: &mut FileSystem,
+    creds: &Credentials,
+    umask: u16,
+    target: &str,
+    content: &[u8],
+    now: f64,
+) -> Result<(), FsError> {
+    let path = fs.resolve_path(target);
+    if fs.is_dir(&path) {
+        return Err(FsError::IsADirectory);
     }
 
-    let source = fs.resolve_path(args[0]);
-    if !fs.exists(&source) {
-        return format!("cp: cannot stat '{}': No such file or directory", args[0]);
+    let existed = fs.exists(&path);
+    if existed {
+        fs.access(&path, creds, PERM_WRITE)?;
+    } else {
+        fs.access_entry(&path, creds)?;
     }
 
-    let destination = resolve_copy_move_destination(fs, &source, args[1]);
-    if source == destination {
-        return format!("cp: '{}' and '{}' are the same file", args[0], args[1]);
+    let cipher = fs.file_cipher(&path).unwrap_or_default();
+    fs.write_file(&path, content, cipher, now)?;
+    if !existed {
+        assign_new_node(fs, &path, creds, umask)?;
     }
+    Ok(())
+}
+
+/// Runs a single parsed command
+fn dispatch(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    cmd: &str,
+    args: &[&str],
+    stdin: Option<&str>,
+    now: f64,
+) -> CommandResponse {
+    let creds = credentials(sudo, term);
+    let umask = term.umask;
 
-    match fs.copy_path(&source, &destination) {
-        Ok(()) => "".to_string(),
-        Err(err) => format!("cp: {}", err),
+    match cmd {
+        "pwd" => handle_pwd(fs),
+        "cd" => handle_cd(fs, &creds, args),
+        "ls" => handle_ls(fs, &creds, term, args, now),
+        "cat" => handle_cat(fs, &creds, args, stdin),
+        "grep" => handle_grep(fs, &creds, args, stdin),
+        "find" => handle_find(fs, sudo, term, args, now),
+        "head" => handle_head(fs, &creds, args, stdin),
+        "tail" => handle_tail(fs, &creds, args, stdin),
+        "wc" => handle_wc(fs, &creds, args, stdin),
+        "sort" => handle_sort(fs, &creds, args, stdin),
+        "uniq" => handle_uniq(fs, &creds, args, stdin),
+        "cut" => handle_cut(fs, &creds, args, stdin),
+        "tr" => handle_tr(args, stdin),
+        "rev" => handle_rev(fs, &creds, args, stdin),
+        "stat" => handle_stat(fs, &creds, args),
+        "du" => handle_du(fs, &creds, args),
+        "df" => handle_df(fs, &creds, args),
+        "tree" => handle_tree(fs, &creds, args),
+        "diff" => handle_diff(fs, &creds, args, stdin),
+        "patch" => handle_patch(fs, &creds, umask, args, stdin, now),
+        "mkdir" => handle_mkdir(fs, &creds, umask, args, now),
+        "touch" => handle_touch(fs, &creds, umask, args, now),
+        "cp" => handle_cp(fs, &creds, umask, args),
+        "mv" => handle_mv(fs, &creds, args),
+        "rm" => handle_rm(fs, term, &creds, args, stdin, now),
+        "rmdir" => handle_rmdir(fs, &creds, args),
+        "trash" => handle_trash(fs, &creds, args),
+        "chmod" => handle_chmod(fs, &creds, args, now),
+        "chown" => handle_chown(fs, &creds, args, now),
+        "chgrp" => handle_chgrp(fs, &creds, args, now),
+        "umask" => handle_umask(term, args),
+        "ln" => handle_ln(fs, &creds, args, now),
+        "readlink" => handle_readlink(fs, args),
+        "date" => handle_date(now),
+        "echo" => handle_echo(args),
+        "true" => CommandResponse::empty(),
+        "false" => CommandResponse {
+            status: 1,
+            ..CommandResponse::empty()
+        },
+        "export" => handle_export(term, args),
+        "unset" => handle_unset(term, args),
+        "env" => handle_env(fs, term),
+        "whoami" => handle_whoami(fs, &creds),
+        "id" => handle_id(fs, &creds, args),
+        "groups" => handle_groups(fs, &creds, args),
+        "su" => handle_su(fs, sudo, term, args),
+        "login" => handle_login(fs, sudo, term, args),
+        "logout" | "exit" => handle_logout(fs, sudo, term, cmd),
+        "passwd" => handle_passwd(fs, term, &creds, args),
+        "sudo" => handle_sudo(fs, sudo, term, args, stdin, now),
+        "history" => handle_history(term),
+        "theme" => handle_theme(term, args),
+        "reboot" => handle_reboot(fs, sudo, term),
+        "submit" => handle_submit(term, args, stdin, now),
+        "score" => handle_score(term),
+        "progress" => handle_progress(term),
+        "hint" => handle_hint(term, args, now),
+        "help" => handle_help(),
+        "clear" => CommandResponse::effect(Effect::Clear),
+        "downld" => handle_download(fs, &creds, args),
+        _ => command_not_found(cmd),
     }
 }
 
-/// mv - Move or rename files and directories
-fn handle_mv(fs: &mut FileSystem, args: &[&str]) -> String {
-    if args.len() < 2 {
-        return "Usage: mv <source> <destination>".to_string();
+/// Identity used for permission checks: root while elevated, otherwise the session user
+fn credentials(sudo: &SudoState, term: &TerminalState) -> Credentials {
+    if sudo.elevated {
+        Credentials::root()
+    } else {
+        term.user.clone()
     }
+}
 
-    let source = fs.resolve_path(args[0]);
-    if !fs.exists(&source) {
-        return format!("mv: cannot stat '{}': No such file or directory", args[0]);
+/// Response for an unknown command (status 127, like POSIX shells)
+fn command_not_found(cmd: &str) -> CommandResponse {
+    CommandResponse {
+        status: 127,
+        ..CommandResponse::error(format!("command not found: {}. Type 'help' for info.", cmd))
     }
+}
 
-    let destination = resolve_copy_move_destination(fs, &source, args[1]);
-    if source == destination {
-        return "".to_string();
+fn password_prompt() -> Effect {
+    hidden_prompt("[sudo] password: ")
+}
+
+/// Asks the frontend to read the next line without echoing it
+fn hidden_prompt(prompt: &str) -> Effect {
+    Effect::PasswordPrompt {
+        prompt: prompt.to_string(),
     }
+}
 
-    match fs.move_path(&source, &destination) {
-        Ok(()) => "".to_string(),
-        Err(err) => format!("mv: {}", err),
+fn format_minutes(ms: f64) -> String {
+    let minutes = (ms / 60_000.0).ceil().max(1.0) as u64;
+    if minutes == 1 {
+        "1 minute".to_string()
+    } else {
+        format!("{} minutes", minutes)
     }
 }
 
-/// rm - Remove file or empty directory (requires sudo authentication)
-fn handle_rm(fs: &mut FileSystem, sudo: &SudoState, args: &[&str]) -> String {
+/// sudo - Run a command with elevated rights
+fn handle_sudo(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    args: &[&str],
+    stdin: Option<&str>,
+    now: f64,
+) -> CommandResponse {
+    let mut args = args;
+    if args.first() == Some(&"-k") {
+        sudo.invalidate();
+        args = &args[1..];
+        if args.is_empty() {
+            return CommandResponse::empty();
+        }
+    }
+
     if args.is_empty() {
-        return "Usage: rm <filename>".to_string();
+        return CommandResponse::error(
+            "Usage: sudo <command> [args...]\n       sudo -k\nExample: sudo rm projects.txt",
+        );
     }
 
-    // Check if user is authenticated with sudo
-    if !sudo.authenticated {
-        return "rm: Permission denied. Use 'sudo <password> rm <filename>' or 'sudo <password>' first.".to_string();
+    let creds = credentials(sudo, term);
+    if !creds.is_root() && !creds.in_group(SUDO_GID) {
+        return CommandResponse::error(format!(
+            "{} is not in the sudoers file. This incident will be reported.",
+            users::user_name(fs, creds.uid)
+        ));
     }
 
-    let target = fs.resolve_path(args[0]);
+    let full_cmd: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
 
-    if !fs.exists(&target) {
-        return format!("rm: cannot remove '{}': No such file or directory", args[0]);
+    if creds.is_root() || sudo.session_valid(now) {
+        return run_elevated(fs, sudo, term, &full_cmd, stdin, now);
     }
 
-    if fs.is_dir(&target) {
-        if !fs.delete(&target) {
-            return format!("rm: cannot remove '{}': Directory not empty", args[0]);
-        }
-    } else {
-        fs.delete(&target);
+    if let Some(remaining) = sudo.lockout_remaining(now) {
+        return CommandResponse::error(format!(
+            "sudo: account locked after too many failed attempts. Try again in {}.",
+            format_minutes(remaining)
+        ));
     }
 
-    "".to_string()
+    // Ask for password
+    sudo.waiting_for_password = true;
+    sudo.pending_command = Some(full_cmd);
+    sudo.pending_stdin = stdin.map(str::to_string);
+    CommandResponse::effect(password_prompt())
 }
 
-/// date - Display current date/time
-fn handle_date(now: f64) -> String {
-    let date = Date::new(&JsValue::from_f64(now));
-    date.to_string().into()
-}
+/// Handles the line typed at a sudo password prompt
+fn handle_sudo_password(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    password: &str,
+    now: f64,
+) -> CommandResponse {
+    // Like sudo(8), the invoking user proves who they are with their own password
+    let user = users::user_name(fs, credentials(sudo, term).uid);
+    if users::check_password(fs, &user, password) {
+        sudo.waiting_for_password = false;
+        sudo.authenticate(now);
+        let pending = sudo.pending_command.take().unwrap_or_default();
+        let stdin = sudo.pending_stdin.take();
+        let response = run_elevated(fs, sudo, term, &pending, stdin.as_deref(), now);
+        term.last_status = response.status;
+        return response;
+    }
 
-/// echo - Print arguments
-fn handle_echo(args: &[&str]) -> String {
-    args.join(" ")
-}
+    sudo.failed_attempts += 1;
+    if sudo.failed_attempts >= sudo.config.max_attempts {
+        let attempts = sudo.failed_attempts;
+        sudo.waiting_for_password = false;
+        sudo.pending_command = None;
+        sudo.pending_stdin = None;
+        sudo.failed_attempts = 0;
+        sudo.locked_until = Some(now + sudo.config.lockout_minutes * 60_000.0);
+        return CommandResponse::error(format!(
+            "sudo: {} incorrect password attempts",
+            attempts
+        ));
+    }
 
-/// whoami - Display current user (encrypted)
-fn handle_whoami() -> String {
-    decode(&[185, 142, 159, 140, 18])
+    CommandResponse::error("Sorry, try again.").with_effect(password_prompt())
 }
 
-/// history - Show command history from backend session
-fn handle_history(term: &TerminalState) -> String {
-    if term.history.is_empty() {
-        return "No history yet".to_string();
+/// Runs an already-expanded command with sudo privileges, consuming one cached use
+fn run_elevated(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    argv: &[String],
+    stdin: Option<&str>,
+    now: f64,
+) -> CommandResponse {
+    let Some((cmd, rest)) = argv.split_first() else {
+        return CommandResponse::empty();
+    };
+    let args: Vec<&str> = rest.iter().map(String::as_str).collect();
+
+    if let Some(left) = sudo.commands_left.as_mut() {
+        *left = left.saturating_sub(1);
     }
 
-    term.history
-        .iter()
-        .enumerate()
-        .map(|(i, cmd)| format!("{}  {}", i + 1, cmd))
-        .collect::<Vec<String>>()
-        .join("\n")
+    let was_elevated = sudo.elevated;
+    sudo.elevated = true;
+    let response = dispatch(fs, sudo, term, cmd, &args, stdin, now);
+    sudo.elevated = was_elevated;
+    response
 }
 
-/// theme - Set terminal theme (frontend applies, backend validates)
-fn handle_theme(term: &mut TerminalState, args: &[&str]) -> String {
+/// pwd - Print working directory
+fn handle_pwd(fs: &FileSystem) -> CommandResponse {
+    CommandResponse::ok(fs.current_path.clone())
+}
+
+/// cd - Change directory
+fn handle_cd(fs: &mut FileSystem, creds: &Credentials, args: &[&str]) -> CommandResponse {
     if args.is_empty() {
-        return format!(
-            "Usage: theme <name>\nAvailable themes: {}",
-            THEMES.join(", ")
-        );
+        fs.current_path = "/home".to_string();
+        return CommandResponse::empty();
     }
 
-    let name = args[0];
-    if !THEMES.contains(&name) {
-        return format!(
-            "theme: unknown theme '{}'. Available themes: {}",
-            name,
-            THEMES.join(", ")
-        );
+    let target = fs.resolve_path(args[0]);
+    let checked = match fs.metadata(&target) {
+        Ok(node) if node.ftype != FileType::Directory => Err(FsError::NotADirectory),
+        Ok(_) => fs.access(&target, creds, PERM_EXEC),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = checked {
+        return fs_error(format!("cd: {}", args[0]), err);
     }
 
-    term.theme = name.to_string();
-    format!("THEME:{}", name)
+    fs.current_path = target;
+    CommandResponse::empty()
 }
 
-/// reboot - Reset terminal state (filesystem, sudo, history, theme)
-fn handle_reboot(fs: &mut FileSystem, sudo: &mut SudoState, term: &mut TerminalState) -> String {
-    *fs = FileSystem::new();
-    *sudo = SudoState::new();
-    *term = TerminalState::new();
-    "REBOOT".to_string()
+/// Sort order for `ls`
+#[derive(Clone, Copy, PartialEq)]
+enum LsSort {
+    Name,
+    Time,
+    Size,
 }
 
-/// help - Display available commands
-fn handle_help() -> String {
-    [
-        "Available commands:",
-        "ls      - List directory contents",
-        "cd      - Change current directory",
-        "pwd     - Show current directory",
-        "cat     - Display file content",
-        "mkdir   - Create a directory",
-        "touch   - Create an empty file",
-        "cp      - Copy file or directory",
-        "mv      - Move or rename file/directory",
-        "date    - Show current date/time",
-        "echo    - Print text",
-        "whoami  - Show current user",
-        "history - Show command history",
-        "theme   - Change terminal theme",
-        "reboot  - Reset terminal state",
-        "downld  - Download a file",
-        "clear   - Clear terminal output",
-    ]
-    .join("\n")
+/// Parsed `ls` flags
+struct LsOptions {
+    long: bool,
+    all: bool,
+    recursive: bool,
+    human: bool,
+    sort: LsSort,
+    reverse: bool,
+    directory: bool,
+    one_per_line: bool,
+    /// Characters per line for the column layout
+    width: usize,
+    now: f64,
 }
 
-/// downld - Download file (returns base64 encoded content)
-fn handle_download(fs: &FileSystem, args: &[&str]) -> String {
-    if args.is_empty() {
-        return "Usage: download <filename>".to_string();
-    }
-
-    let target = fs.resolve_path(args[0]);
+/// A name to list and the node it shows (the link itself for symlinks)
+struct LsEntry<'a> {
+    name: String,
+    path: String,
+    node: &'a FileNode,
+    size: u64,
+}
 
-    if !fs.exists(&target) {
-        return format!("download: {}: No such file", args[0]);
+impl<'a> LsEntry<'a> {
+    fn new(name: String, path: String, node: &'a FileNode) -> Self {
+        let size = node.size();
+        LsEntry {
+            name,
+            path,
+            node,
+            size,
+        }
     }
+}
 
-    if fs.is_dir(&target) {
-        return format!("download: {}: Is a directory", args[0]);
+/// ls - List directory contents
+///
+/// Names are sorted bytewise (or by -t time / -S size, -r reversed) and laid
+/// out in columns across the reported terminal width, one per line when the
+/// output is piped or redirected. Dotfiles are hidden without -a.
+fn handle_ls(
+    fs: &FileSystem,
+    creds: &Credentials,
+    term: &TerminalState,
+    args: &[&str],
+    now: f64,
+) -> CommandResponse {
+    let mut opts = LsOptions {
+        long: false,
+        all: false,
+        recursive: false,
+        human: false,
+        sort: LsSort::Name,
+        reverse: false,
+        directory: false,
+        one_per_line: term.stdout_redirected,
+        width: term.columns,
+        now,
+    };
+    let mut operands = Vec::new();
+    let mut flags_done = false;
+    for arg in args {
+        if flags_done || *arg == "-" || !arg.starts_with('-') {
+            operands.push(*arg);
+            continue;
+        }
+        if *arg == "--" {
+            flags_done = true;
+            continue;
+        }
+        for flag in arg.chars().skip(1) {
+            match flag {
+                'l' => opts.long = true,
+                'a' => opts.all = true,
+                'R' => opts.recursive = true,
+                'h' => opts.human = true,
+                't' => opts.sort = LsSort::Time,
+                'S' => opts.sort = LsSort::Size,
+                'r' => opts.reverse = true,
+                'd' => opts.directory = true,
+                '1' => opts.one_per_line = true,
+                _ => {
+                    return CommandResponse {
+                        status: 2,
+                        ..CommandResponse::error(format!("ls: invalid option -- '{}'", flag))
+                    };
+                }
+            }
+        }
+    }
+    if operands.is_empty() {
+        operands.push(".");
     }
 
-    if let Some(content) = fs.get_file_content(&target) {
-        let filename = args[0].split('/').last().unwrap_or("file");
-        let b64 = if is_binary_file(&target, content) {
-            base64_encode(content)
-        } else {
-            let decrypted = decode(content);
-            base64_encode(decrypted.as_bytes())
+    // Operands that are files (or anything with -d) are listed together first
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    let mut errors = Vec::new();
+    let mut status = 0;
+    for operand in &operands {
+        let path = fs.resolve_path(operand);
+        let node = match stat_node(fs, creds, &path, false) {
+            Ok(node) => node,
+            Err(err) => {
+                errors.push(format!("ls: cannot access '{}': {}", operand, err));
+                status = 2;
+                continue;
+            }
         };
-        format!("DOWNLOAD:{}:{}", filename, b64)
-    } else {
-        "Error reading file".to_string()
+        // A symlink operand is followed to its directory, except by -l and -d
+        let target = stat_node(fs, creds, &path, true).unwrap_or(node);
+        let keep_link = opts.long && node.ftype == FileType::Symlink;
+        if !opts.directory && !keep_link && target.ftype == FileType::Directory {
+            dirs.push(LsEntry::new(operand.to_string(), path, target));
+        } else {
+            files.push(LsEntry::new(operand.to_string(), path, node));
+        }
+    }
+
+    let names = users::Names::load(fs);
+    let mut blocks = Vec::new();
+    if !files.is_empty() {
+        sort_ls_entries(&mut files, &opts);
+        blocks.push(ls_format(fs, &names, &files, &opts).join("\n"));
+    }
+    sort_ls_entries(&mut dirs, &opts);
+    let headers = operands.len() > 1 || opts.recursive;
+    for dir in &dirs {
+        let dir_status =
+            ls_directory(fs, creds, &names, &opts, dir, headers, &mut blocks, &mut errors);
+        status = status.max(dir_status);
+    }
+
+    CommandResponse {
+        stdout: blocks.join("\n\n"),
+        stderr: errors.join("\n"),
+        status,
+        ..CommandResponse::default()
+    }
+}
+
+/// Lists one directory as a block (with a `name:` header if asked), then its
+/// subdirectories when -R is given
+///
+/// # Returns
+/// 2 if this directory could not be read, 1 if only one below it could not
+/// (a minor problem to GNU ls), else 0
+#[allow(clippy::too_many_arguments)]
+fn ls_directory(
+    fs: &FileSystem,
+    creds: &Credentials,
+    names: &users::Names,
+    opts: &LsOptions,
+    dir: &LsEntry,
+    header: bool,
+    blocks: &mut Vec<String>,
+    errors: &mut Vec<String>,
+) -> i32 {
+    let mut lines = Vec::new();
+    if header {
+        lines.push(format!("{}:", dir.name));
+    }
+    let listing = fs
+        .access(&dir.path, creds, PERM_READ)
+        .and_then(|()| fs.list_directory(&dir.path));
+    let listing = match listing {
+        Ok(listing) => listing,
+        Err(err) => {
+            errors.push(format!("ls: cannot open directory '{}': {}", dir.name, err));
+            if header {
+                blocks.push(lines.join("\n"));
+            }
+            return 2;
+        }
+    };
+
+    let mut entries = Vec::new();
+    if opts.all {
+        let parent = fs.resolve_path(&join_path(&dir.path, ".."));
+        for (name, path) in [(".", dir.path.clone()), ("..", parent)] {
+            if let Ok(node) = fs.metadata(&path) {
+                entries.push(LsEntry::new(name.to_string(), path, node));
+            }
+        }
+    }
+    for name in listing {
+        let name = name.trim_end_matches('/');
+        if !opts.all && name.starts_with('.') {
+            continue;
+        }
+        let path = join_path(&dir.path, name);
+        if let Ok(node) = fs.symlink_metadata(&path) {
+            entries.push(LsEntry::new(name.to_string(), path, node));
+        }
+    }
+    sort_ls_entries(&mut entries, opts);
+
+    if opts.long {
+        let blocks: u64 = entries.iter().map(|entry| entry.node.allocated() / 1024).sum();
+        let total = if opts.human {
+            human_size(blocks * 1024)
+        } else {
+            blocks.to_string()
+        };
+        lines.push(format!("total {}", total));
+    }
+    lines.extend(ls_format(fs, names, &entries, opts));
+    blocks.push(lines.join("\n"));
+
+    let mut status = 0;
+    if opts.recursive {
+        let subdirs = entries.iter().filter(|entry| {
+            entry.node.ftype == FileType::Directory && entry.name != "." && entry.name != ".."
+        });
+        for subdir in subdirs {
+            let name = join_path(&dir.name, &subdir.name);
+            let child = LsEntry::new(name, subdir.path.clone(), subdir.node);
+            let child_status =
+                ls_directory(fs, creds, names, opts, &child, true, blocks, errors);
+            status = status.max(child_status.min(1));
+        }
+    }
+    status
+}
+
+/// Orders entries by name, or newest/largest first for -t/-S, reversed by -r
+fn sort_ls_entries(entries: &mut [LsEntry], opts: &LsOptions) {
+    entries.sort_by(|a, b| {
+        let order = match opts.sort {
+            LsSort::Name => Ordering::Equal,
+            LsSort::Time => b.node.timestamp.total_cmp(&a.node.timestamp),
+            LsSort::Size => b.size.cmp(&a.size),
+        };
+        order.then_with(|| a.name.cmp(&b.name))
+    });
+    if opts.reverse {
+        entries.reverse();
+    }
+}
+
+/// Renders entries as `-l` rows, one name per line, or columns
+fn ls_format(
+    fs: &FileSystem,
+    names: &users::Names,
+    entries: &[LsEntry],
+    opts: &LsOptions,
+) -> Vec<String> {
+    if !opts.long {
+        // Directories keep their trailing slash, as there are no colours to tell them apart
+        let shown: Vec<String> = entries
+            .iter()
+            .map(|entry| match entry.node.ftype {
+                FileType::Directory if !entry.name.ends_with('/') => format!("{}/", entry.name),
+                _ => entry.name.clone(),
+            })
+            .collect();
+        return if opts.one_per_line {
+            shown
+        } else {
+            layout_columns(&shown, opts.width)
+        };
+    }
+
+    let rows: Vec<[String; 5]> = entries
+        .iter()
+        .map(|entry| {
+            let size = if opts.human {
+                human_size(entry.size)
+            } else {
+                entry.size.to_string()
+            };
+            [
+                entry.node.mode_string(),
+                entry.node.nlink.to_string(),
+                names.user(entry.node.uid),
+                names.group(entry.node.gid),
+                size,
+            ]
+        })
+        .collect();
+    let width = |column: usize| rows.iter().map(|row| row[column].len()).max().unwrap_or(0);
+    let widths = [width(1), width(2), width(3), width(4)];
+
+    entries
+        .iter()
+        .zip(&rows)
+        .map(|(entry, [mode, links, owner, group, size])| {
+            let mut line = format!(
+                "{} {:>links_width$} {:<owner_width$} {:<group_width$} {:>size_width$} {} {}",
+                mode,
+                links,
+                owner,
+                group,
+                size,
+                clock::format_listing_time(entry.node.timestamp, opts.now),
+                entry.name,
+                links_width = widths[0],
+                owner_width = widths[1],
+                group_width = widths[2],
+                size_width = widths[3],
+            );
+            if let Ok(target) = fs.read_link(&entry.path) {
+                line.push_str(&format!(" -> {}", target));
+            }
+            line
+        })
+        .collect()
+}
+
+/// Arranges names down then across in as many columns as fit in `width`
+///
+/// Columns are two spaces apart and as wide as their longest name, like GNU ls.
+fn layout_columns(names: &[String], width: usize) -> Vec<String> {
+    let lengths: Vec<usize> = names.iter().map(|name| name.chars().count()).collect();
+    // The narrowest possible column is one character plus the gap
+    let most = names.len().min(width / 3).max(1);
+
+    for columns in (1..=most).rev() {
+        let rows = names.len().div_ceil(columns);
+        let columns = names.len().div_ceil(rows.max(1));
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                let end = ((column + 1) * rows).min(names.len());
+                lengths[column * rows..end].iter().copied().max().unwrap_or(0)
+            })
+            .collect();
+        let total = widths.iter().sum::<usize>() + 2 * columns.saturating_sub(1);
+        if total > width && columns > 1 {
+            continue;
+        }
+
+        return (0..rows)
+            .map(|row| {
+                let mut line = String::new();
+                for (column, column_width) in widths.iter().enumerate() {
+                    let Some(name) = names.get(column * rows + row) else {
+                        break;
+                    };
+                    line.push_str(name);
+                    let pad = column_width - lengths[column * rows + row] + 2;
+                    line.push_str(&" ".repeat(pad));
+                }
+                line.trim_end().to_string()
+            })
+            .collect();
+    }
+    Vec::new()
+}
+
+/// Formats a byte count like `ls -h`: `512`, `4.0K`, `1.5M`, `23G`
+///
+/// Values are rounded up; one decimal is kept below 10.
+fn human_size(bytes: u64) -> String {
+    const UNITS: [char; 6] = ['K', 'M', 'G', 'T', 'P', 'E'];
+
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    let tenths = (value * 10.0).ceil() / 10.0;
+    if tenths < 10.0 {
+        format!("{:.1}{}", tenths, UNITS[unit])
+    } else {
+        format!("{:.0}{}", value.ceil(), UNITS[unit])
+    }
+}
+
+/// Looks up a node the way stat(2) does: only the directories leading to it
+/// need search permission, not the node itself
+fn stat_node<'a>(
+    fs: &'a FileSystem,
+    creds: &Credentials,
+    path: &str,
+    follow: bool,
+) -> Result<&'a FileNode, FsError> {
+    if let Some(parent) = FileSystem::parent_path(path) {
+        fs.access(&parent, creds, PERM_EXEC)?;
+    }
+    if follow {
+        fs.metadata(path)
+    } else {
+        fs.symlink_metadata(path)
+    }
+}
+
+/// stat - Show a node's size, blocks, inode, links, mode, owner and times
+///
+/// Symlinks are described themselves unless -L is given. Access times are
+/// only set on creation and by `touch`; change times follow writes, chmod
+/// and chown.
+fn handle_stat(fs: &FileSystem, creds: &Credentials, args: &[&str]) -> CommandResponse {
+    let mut follow = false;
+    let mut operands = Vec::new();
+    for arg in args {
+        match *arg {
+            "-L" | "--dereference" => follow = true,
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return CommandResponse::error(format!("stat: invalid option -- '{}'", &arg[1..]));
+            }
+            _ => operands.push(*arg),
+        }
+    }
+    if operands.is_empty() {
+        return CommandResponse::error("Usage: stat [-L] <file>...");
+    }
+
+    let names = users::Names::load(fs);
+    let mut out = Vec::new();
+    let mut errors = Vec::new();
+    for operand in operands {
+        let path = fs.resolve_path(operand);
+        let node = match stat_node(fs, creds, &path, follow) {
+            Ok(node) => node,
+            Err(err) => {
+                errors.push(format!("stat: cannot statx '{}': {}", operand, err));
+                continue;
+            }
+        };
+
+        let size = node.size();
+        let (name, kind) = match node.ftype {
+            FileType::Symlink => {
+                let target = fs.read_link(&path).unwrap_or_default();
+                (format!("{} -> {}", operand, target), "symbolic link")
+            }
+            FileType::Directory => (operand.to_string(), "directory"),
+            FileType::File if size == 0 => (operand.to_string(), "regular empty file"),
+            FileType::File => (operand.to_string(), "regular file"),
+        };
+        let mode = format!("{:04o}/{}", node.mode, node.mode_string());
+        out.push(format!("  File: {}", name));
+        out.push(format!(
+            "  Size: {:<10}\tBlocks: {:<10} IO Block: {:<6} {}",
+            size,
+            node.allocated() / 512,
+            BLOCK_SIZE,
+            kind
+        ));
+        out.push(format!("Device: 0,1\tInode: {:<11} Links: {}", node.ino, node.nlink));
+        out.push(format!(
+            "Access: ({})  Uid: ({:>5}/{:>8})   Gid: ({:>5}/{:>8})",
+            mode,
+            node.uid,
+            names.user(node.uid),
+            node.gid,
+            names.group(node.gid)
+        ));
+        out.push(format!("Access: {}", clock::format_timestamp(node.atime)));
+        out.push(format!("Modify: {}", clock::format_timestamp(node.timestamp)));
+        out.push(format!("Change: {}", clock::format_timestamp(node.ctime)));
+        out.push(" Birth: -".to_string());
+    }
+    filter_response(out.join("\n"), errors)
+}
+
+/// Parsed `du` flags
+struct DuOptions {
+    human: bool,
+    /// Deepest level printed; -s is a depth of 0
+    max_depth: Option<usize>,
+}
+
+/// du - Show the disk space used by files and directory trees
+///
+/// Every directory is listed after its contents with its total in KiB (or
+/// -h units); symlinks are not followed and hard-linked files count once.
+fn handle_du(fs: &FileSystem, creds: &Credentials, args: &[&str]) -> CommandResponse {
+    let mut opts = DuOptions {
+        human: false,
+        max_depth: None,
+    };
+    let mut operands = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let depth = if let Some(value) = arg.strip_prefix("--max-depth=") {
+            value
+        } else if *arg == "--max-depth" || *arg == "-d" {
+            match rest.next() {
+                Some(value) => value,
+                None => {
+                    let message = format!("du: option '{}' requires an argument", arg);
+                    return CommandResponse::error(message);
+                }
+            }
+        } else if arg.starts_with('-') && arg.len() > 1 {
+            for flag in arg.chars().skip(1) {
+                match flag {
+                    'h' => opts.human = true,
+                    's' => opts.max_depth = Some(0),
+                    _ => return CommandResponse::error(format!("du: invalid option -- '{}'", flag)),
+                }
+            }
+            continue;
+        } else {
+            operands.push(*arg);
+            continue;
+        };
+        match depth.parse() {
+            Ok(depth) => opts.max_depth = Some(depth),
+            Err(_) => {
+                return CommandResponse::error(format!("du: invalid maximum depth '{}'", depth));
+            }
+        }
+    }
+    if operands.is_empty() {
+        operands.push(".");
+    }
+
+    let mut out = Vec::new();
+    let mut errors = Vec::new();
+    let mut seen = HashSet::new();
+    for operand in operands {
+        let path = fs.resolve_path(operand);
+        match stat_node(fs, creds, &path, false) {
+            Ok(node) => {
+                let (out, errors) = (&mut out, &mut errors);
+                du_walk(fs, creds, &opts, operand, &path, node, 0, &mut seen, out, errors);
+            }
+            Err(err) => errors.push(format!("du: cannot access '{}': {}", operand, err)),
+        }
+    }
+    filter_response(out.join("\n"), errors)
+}
+
+/// Adds up a node and everything below it, printing directories deepest first
+///
+/// # Returns
+/// Bytes allocated by the node and its descendants not already `seen`
+#[allow(clippy::too_many_arguments)]
+fn du_walk(
+    fs: &FileSystem,
+    creds: &Credentials,
+    opts: &DuOptions,
+    display: &str,
+    path: &str,
+    node: &FileNode,
+    depth: usize,
+    seen: &mut HashSet<u64>,
+    out: &mut Vec<String>,
+    errors: &mut Vec<String>,
+) -> u64 {
+    if !seen.insert(node.ino) {
+        return 0;
+    }
+    let mut total = node.allocated();
+
+    if node.ftype == FileType::Directory {
+        let names = fs
+            .access(path, creds, PERM_READ | PERM_EXEC)
+            .and_then(|()| fs.list_directory(path));
+        match names {
+            Ok(names) => {
+                for name in names {
+                    let name = name.trim_end_matches('/');
+                    let child_path = join_path(path, name);
+                    if let Ok(child) = fs.symlink_metadata(&child_path) {
+                        let child_display = join_path(display, name);
+                        total += du_walk(
+                            fs,
+                            creds,
+                            opts,
+                            &child_display,
+                            &child_path,
+                            child,
+                            depth + 1,
+                            seen,
+                            out,
+                            errors,
+                        );
+                    }
+                }
+            }
+            Err(err) => errors.push(format!("du: cannot read directory '{}': {}", display, err)),
+        }
+    }
+
+    // Files inside a tree are counted but only listed when named on the command line
+    let listed = node.ftype == FileType::Directory || depth == 0;
+    if listed && opts.max_depth.is_none_or(|max| depth <= max) {
+        let size = if opts.human {
+            human_size(total)
+        } else {
+            total.div_ceil(1024).to_string()
+        };
+        out.push(format!("{}\t{}", size, display));
+    }
+    total
+}
+
+/// df - Report the size, usage and free space of the virtual disk
+///
+/// The capacity comes from the terminal's `disk_capacity` setting; writes
+/// that would exceed it fail with "No space left on device".
+fn handle_df(fs: &FileSystem, creds: &Credentials, args: &[&str]) -> CommandResponse {
+    let mut human = false;
+    let mut operands = Vec::new();
+    for arg in args {
+        if arg.starts_with('-') && arg.len() > 1 {
+            for flag in arg.chars().skip(1) {
+                match flag {
+                    'h' => human = true,
+                    'k' => human = false,
+                    _ => return CommandResponse::error(format!("df: invalid option -- '{}'", flag)),
+                }
+            }
+        } else {
+            operands.push(*arg);
+        }
+    }
+
+    // Every path is on the same disk, so each operand only needs to exist
+    let mut errors = Vec::new();
+    let mut rows = 0;
+    for operand in &operands {
+        match stat_node(fs, creds, &fs.resolve_path(operand), true) {
+            Ok(_) => rows += 1,
+            Err(err) => errors.push(format!("df: {}: {}", operand, err)),
+        }
+    }
+    if operands.is_empty() {
+        rows = 1;
+    }
+
+    let used = fs.usage();
+    let available = fs.capacity.saturating_sub(used);
+    let percent = match used + available {
+        0 => 0,
+        total => (used * 100).div_ceil(total),
+    };
+    let amount = |bytes: u64| {
+        if human {
+            human_size(bytes)
+        } else {
+            bytes.div_ceil(1024).to_string()
+        }
+    };
+
+    let mut out = Vec::new();
+    if rows > 0 {
+        let (size_label, avail_label) = if human {
+            ("Size", "Avail")
+        } else {
+            ("1K-blocks", "Available")
+        };
+        out.push(format!(
+            "Filesystem {:>9} {:>9} {:>9} Use% Mounted on",
+            size_label, "Used", avail_label
+        ));
+        let row = format!(
+            "securefs   {:>9} {:>9} {:>9} {:>3}% /",
+            amount(fs.capacity),
+            amount(used),
+            amount(available),
+            percent
+        );
+        out.extend(std::iter::repeat_n(row, rows));
+    }
+    filter_response(out.join("\n"), errors)
+}
+
+/// Entries drawn by `tree`
+#[derive(Default)]
+struct TreeCounts {
+    dirs: usize,
+    files: usize,
+}
+
+/// tree - Draw directory trees with box-drawing branches
+///
+/// Hidden entries appear with -a and -L N stops N levels down; symlinks are
+/// shown with their targets but never followed.
+fn handle_tree(fs: &FileSystem, creds: &Credentials, args: &[&str]) -> CommandResponse {
+    let mut all = false;
+    let mut max_depth = None;
+    let mut operands = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        if let Some(level) = arg.strip_prefix("-L") {
+            let level = match option_value("tree", 'L', level, &mut rest) {
+                Ok(level) => level,
+                Err(response) => return response,
+            };
+            match level.parse::<usize>() {
+                Ok(level) if level > 0 => max_depth = Some(level),
+                _ => return CommandResponse::error("tree: Invalid level, must be greater than 0."),
+            }
+        } else if *arg == "-a" {
+            all = true;
+        } else if arg.starts_with('-') && arg.len() > 1 {
+            return CommandResponse::error(format!("tree: invalid option -- '{}'", &arg[1..]));
+        } else {
+            operands.push(*arg);
+        }
+    }
+    if operands.is_empty() {
+        operands.push(".");
+    }
+
+    let mut lines = Vec::new();
+    let mut counts = TreeCounts::default();
+    let mut status = 0;
+    for operand in operands {
+        let path = fs.resolve_path(operand);
+        lines.push(operand.to_string());
+        if fs.is_dir(&path) {
+            tree_walk(fs, creds, all, max_depth, &path, "", 1, &mut lines, &mut counts);
+        } else {
+            if let Some(line) = lines.last_mut() {
+                line.push_str("  [error opening dir]");
+            }
+            status = 2;
+        }
+    }
+
+    lines.push(String::new());
+    lines.push(format!(
+        "{} director{}, {} file{}",
+        counts.dirs,
+        if counts.dirs == 1 { "y" } else { "ies" },
+        counts.files,
+        if counts.files == 1 { "" } else { "s" }
+    ));
+    CommandResponse {
+        status,
+        ..CommandResponse::ok(lines.join("\n"))
+    }
+}
+
+/// Draws the entries of one directory below `prefix`, recursing into subdirectories
+#[allow(clippy::too_many_arguments)]
+fn tree_walk(
+    fs: &FileSystem,
+    creds: &Credentials,
+    all: bool,
+    max_depth: Option<usize>,
+    path: &str,
+    prefix: &str,
+    depth: usize,
+    lines: &mut Vec<String>,
+    counts: &mut TreeCounts,
+) {
+    if max_depth.is_some_and(|max| depth > max) {
+        return;
+    }
+    let names = fs
+        .access(path, creds, PERM_READ | PERM_EXEC)
+        .and_then(|()| fs.list_directory(path));
+    let Ok(names) = names else {
+        if let Some(line) = lines.last_mut() {
+            line.push_str("  [error opening dir]");
+        }
+        return;
+    };
+
+    let names: Vec<&str> = names
+        .iter()
+        .map(|name| name.trim_end_matches('/'))
+        .filter(|name| all || !name.starts_with('.'))
+        .collect();
+    for (idx, name) in names.iter().enumerate() {
+        let last = idx + 1 == names.len();
+        let child = join_path(path, name);
+        let mut line = format!("{}{}{}", prefix, if last { "└── " } else { "├── " }, name);
+        if let Ok(target) = fs.read_link(&child) {
+            line.push_str(&format!(" -> {}", target));
+        }
+        lines.push(line);
+
+        if fs.symlink_metadata(&child).is_ok_and(|node| node.ftype == FileType::Directory) {
+            counts.dirs += 1;
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            tree_walk(fs, creds, all, max_depth, &child, &prefix, depth + 1, lines, counts);
+        } else {
+            counts.files += 1;
+        }
+    }
+}
+
+/// cat - Display file contents (or stdin when piped)
+fn handle_cat(
+    fs: &FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> CommandResponse {
+    if args.is_empty() {
+        return match stdin {
+            Some(input) => CommandResponse::ok(input),
+            None => CommandResponse::error("Usage: cat <filename>"),
+        };
+    }
+
+    let target = fs.resolve_path(args[0]);
+    let content = match fs
+        .access(&target, creds, PERM_READ)
+        .and_then(|()| fs.read_file(&target))
+    {
+        Ok(content) => content,
+        Err(err) => return fs_error(format!("cat: {}", args[0]), err),
+    };
+
+    if is_binary_file(&target, &content) {
+        return CommandResponse::error(format!(
+            "cat: {}: binary file (use downld to download)",
+            args[0]
+        ));
+    }
+    if std::str::from_utf8(&content).is_err() {
+        return CommandResponse::ok("??");
+    }
+    CommandResponse::ok(stream_text(&content))
+}
+
+/// Parsed `grep` flags
+#[derive(Default)]
+struct GrepOptions {
+    ignore_case: bool,
+    invert: bool,
+    line_numbers: bool,
+    recursive: bool,
+    files_only: bool,
+    count: bool,
+    extended: bool,
+    text: bool,
+    before: usize,
+    after: usize,
+}
+
+/// Reads a context length given as `-A N` or `-AN`
+fn context_length(
+    attached: &str,
+    rest: &mut std::slice::Iter<'_, &str>,
+) -> Result<usize, CommandResponse> {
+    let value = if attached.is_empty() {
+        rest.next().copied().unwrap_or("")
+    } else {
+        attached
+    };
+    value.parse().map_err(|_| CommandResponse {
+        status: 2,
+        ..CommandResponse::error(format!("grep: {}: invalid context length argument", value))
+    })
+}
+
+/// grep - Print lines matching a regular expression
+///
+/// Searches the named files (directories too with -r), or stdin when piped.
+/// Exits 0 when a line was selected, 1 when none was and 2 on errors.
+fn handle_grep(
+    fs: &FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> CommandResponse {
+    let usage = || CommandResponse {
+        status: 2,
+        ..CommandResponse::error("Usage: grep [-ivnrlcEa] [-A N] [-B N] [-C N] <pattern> [file...]")
+    };
+
+    let mut opts = GrepOptions::default();
+    let mut pattern = None;
+    let mut operands = Vec::new();
+    let mut rest = args.iter();
+    let mut options_done = false;
+    while let Some(arg) = rest.next() {
+        if options_done || !arg.starts_with('-') || *arg == "-" {
+            operands.push(*arg);
+            continue;
+        }
+        if *arg == "--" {
+            options_done = true;
+            continue;
+        }
+
+        for (idx, flag) in arg.char_indices().skip(1) {
+            match flag {
+                'i' => opts.ignore_case = true,
+                'v' => opts.invert = true,
+                'n' => opts.line_numbers = true,
+                'r' | 'R' => opts.recursive = true,
+                'l' => opts.files_only = true,
+                'c' => opts.count = true,
+                'E' => opts.extended = true,
+                'a' => opts.text = true,
+                'A' | 'B' | 'C' | 'e' => {
+                    let attached = &arg[idx + 1..];
+                    if flag == 'e' {
+                        let value = if attached.is_empty() {
+                            rest.next().copied()
+                        } else {
+                            Some(attached)
+                        };
+                        match value {
+                            Some(value) => pattern = Some(value),
+                            None => return usage(),
+                        }
+                        break;
+                    }
+                    let length = match context_length(attached, &mut rest) {
+                        Ok(length) => length,
+                        Err(response) => return response,
+                    };
+                    if flag != 'B' {
+                        opts.after = length;
+                    }
+                    if flag != 'A' {
+                        opts.before = length;
+                    }
+                    break;
+                }
+                _ => {
+                    return CommandResponse {
+                        status: 2,
+                        ..CommandResponse::error(format!("grep: invalid option -- '{}'", flag))
+                    };
+                }
+            }
+        }
+    }
+
+    let pattern = match pattern {
+        Some(pattern) => pattern,
+        None if !operands.is_empty() => operands.remove(0),
+        None => return usage(),
+    };
+    let syntax = if opts.extended { Syntax::Extended } else { Syntax::Basic };
+    let regex = match Regex::new(pattern, syntax, opts.ignore_case) {
+        Ok(regex) => regex,
+        Err(err) => {
+            return CommandResponse {
+                status: 2,
+                ..CommandResponse::error(format!("grep: {}", err))
+            };
+        }
+    };
+
+    let mut out = Vec::new();
+    let mut errors = Vec::new();
+    let mut selected = false;
+
+    // Plain `grep pattern` reads stdin; `grep -r pattern` searches the current directory
+    if operands.is_empty() && !opts.recursive {
+        let Some(input) = stdin else {
+            return usage();
+        };
+        selected = grep_text(&regex, &opts, input, None, &mut out);
+    } else {
+        let labelled = opts.recursive || operands.len() > 1;
+        let implicit = operands.is_empty();
+        let operands = if implicit { vec!["."] } else { operands };
+        for operand in operands {
+            if operand == "-" {
+                let input = stdin.unwrap_or("");
+                let label = labelled.then_some("(standard input)");
+                selected |= grep_text(&regex, &opts, input, label, &mut out);
+                continue;
+            }
+            for (display, path) in grep_targets(fs, creds, operand, implicit, &opts, &mut errors) {
+                let content = match fs
+                    .access(&path, creds, PERM_READ)
+                    .and_then(|()| fs.read_file(&path))
+                {
+                    Ok(content) => content,
+                    Err(err) => {
+                        errors.push(format!("grep: {}: {}", display, err));
+                        continue;
+                    }
+                };
+                if !opts.text && is_binary_file(&path, &content) {
+                    continue;
+                }
+                let text = String::from_utf8_lossy(&content);
+                let label = labelled.then_some(display.as_str());
+                selected |= grep_text(&regex, &opts, &text, label, &mut out);
+            }
+        }
+    }
+
+    CommandResponse {
+        stdout: out.join("\n"),
+        stderr: errors.join("\n"),
+        status: if !errors.is_empty() {
+            2
+        } else if selected {
+            0
+        } else {
+            1
+        },
+        ..CommandResponse::default()
+    }
+}
+
+/// Expands a `grep` operand into `(display name, path)` pairs of files to search
+///
+/// With -r, directories are walked; symlinks found while walking are skipped
+/// and unreadable directories are reported and pruned. `implicit` marks the
+/// current directory searched by a bare `grep -r`, shown without a `./` prefix.
+fn grep_targets(
+    fs: &FileSystem,
+    creds: &Credentials,
+    operand: &str,
+    implicit: bool,
+    opts: &GrepOptions,
+    errors: &mut Vec<String>,
+) -> Vec<(String, String)> {
+    let path = fs.resolve_path(operand);
+    if let Err(err) = fs.metadata(&path) {
+        errors.push(format!("grep: {}: {}", operand, err));
+        return Vec::new();
+    }
+    if !fs.is_dir(&path) {
+        return vec![(operand.to_string(), path)];
+    }
+    if !opts.recursive {
+        errors.push(format!("grep: {}: {}", operand, FsError::IsADirectory));
+        return Vec::new();
+    }
+
+    let tree = match walk_tree(fs, creds, operand, &path, None) {
+        Ok(tree) => tree,
+        Err(err) => {
+            errors.push(format!("grep: {}: {}", operand, err));
+            return Vec::new();
+        }
+    };
+    for (display, err) in tree.denied {
+        errors.push(format!("grep: {}: {}", display, err));
+    }
+
+    tree.entries
+        .into_iter()
+        .filter(|entry| fs.is_file(&entry.path) && !fs.is_symlink(&entry.path))
+        .map(|entry| {
+            let display = if implicit {
+                entry.display.trim_start_matches("./").to_string()
+            } else {
+                entry.display
+            };
+            (display, entry.path)
+        })
+        .collect()
+}
+
+/// A node reached by `walk_tree`
+struct TreeEntry {
+    /// The operand as written, followed by the path below it
+    display: String,
+    /// Physical path
+    path: String,
+}
+
+/// Nodes found by `walk_tree`, in preorder
+struct Tree {
+    entries: Vec<TreeEntry>,
+    /// Directories that could not be opened, by display name
+    denied: Vec<(String, FsError)>,
+}
+
+/// Walks a tree in preorder for -r/find, pruning directories `creds` cannot list
+///
+/// Pruned directories are still yielded, since their own metadata is visible,
+/// and are also listed in `denied`. Symlinks are not followed.
+///
+/// # Arguments
+/// * `operand` - Starting point as the user wrote it, used for display names
+/// * `path` - The operand resolved with `FileSystem::resolve_path`
+/// * `max_depth` - Deepest level to yield; directories there are not opened
+fn walk_tree(
+    fs: &FileSystem,
+    creds: &Credentials,
+    operand: &str,
+    path: &str,
+    max_depth: Option<usize>,
+) -> Result<Tree, FsError> {
+    let walked = fs.walk(path)?;
+    let root = walked.first().cloned().unwrap_or_default();
+
+    let mut entries = Vec::new();
+    let mut denied = Vec::new();
+    let mut pruned: Vec<String> = Vec::new();
+    for entry in walked {
+        if pruned.iter().any(|dir| entry.starts_with(dir.as_str())) {
+            continue;
+        }
+
+        let suffix = if entry == root {
+            ""
+        } else if root == "/" {
+            entry.as_str()
+        } else {
+            &entry[root.len()..]
+        };
+        let display = if suffix.is_empty() {
+            operand.to_string()
+        } else {
+            format!("{}{}", operand.trim_end_matches('/'), suffix)
+        };
+
+        let depth = suffix.matches('/').count();
+        if max_depth.is_some_and(|max| depth > max) {
+            continue;
+        }
+        let opened = max_depth.is_none_or(|max| depth < max);
+        if opened
+            && fs.is_dir(&entry)
+            && !fs.is_symlink(&entry)
+            && let Err(err) = fs.access(&entry, creds, PERM_READ | PERM_EXEC)
+        {
+            denied.push((display.clone(), err));
+            pruned.push(join_path(&entry, ""));
+        }
+        entries.push(TreeEntry {
+            display,
+            path: entry,
+        });
+    }
+    Ok(Tree { entries, denied })
+}
+
+/// Searches one input, appending its output lines to `out`
+///
+/// # Arguments
+/// * `label` - File name prefixed to each line, when several inputs are searched
+///
+/// # Returns
+/// Whether any line was selected
+fn grep_text(
+    regex: &Regex,
+    opts: &GrepOptions,
+    text: &str,
+    label: Option<&str>,
+    out: &mut Vec<String>,
+) -> bool {
+    let lines: Vec<&str> = text.lines().collect();
+    let selected: Vec<bool> = lines
+        .iter()
+        .map(|line| regex.is_match(line) != opts.invert)
+        .collect();
+    let count = selected.iter().filter(|hit| **hit).count();
+
+    if opts.files_only {
+        if count > 0 {
+            out.push(label.unwrap_or("(standard input)").to_string());
+        }
+        return count > 0;
+    }
+    if opts.count {
+        out.push(match label {
+            Some(label) => format!("{}:{}", label, count),
+            None => count.to_string(),
+        });
+        return count > 0;
+    }
+
+    // Context lines use '-' where selected lines use ':'; '--' separates groups
+    let format_line = |idx: usize, sep: char| {
+        let mut line = String::new();
+        if let Some(label) = label {
+            line.push_str(label);
+            line.push(sep);
+        }
+        if opts.line_numbers {
+            line.push_str(&(idx + 1).to_string());
+            line.push(sep);
+        }
+        line.push_str(lines[idx]);
+        line
+    };
+    let mut last_printed: Option<usize> = None;
+    let mut after_left = 0;
+    for (idx, &hit) in selected.iter().enumerate() {
+        if hit {
+            let first = idx.saturating_sub(opts.before);
+            let first = last_printed.map_or(first, |last| first.max(last + 1));
+            let has_context = opts.before > 0 || opts.after > 0;
+            if has_context && last_printed.is_some_and(|last| first > last + 1) {
+                out.push("--".to_string());
+            }
+            for context in first..idx {
+                out.push(format_line(context, '-'));
+            }
+            out.push(format_line(idx, ':'));
+            last_printed = Some(idx);
+            after_left = opts.after;
+        } else if after_left > 0 {
+            out.push(format_line(idx, '-'));
+            last_printed = Some(idx);
+            after_left -= 1;
+        }
+    }
+    count > 0
+}
+
+/// How `find -perm` compares mode bits
+#[derive(Clone, Copy)]
+enum PermMatch {
+    /// `-perm MODE`: exactly these bits
+    Exact,
+    /// `-perm -MODE`: at least these bits
+    All,
+    /// `-perm /MODE`: any of these bits
+    Any,
+}
+
+/// A parsed `find` expression
+enum FindExpr {
+    Name(Regex),
+    Type(FileType),
+    /// Size in units of `unit` bytes (rounded up) compared with a count
+    Size(Ordering, u64, u64),
+    Newer(f64),
+    /// Whole days since modification compared with a count
+    Mtime(Ordering, u64),
+    Perm(PermMatch, u16),
+    User(u32),
+    Exec(Vec<String>),
+    Print,
+    True,
+    Not(Box<FindExpr>),
+    And(Box<FindExpr>, Box<FindExpr>),
+    Or(Box<FindExpr>, Box<FindExpr>),
+}
+
+impl FindExpr {
+    /// Whether the expression has an action, which turns off the implicit -print
+    fn has_action(&self) -> bool {
+        match self {
+            FindExpr::Exec(_) | FindExpr::Print => true,
+            FindExpr::Not(inner) => inner.has_action(),
+            FindExpr::And(left, right) | FindExpr::Or(left, right) => {
+                left.has_action() || right.has_action()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Recursive-descent parser for `find` expressions
+///
+/// Precedence from loosest to tightest: `-o`, `-a` (or juxtaposition),
+/// `!`/`-not`, then parenthesized groups and primaries.
+struct FindParser<'a> {
+    fs: &'a FileSystem,
+    tokens: &'a [&'a str],
+    pos: usize,
+    max_depth: Option<usize>,
+}
+
+impl<'a> FindParser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn argument(&mut self, primary: &str) -> Result<&'a str, String> {
+        let value = self
+            .tokens
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| format!("find: missing argument to `{}'", primary))?;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    fn parse_or(&mut self) -> Result<FindExpr, String> {
+        let mut expr = self.parse_and()?;
+        while matches!(self.peek(), Some("-o" | "-or")) {
+            self.pos += 1;
+            expr = FindExpr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<FindExpr, String> {
+        let mut expr = self.parse_not()?;
+        loop {
+            match self.peek() {
+                Some("-a" | "-and") => self.pos += 1,
+                None | Some(")" | "-o" | "-or") => return Ok(expr),
+                Some(_) => {}
+            }
+            expr = FindExpr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+    }
+
+    fn parse_not(&mut self) -> Result<FindExpr, String> {
+        if matches!(self.peek(), Some("!" | "-not")) {
+            self.pos += 1;
+            return Ok(FindExpr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<FindExpr, String> {
+        let Some(token) = self.peek() else {
+            return Err("find: expected an expression".to_string());
+        };
+        self.pos += 1;
+
+        match token {
+            ")" => Err("find: invalid expression; you have too many ')'".to_string()),
+            "(" => {
+                let expr = self.parse_or()?;
+                if self.peek() != Some(")") {
+                    return Err("find: invalid expression; I was expecting to find a ')' \
+                                somewhere but did not see one."
+                        .to_string());
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            "-name" | "-iname" => {
+                let pattern = self.argument(token)?;
+                Regex::glob(pattern, token == "-iname")
+                    .map(FindExpr::Name)
+                    .map_err(|err| format!("find: {}: {}", pattern, err))
+            }
+            "-type" => match self.argument(token)? {
+                "f" => Ok(FindExpr::Type(FileType::File)),
+                "d" => Ok(FindExpr::Type(FileType::Directory)),
+                "l" => Ok(FindExpr::Type(FileType::Symlink)),
+                other => Err(format!("find: Unknown argument to -type: {}", other)),
+            },
+            "-size" => {
+                let spec = self.argument(token)?;
+                let (ordering, rest) = find_comparison(spec);
+                let (digits, unit) = match rest.char_indices().last() {
+                    Some((idx, suffix)) if suffix.is_ascii_alphabetic() => (&rest[..idx], suffix),
+                    _ => (rest, 'b'),
+                };
+                let unit = match unit {
+                    'c' => 1,
+                    'w' => 2,
+                    'b' => 512,
+                    'k' => 1 << 10,
+                    'M' => 1 << 20,
+                    'G' => 1 << 30,
+                    _ => return Err(format!("find: invalid -size type `{}'", unit)),
+                };
+                let count = digits
+                    .parse()
+                    .map_err(|_| format!("find: invalid argument `{}' to `-size'", spec))?;
+                Ok(FindExpr::Size(ordering, count, unit))
+            }
+            "-newer" => {
+                let reference = self.argument(token)?;
+                self.fs
+                    .metadata(&self.fs.resolve_path(reference))
+                    .map(|node| FindExpr::Newer(node.timestamp))
+                    .map_err(|err| format!("find: '{}': {}", reference, err))
+            }
+            "-mtime" => {
+                let spec = self.argument(token)?;
+                let (ordering, digits) = find_comparison(spec);
+                digits
+                    .parse()
+                    .map(|days| FindExpr::Mtime(ordering, days))
+                    .map_err(|_| format!("find: invalid argument `{}' to `-mtime'", spec))
+            }
+            "-perm" => {
+                let spec = self.argument(token)?;
+                let (kind, mode) = if let Some(mode) = spec.strip_prefix('-') {
+                    (PermMatch::All, mode)
+                } else if let Some(mode) = spec.strip_prefix('/') {
+                    (PermMatch::Any, mode)
+                } else {
+                    (PermMatch::Exact, spec)
+                };
+                parse_mode(mode, 0, false)
+                    .map(|mode| FindExpr::Perm(kind, mode))
+                    .ok_or_else(|| format!("find: invalid mode `{}'", spec))
+            }
+            "-user" => {
+                let name = self.argument(token)?;
+                users::resolve_user(self.fs, name)
+                    .map(FindExpr::User)
+                    .ok_or_else(|| format!("find: '{}' is not the name of a known user", name))
+            }
+            "-maxdepth" => {
+                let depth = self.argument(token)?;
+                let depth = depth.parse().map_err(|_| {
+                    format!("find: Expected a positive decimal integer argument to -maxdepth, \
+                             but got `{}'", depth)
+                })?;
+                self.max_depth = Some(depth);
+                Ok(FindExpr::True)
+            }
+            "-exec" => {
+                let start = self.pos;
+                let end = self.tokens[start..]
+                    .iter()
+                    .position(|token| *token == ";")
+                    .map(|offset| start + offset)
+                    .filter(|end| *end > start)
+                    .ok_or_else(|| "find: missing argument to `-exec'".to_string())?;
+                self.pos = end + 1;
+                let command = self.tokens[start..end].iter().map(|arg| arg.to_string());
+                Ok(FindExpr::Exec(command.collect()))
+            }
+            "-print" => Ok(FindExpr::Print),
+            "-true" => Ok(FindExpr::True),
+            "-false" => Ok(FindExpr::Not(Box::new(FindExpr::True))),
+            _ if token.starts_with('-') => Err(format!("find: unknown predicate `{}'", token)),
+            _ => Err(format!("find: paths must precede expression: `{}'", token)),
+        }
+    }
+}
+
+/// Splits the `+N`/`-N`/`N` prefix of a numeric `find` argument
+fn find_comparison(spec: &str) -> (Ordering, &str) {
+    if let Some(rest) = spec.strip_prefix('+') {
+        (Ordering::Greater, rest)
+    } else if let Some(rest) = spec.strip_prefix('-') {
+        (Ordering::Less, rest)
+    } else {
+        (Ordering::Equal, spec)
+    }
+}
+
+/// State for evaluating a `find` expression; -exec runs commands, so it needs the whole shell
+struct FindRun<'a> {
+    fs: &'a mut FileSystem,
+    sudo: &'a mut SudoState,
+    term: &'a mut TerminalState,
+    now: f64,
+    response: CommandResponse,
+}
+
+impl FindRun<'_> {
+    /// Evaluates `expr` for one node, short-circuiting -a and -o like find does
+    fn eval(&mut self, expr: &FindExpr, entry: &TreeEntry) -> bool {
+        match expr {
+            FindExpr::Exec(command) => {
+                let argv: Vec<String> = command
+                    .iter()
+                    .map(|arg| arg.replace("{}", &entry.display))
+                    .collect();
+                let args: Vec<&str> = argv[1..].iter().map(String::as_str).collect();
+                let result =
+                    dispatch(self.fs, self.sudo, self.term, &argv[0], &args, None, self.now);
+                let succeeded = result.status == 0;
+                self.response.append(result);
+                succeeded
+            }
+            FindExpr::Print => {
+                self.response.append(CommandResponse::ok(entry.display.as_str()));
+                true
+            }
+            FindExpr::True => true,
+            FindExpr::Not(inner) => !self.eval(inner, entry),
+            FindExpr::And(left, right) => self.eval(left, entry) && self.eval(right, entry),
+            FindExpr::Or(left, right) => self.eval(left, entry) || self.eval(right, entry),
+            test => self.test(test, entry),
+        }
+    }
+
+    /// Evaluates a test against the node's current metadata (symlinks not followed)
+    fn test(&self, test: &FindExpr, entry: &TreeEntry) -> bool {
+        let Ok(node) = self.fs.symlink_metadata(&entry.path) else {
+            // Removed by an earlier -exec
+            return false;
+        };
+
+        match test {
+            FindExpr::Name(glob) => glob.is_match(basename(&entry.display)),
+            FindExpr::Type(ftype) => node.ftype == *ftype,
+            FindExpr::Size(ordering, count, unit) => {
+                node.size().div_ceil(*unit).cmp(count) == *ordering
+            }
+            FindExpr::Newer(time) => node.timestamp > *time,
+            FindExpr::Mtime(ordering, days) => {
+                let age = ((self.now - node.timestamp) / 86_400_000.0).floor().max(0.0) as u64;
+                age.cmp(days) == *ordering
+            }
+            FindExpr::Perm(kind, mode) => {
+                let bits = node.mode & 0o7777;
+                match kind {
+                    PermMatch::Exact => bits == *mode,
+                    PermMatch::All => bits & mode == *mode,
+                    PermMatch::Any => *mode == 0 || bits & mode != 0,
+                }
+            }
+            FindExpr::User(uid) => node.uid == *uid,
+            _ => true,
+        }
+    }
+}
+
+/// find - Walk directory trees, printing or acting on nodes that match an expression
+///
+/// Paths come first (default `.`), then the expression; without -print or
+/// -exec, matching paths are printed. Symlinks are not followed.
+fn handle_find(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    args: &[&str],
+    now: f64,
+) -> CommandResponse {
+    let paths_end = args
+        .iter()
+        .position(|arg| {
+            (arg.starts_with('-') && arg.len() > 1) || matches!(*arg, "(" | ")" | "!")
+        })
+        .unwrap_or(args.len());
+    let (paths, tokens) = args.split_at(paths_end);
+    let paths = if paths.is_empty() { &["."][..] } else { paths };
+
+    let mut parser = FindParser {
+        fs,
+        tokens,
+        pos: 0,
+        max_depth: None,
+    };
+    let parsed = if tokens.is_empty() {
+        Ok(FindExpr::True)
+    } else {
+        parser.parse_or().and_then(|expr| match parser.peek() {
+            None => Ok(expr),
+            Some(")") => Err("find: invalid expression; you have too many ')'".to_string()),
+            Some(token) => Err(format!("find: paths must precede expression: `{}'", token)),
+        })
+    };
+    let max_depth = parser.max_depth;
+    let mut expr = match parsed {
+        Ok(expr) => expr,
+        Err(message) => return CommandResponse::error(message),
+    };
+    if !expr.has_action() {
+        expr = FindExpr::And(Box::new(expr), Box::new(FindExpr::Print));
+    }
+
+    let creds = credentials(sudo, term);
+    let mut errors = Vec::new();
+    let mut entries = Vec::new();
+    for operand in paths {
+        let path = fs.resolve_path(operand);
+        let walked = match fs.symlink_metadata(&path) {
+            // A symlink operand is examined itself, not followed
+            Ok(node) if node.ftype == FileType::Symlink => Ok(Tree {
+                entries: vec![TreeEntry {
+                    display: operand.to_string(),
+                    path,
+                }],
+                denied: Vec::new(),
+            }),
+            Ok(_) => walk_tree(fs, &creds, operand, &path, max_depth),
+            Err(err) => Err(err),
+        };
+        match walked {
+            Ok(tree) => {
+                entries.extend(tree.entries);
+                for (display, err) in tree.denied {
+                    errors.push(format!("find: '{}': {}", display, err));
+                }
+            }
+            Err(err) => errors.push(format!("find: '{}': {}", operand, err)),
+        }
+    }
+
+    // The tree is walked up front, so -exec commands that change it see a stable list
+    let mut run = FindRun {
+        fs,
+        sudo,
+        term,
+        now,
+        response: CommandResponse::empty(),
+    };
+    for entry in &entries {
+        run.eval(&expr, entry);
+    }
+
+    // -exec statuses only decide matches; find itself fails only on errors
+    let mut response = run.response;
+    let status = if errors.is_empty() { 0 } else { 1 };
+    for error in errors {
+        response.append(CommandResponse::error(error));
+    }
+    response.status = status;
+    response
+}
+
+/// Reads the inputs of a text filter: each named file (`-` is stdin), or stdin if none are named
+///
+/// Files that cannot be read, and binary files, are reported in `errors`
+/// as `cmd: name: message` and skipped.
+///
+/// # Returns
+/// `(name, text)` pairs in argument order; stdin is named `-`
+fn filter_inputs(
+    fs: &FileSystem,
+    creds: &Credentials,
+    cmd: &str,
+    files: &[&str],
+    stdin: Option<&str>,
+    errors: &mut Vec<String>,
+) -> Vec<(String, String)> {
+    if files.is_empty() {
+        return vec![("-".to_string(), stdin.unwrap_or("").to_string())];
+    }
+
+    let mut inputs = Vec::new();
+    for file in files {
+        if *file == "-" {
+            inputs.push(("-".to_string(), stdin.unwrap_or("").to_string()));
+            continue;
+        }
+        let path = fs.resolve_path(file);
+        match fs
+            .access(&path, creds, PERM_READ)
+            .and_then(|()| fs.read_file(&path))
+        {
+            Ok(content) if is_binary_file(&path, &content) => {
+                errors.push(format!("{}: {}: binary file", cmd, file));
+            }
+            Ok(content) => {
+                inputs.push((file.to_string(), String::from_utf8_lossy(&content).into_owned()));
+            }
+            Err(err) => errors.push(format!("{}: {}: {}", cmd, file, err)),
+        }
+    }
+    inputs
+}
+
+/// Lines of every input, in order
+fn filter_lines(inputs: &[(String, String)]) -> Vec<&str> {
+    inputs.iter().flat_map(|(_, text)| text.lines()).collect()
+}
+
+/// Response for a filter: its output plus any per-file errors (status 1 if there were some)
+fn filter_response(stdout: String, errors: Vec<String>) -> CommandResponse {
+    CommandResponse {
+        stdout,
+        status: if errors.is_empty() { 0 } else { 1 },
+        stderr: errors.join("\n"),
+        ..CommandResponse::default()
+    }
+}
+
+/// Takes an option's value, attached (`-n5`) or as the next argument (`-n 5`)
+fn option_value<'a>(
+    cmd: &str,
+    flag: char,
+    attached: &'a str,
+    rest: &mut std::slice::Iter<'_, &'a str>,
+) -> Result<&'a str, CommandResponse> {
+    if !attached.is_empty() {
+        return Ok(attached);
+    }
+    rest.next().copied().ok_or_else(|| {
+        CommandResponse::error(format!("{}: option requires an argument -- '{}'", cmd, flag))
+    })
+}
+
+/// Line or byte count for head/tail with its optional sign (`-n -5`, `-n +5`)
+struct HeadTailArgs<'a> {
+    count: usize,
+    sign: Option<char>,
+    bytes: bool,
+    files: Vec<&'a str>,
+}
+
+/// Parses `-n N`, `-c N` and the short form `-N` shared by head and tail
+fn parse_head_tail<'a>(cmd: &str, args: &[&'a str]) -> Result<HeadTailArgs<'a>, CommandResponse> {
+    let mut spec = "10";
+    let mut bytes = false;
+    let mut files = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        if *arg == "-" || !arg.starts_with('-') {
+            files.push(*arg);
+            continue;
+        }
+        let flag = arg[1..].chars().next().unwrap_or('-');
+        match flag {
+            'n' | 'c' => {
+                bytes = flag == 'c';
+                spec = option_value(cmd, flag, &arg[2..], &mut rest)?;
+            }
+            _ if arg[1..].chars().all(|c| c.is_ascii_digit()) => spec = &arg[1..],
+            _ => {
+                return Err(CommandResponse::error(format!(
+                    "{}: invalid option -- '{}'",
+                    cmd, flag
+                )));
+            }
+        }
+    }
+
+    let sign = spec.chars().next().filter(|c| matches!(c, '+' | '-'));
+    let digits = if sign.is_some() { &spec[1..] } else { spec };
+    let count = digits.parse().map_err(|_| {
+        let unit = if bytes { "bytes" } else { "lines" };
+        CommandResponse::error(format!("{}: invalid number of {}: '{}'", cmd, unit, spec))
+    })?;
+    Ok(HeadTailArgs {
+        count,
+        sign,
+        bytes,
+        files,
+    })
+}
+
+/// Runs head or tail over each input, with `==> name <==` headers for several files
+fn head_tail(
+    fs: &FileSystem,
+    creds: &Credentials,
+    cmd: &str,
+    args: &[&str],
+    stdin: Option<&str>,
+    select: fn(&HeadTailArgs, &str) -> String,
+) -> CommandResponse {
+    let parsed = match parse_head_tail(cmd, args) {
+        Ok(parsed) => parsed,
+        Err(response) => return response,
+    };
+    if parsed.files.is_empty() && stdin.is_none() {
+        return CommandResponse::error(format!("Usage: {} [-n N] [-c N] <file>...", cmd));
+    }
+
+    let mut errors = Vec::new();
+    let inputs = filter_inputs(fs, creds, cmd, &parsed.files, stdin, &mut errors);
+    let headers = parsed.files.len() > 1;
+    let mut out = Vec::new();
+    for (name, text) in &inputs {
+        if headers {
+            if !out.is_empty() {
+                out.push(String::new());
+            }
+            let name = if name == "-" { "standard input" } else { name };
+            out.push(format!("==> {} <==", name));
+        }
+        let mut selected = select(&parsed, text);
+        // A byte count can end on the input's final newline, which streams leave out
+        if parsed.bytes && selected.ends_with('\n') {
+            selected.pop();
+        }
+        if !selected.is_empty() || !headers {
+            out.push(selected);
+        }
+    }
+    filter_response(out.join("\n"), errors)
+}
+
+/// head - Print the first lines (-n N, default 10) or bytes (-c N) of each input
+///
+/// A negative count (`-n -N`) prints all but the last N. A byte count that
+/// ends inside a multi-byte character stops before that character.
+fn handle_head(
+    fs: &FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> CommandResponse {
+    head_tail(fs, creds, "head", args, stdin, |parsed, text| {
+        if parsed.bytes {
+            let end = if parsed.sign == Some('-') {
+                text.len().saturating_sub(parsed.count)
+            } else {
+                parsed.count
+            };
+            // The last character boundary at or before `end`
+            let cut = char_boundaries(text).take_while(|idx| *idx <= end).last();
+            return text[..cut.unwrap_or(0)].to_string();
+        }
+        let lines: Vec<&str> = text.lines().collect();
+        let end = if parsed.sign == Some('-') {
+            lines.len().saturating_sub(parsed.count)
+        } else {
+            parsed.count.min(lines.len())
+        };
+        lines[..end].join("\n")
+    })
+}
+
+/// Byte offsets where a character starts, then the end of the text
+fn char_boundaries(text: &str) -> impl Iterator<Item = usize> + '_ {
+    text.char_indices().map(|(idx, _)| idx).chain([text.len()])
+}
+
+/// tail - Print the last lines (-n N, default 10) or bytes (-c N) of each input
+///
+/// A `+N` count starts at line (or byte) N instead. A byte count that starts
+/// inside a multi-byte character skips the rest of that character.
+fn handle_tail(
+    fs: &FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> CommandResponse {
+    head_tail(fs, creds, "tail", args, stdin, |parsed, text| {
+        let start = |len: usize| {
+            if parsed.sign == Some('+') {
+                parsed.count.saturating_sub(1).min(len)
+            } else {
+                len.saturating_sub(parsed.count)
+            }
+        };
+        if parsed.bytes {
+            let start = start(text.len());
+            let cut = char_boundaries(text).find(|idx| *idx >= start);
+            return text[cut.unwrap_or(text.len())..].to_string();
+        }
+        let lines: Vec<&str> = text.lines().collect();
+        lines[start(lines.len())..].join("\n")
+    })
+}
+
+/// wc - Count lines, words and bytes (-l, -w, -c; -m counts characters)
+///
+/// Command output here carries no trailing newline, so a final unterminated
+/// line is counted as a line.
+fn handle_wc(
+    fs: &FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> CommandResponse {
+    let (mut lines, mut words, mut chars, mut bytes) = (false, false, false, false);
+    let mut files = Vec::new();
+    for arg in args {
+        if *arg == "-" || !arg.starts_with('-') {
+            files.push(*arg);
+            continue;
+        }
+        for flag in arg.chars().skip(1) {
+            match flag {
+                'l' => lines = true,
+                'w' => words = true,
+                'm' => chars = true,
+                'c' => bytes = true,
+                _ => return CommandResponse::error(format!("wc: invalid option -- '{}'", flag)),
+            }
+        }
+    }
+    if !(lines || words || chars || bytes) {
+        (lines, words, bytes) = (true, true, true);
+    }
+    if files.is_empty() && stdin.is_none() {
+        return CommandResponse::error("Usage: wc [-lwmc] <file>...");
+    }
+
+    let mut errors = Vec::new();
+    let inputs = filter_inputs(fs, creds, "wc", &files, stdin, &mut errors);
+    let mut rows: Vec<(Vec<usize>, String)> = inputs
+        .iter()
+        .map(|(name, text)| {
+            let newlines = text.matches('\n').count();
+            let counts = [
+                (lines, newlines + usize::from(!text.is_empty() && !text.ends_with('\n'))),
+                (words, text.split_whitespace().count()),
+                (chars, text.chars().count()),
+                (bytes, text.len()),
+            ];
+            let counts = counts.iter().filter(|(on, _)| *on).map(|(_, n)| *n).collect();
+            let name = if files.is_empty() { String::new() } else { name.clone() };
+            (counts, name)
+        })
+        .collect();
+    if files.len() > 1 && !rows.is_empty() {
+        let columns = rows[0].0.len();
+        let total = (0..columns).map(|col| rows.iter().map(|row| row.0[col]).sum()).collect();
+        rows.push((total, "total".to_string()));
+    }
+
+    // Columns share one width unless a single count is printed on its own
+    let single = rows.len() == 1 && rows[0].0.len() == 1;
+    let widest = rows.iter().flat_map(|row| &row.0).max().copied().unwrap_or(0);
+    let width = if single {
+        0
+    } else if files.is_empty() {
+        widest.to_string().len().max(7)
+    } else {
+        widest.to_string().len()
+    };
+    let out: Vec<String> = rows
+        .iter()
+        .map(|(counts, name)| {
+            let mut fields: Vec<String> =
+                counts.iter().map(|n| format!("{:>width$}", n, width = width)).collect();
+            if !name.is_empty() {
+                fields.push(name.clone());
+            }
+            fields.join(" ")
+        })
+        .collect();
+    filter_response(out.join("\n"), errors)
+}
+
+/// A `sort -k` key: fields `start..=end` (1-based), compared as text or numbers
+struct SortKey {
+    start: usize,
+    end: Option<usize>,
+    numeric: bool,
+    reverse: bool,
+}
+
+/// Parses a key spec like `2`, `2,3` or `2,2nr`; trailing `n`/`r` set per-key options
+fn parse_sort_key(spec: &str, numeric: bool, reverse: bool) -> Option<SortKey> {
+    let mut key = SortKey {
+        start: 0,
+        end: None,
+        numeric,
+        reverse,
+    };
+    let mut own_options = false;
+    let (start, end) = match spec.split_once(',') {
+        Some((start, end)) => (start, Some(end)),
+        None => (spec, None),
+    };
+
+    let mut field = |part: &str| -> Option<usize> {
+        let digits = part.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        for option in part[digits.len()..].chars() {
+            if !own_options {
+                (key.numeric, key.reverse, own_options) = (false, false, true);
+            }
+            match option {
+                'n' => key.numeric = true,
+                'r' => key.reverse = true,
+                _ => return None,
+            }
+        }
+        digits.parse().ok().filter(|field| *field > 0)
+    };
+    let start = field(start)?;
+    let end = match end {
+        Some(end) => Some(field(end)?),
+        None => None,
+    };
+    (key.start, key.end) = (start, end);
+    Some(key)
+}
+
+/// Extracts a key's fields from a line, split on `separator` or on runs of blanks
+fn sort_key_text(line: &str, key: &SortKey, separator: Option<char>) -> String {
+    let fields: Vec<&str> = match separator {
+        Some(separator) => line.split(separator).collect(),
+        None => line.split_whitespace().collect(),
+    };
+    let end = key.end.unwrap_or(fields.len()).min(fields.len());
+    if key.start > end {
+        return String::new();
+    }
+    let joiner = separator.map(String::from).unwrap_or_else(|| " ".to_string());
+    fields[key.start - 1..end].join(&joiner)
+}
+
+/// The leading number of a sort key (`-12.5kg` → -12.5); text without one sorts as 0
+fn sort_number(text: &str) -> f64 {
+    let text = text.trim_start();
+    let mut end = 0;
+    let mut seen_dot = false;
+    for (idx, c) in text.char_indices() {
+        match c {
+            '-' if idx == 0 => {}
+            '.' if !seen_dot => seen_dot = true,
+            _ if c.is_ascii_digit() => {}
+            _ => break,
+        }
+        end = idx + c.len_utf8();
+    }
+    text[..end].parse().unwrap_or(0.0)
+}
+
+/// sort - Sort lines (-n numeric, -r reverse, -u unique, -k key fields, -t separator)
+///
+/// Text compares byte-wise, as in the C locale. Lines whose keys tie are
+/// ordered by the whole line unless -u is given.
+fn handle_sort(
+    fs: &FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> CommandResponse {
+    let (mut numeric, mut reverse, mut unique) = (false, false, false);
+    let mut key_specs = Vec::new();
+    let mut separator = None;
+    let mut files = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        if *arg == "-" || !arg.starts_with('-') {
+            files.push(*arg);
+            continue;
+        }
+        for (idx, flag) in arg.char_indices().skip(1) {
+            match flag {
+                'n' => numeric = true,
+                'r' => reverse = true,
+                'u' => unique = true,
+                'k' | 't' => {
+                    let value = match option_value("sort", flag, &arg[idx + 1..], &mut rest) {
+                        Ok(value) => value,
+                        Err(response) => return response,
+                    };
+                    if flag == 'k' {
+                        key_specs.push(value);
+                    } else {
+                        let mut chars = value.chars();
+                        separator = chars.next();
+                        if separator.is_none() || chars.next().is_some() {
+                            return CommandResponse::error(
+                                "sort: the separator must be one character",
+                            );
+                        }
+                    }
+                    break;
+                }
+                _ => return CommandResponse::error(format!("sort: invalid option -- '{}'", flag)),
+            }
+        }
+    }
+    if files.is_empty() && stdin.is_none() {
+        return CommandResponse::error("Usage: sort [-nru] [-k N[,M]] [-t SEP] <file>...");
+    }
+
+    let mut keys = Vec::new();
+    for spec in &key_specs {
+        match parse_sort_key(spec, numeric, reverse) {
+            Some(key) => keys.push(key),
+            None => return CommandResponse::error(format!("sort: invalid key '{}'", spec)),
+        }
+    }
+    if keys.is_empty() {
+        keys.push(SortKey {
+            start: 1,
+            end: None,
+            numeric,
+            reverse,
+        });
+    }
+    let whole_line = key_specs.is_empty();
+
+    let compare_keys = |a: &str, b: &str| {
+        for key in &keys {
+            let (a, b) = if whole_line {
+                (a.to_string(), b.to_string())
+            } else {
+                (sort_key_text(a, key, separator), sort_key_text(b, key, separator))
+            };
+            let ordering = if key.numeric {
+                sort_number(&a).total_cmp(&sort_number(&b))
+            } else {
+                a.cmp(&b)
+            };
+            let ordering = if key.reverse { ordering.reverse() } else { ordering };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    };
+
+    let mut errors = Vec::new();
+    let inputs = filter_inputs(fs, creds, "sort", &files, stdin, &mut errors);
+    let mut lines = filter_lines(&inputs);
+    lines.sort_by(|a, b| {
+        compare_keys(a, b).then_with(|| {
+            if unique {
+                Ordering::Equal
+            } else if reverse {
+                b.cmp(a)
+            } else {
+                a.cmp(b)
+            }
+        })
+    });
+    if unique {
+        lines.dedup_by(|a, b| compare_keys(a, b) == Ordering::Equal);
+    }
+    filter_response(lines.join("\n"), errors)
+}
+
+/// uniq - Collapse adjacent duplicate lines (-c count, -d only repeated, -u only unique, -i)
+fn handle_uniq(
+    fs: &FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> CommandResponse {
+    let (mut count, mut repeated, mut single, mut ignore_case) = (false, false, false, false);
+    let mut files = Vec::new();
+    for arg in args {
+        if *arg == "-" || !arg.starts_with('-') {
+            files.push(*arg);
+            continue;
+        }
+        for flag in arg.chars().skip(1) {
+            match flag {
+                'c' => count = true,
+                'd' => repeated = true,
+                'u' => single = true,
+                'i' => ignore_case = true,
+                _ => return CommandResponse::error(format!("uniq: invalid option -- '{}'", flag)),
+            }
+        }
+    }
+    if files.len() > 1 {
+        return CommandResponse::error(format!("uniq: extra operand '{}'", files[1]));
+    }
+    if files.is_empty() && stdin.is_none() {
+        return CommandResponse::error("Usage: uniq [-cdui] <file>");
+    }
+
+    let mut errors = Vec::new();
+    let inputs = filter_inputs(fs, creds, "uniq", &files, stdin, &mut errors);
+    let same = |a: &str, b: &str| {
+        if ignore_case {
+            a.to_lowercase() == b.to_lowercase()
+        } else {
+            a == b
+        }
+    };
+
+    let mut groups: Vec<(&str, usize)> = Vec::new();
+    for line in filter_lines(&inputs) {
+        match groups.last_mut() {
+            Some((first, n)) if same(first, line) => *n += 1,
+            _ => groups.push((line, 1)),
+        }
+    }
+    let out: Vec<String> = groups
+        .into_iter()
+        .filter(|(_, n)| (!repeated || *n > 1) && (!single || *n == 1))
+        .map(|(line, n)| {
+            if count {
+                format!("{:>7} {}", n, line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    filter_response(out.join("\n"), errors)
+}
+
+/// Parses a cut list like `1,3-5,7-` into inclusive 1-based ranges
+fn parse_cut_list(list: &str) -> Result<Vec<(usize, usize)>, String> {
+    let number = |text: &str, default: usize| -> Result<usize, String> {
+        if text.is_empty() {
+            return Ok(default);
+        }
+        match text.parse::<usize>() {
+            Ok(0) => Err("cut: fields and positions are numbered from 1".to_string()),
+            Ok(n) => Ok(n),
+            Err(_) => Err(format!("cut: invalid field value '{}'", text)),
+        }
+    };
+
+    let mut ranges = Vec::new();
+    for item in list.split(',') {
+        let range = match item.split_once('-') {
+            Some(("", "")) => return Err("cut: invalid range with no endpoint: -".to_string()),
+            Some((start, end)) => (number(start, 1)?, number(end, usize::MAX)?),
+            None => {
+                let n = number(item, 0)?;
+                if n == 0 {
+                    return Err(format!("cut: invalid field value '{}'", item));
+                }
+                (n, n)
+            }
+        };
+        if range.0 > range.1 {
+            return Err("cut: invalid decreasing range".to_string());
+        }
+        ranges.push(range);
+    }
+    Ok(ranges)
+}
+
+/// cut - Print selected fields (-f, split on -d, default tab) or characters (-c) of each line
+///
+/// Lines without the delimiter are printed whole unless -s is given.
+fn handle_cut(
+    fs: &FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> CommandResponse {
+    let mut delimiter = None;
+    let mut fields = None;
+    let mut chars = None;
+    let mut only_delimited = false;
+    let mut files = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        if *arg == "-" || !arg.starts_with('-') {
+            files.push(*arg);
+            continue;
+        }
+        for (idx, flag) in arg.char_indices().skip(1) {
+            match flag {
+                's' => only_delimited = true,
+                'd' | 'f' | 'c' | 'b' => {
+                    let value = match option_value("cut", flag, &arg[idx + 1..], &mut rest) {
+                        Ok(value) => value,
+                        Err(response) => return response,
+                    };
+                    match flag {
+                        'd' => {
+                            let mut value_chars = value.chars();
+                            delimiter = value_chars.next();
+                            if delimiter.is_none() || value_chars.next().is_some() {
+                                return CommandResponse::error(
+                                    "cut: the delimiter must be a single character",
+                                );
+                            }
+                        }
+                        'f' => fields = Some(value),
+                        _ => chars = Some(value),
+                    }
+                    break;
+                }
+                _ => return CommandResponse::error(format!("cut: invalid option -- '{}'", flag)),
+            }
+        }
+    }
+
+    let (list, by_fields) = match (fields, chars) {
+        (Some(list), None) => (list, true),
+        (None, Some(list)) => (list, false),
+        (Some(_), Some(_)) => {
+            return CommandResponse::error("cut: only one type of list may be specified");
+        }
+        (None, None) => {
+            return CommandResponse::error(
+                "cut: you must specify a list of bytes, characters, or fields",
+            );
+        }
+    };
+    if !by_fields && delimiter.is_some() {
+        return CommandResponse::error(
+            "cut: an input delimiter may be specified only when operating on fields",
+        );
+    }
+    let ranges = match parse_cut_list(list) {
+        Ok(ranges) => ranges,
+        Err(message) => return CommandResponse::error(message),
+    };
+    if files.is_empty() && stdin.is_none() {
+        return CommandResponse::error("Usage: cut -f LIST [-d DELIM] | -c LIST <file>...");
+    }
+    let selected =
+        |position: usize| ranges.iter().any(|(lo, hi)| *lo <= position && position <= *hi);
+
+    let mut errors = Vec::new();
+    let inputs = filter_inputs(fs, creds, "cut", &files, stdin, &mut errors);
+    let delimiter = delimiter.unwrap_or('\t');
+    let mut out = Vec::new();
+    for line in filter_lines(&inputs) {
+        if !by_fields {
+            let kept: String = line
+                .chars()
+                .enumerate()
+                .filter(|(idx, _)| selected(idx + 1))
+                .map(|(_, c)| c)
+                .collect();
+            out.push(kept);
+        } else if line.contains(delimiter) {
+            let kept: Vec<&str> = line
+                .split(delimiter)
+                .enumerate()
+                .filter(|(idx, _)| selected(idx + 1))
+                .map(|(_, field)| field)
+                .collect();
+            out.push(kept.join(&delimiter.to_string()));
+        } else if !only_delimited {
+            out.push(line.to_string());
+        }
+    }
+    filter_response(out.join("\n"), errors)
+}
+
+/// Expands a tr set: escapes (`\n`, `\t`, `\\`), ranges (`a-z`) and `[:class:]` names
+fn expand_tr_set(set: &str) -> Result<Vec<char>, String> {
+    let mut raw = Vec::new();
+    let mut chars = set.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '[' && chars.peek() == Some(&':') {
+            let rest: String = chars.clone().skip(1).collect();
+            if let Some(end) = rest.find(":]") {
+                let name = &rest[..end];
+                let members: Vec<char> = match name {
+                    "alpha" => ('A'..='Z').chain('a'..='z').collect(),
+                    "upper" => ('A'..='Z').collect(),
+                    "lower" => ('a'..='z').collect(),
+                    "digit" => ('0'..='9').collect(),
+                    "alnum" => ('0'..='9').chain('A'..='Z').chain('a'..='z').collect(),
+                    "space" => vec!['\t', '\n', '\u{b}', '\u{c}', '\r', ' '],
+                    "blank" => vec!['\t', ' '],
+                    "punct" => {
+                        (0u8..128).map(char::from).filter(char::is_ascii_punctuation).collect()
+                    }
+                    _ => return Err(format!("tr: invalid character class '{}'", name)),
+                };
+                // Class members count as literal characters, never as range ends
+                raw.extend(members.into_iter().map(|c| (c, true)));
+                for _ in 0..name.chars().count() + 3 {
+                    chars.next();
+                }
+                continue;
+            }
+        }
+        let c = if c == '\\' {
+            match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some(escaped) => escaped,
+                None => '\\',
+            }
+        } else {
+            c
+        };
+        raw.push((c, false));
+    }
+
+    let mut expanded = Vec::new();
+    let mut idx = 0;
+    while idx < raw.len() {
+        let (c, literal) = raw[idx];
+        if !literal
+            && idx + 2 < raw.len()
+            && raw[idx + 1] == ('-', false)
+            && !raw[idx + 2].1
+        {
+            let end = raw[idx + 2].0;
+            if end < c {
+                return Err(format!(
+                    "tr: range-endpoints of '{}-{}' are in reverse collating sequence order",
+                    c, end
+                ));
+            }
+            expanded.extend(c..=end);
+            idx += 3;
+        } else {
+            expanded.push(c);
+            idx += 1;
+        }
+    }
+    Ok(expanded)
+}
+
+/// tr - Translate (tr SET1 SET2), delete (-d) or squeeze (-s) characters from stdin
+///
+/// -c complements SET1. When SET2 is shorter than SET1 its last character repeats.
+fn handle_tr(args: &[&str], stdin: Option<&str>) -> CommandResponse {
+    let (mut complement, mut delete, mut squeeze) = (false, false, false);
+    let mut operands = Vec::new();
+    for arg in args {
+        if !arg.starts_with('-') || arg.len() == 1 || !operands.is_empty() {
+            operands.push(*arg);
+            continue;
+        }
+        for flag in arg.chars().skip(1) {
+            match flag {
+                'c' | 'C' => complement = true,
+                'd' => delete = true,
+                's' => squeeze = true,
+                _ => return CommandResponse::error(format!("tr: invalid option -- '{}'", flag)),
+            }
+        }
+    }
+
+    // Translating and -ds need both sets; -d takes only SET1; -s takes one or two
+    let needs_set2 = delete == squeeze;
+    let max_sets = if delete && !squeeze { 1 } else { 2 };
+    if operands.is_empty() {
+        return CommandResponse::error("tr: missing operand");
+    }
+    if operands.len() == 1 && needs_set2 {
+        return CommandResponse::error(format!("tr: missing operand after '{}'", operands[0]));
+    }
+    if operands.len() > max_sets {
+        return CommandResponse::error(format!("tr: extra operand '{}'", operands[max_sets]));
+    }
+    let (set1, set2) = (operands[0], operands.get(1).copied());
+    let Some(input) = stdin else {
+        return CommandResponse::error("Usage: tr [-cds] SET1 [SET2]  (reads stdin)");
+    };
+
+    let set1 = match expand_tr_set(set1) {
+        Ok(set) => set,
+        Err(message) => return CommandResponse::error(message),
+    };
+    let set2 = match set2.map(expand_tr_set).transpose() {
+        Ok(set) => set,
+        Err(message) => return CommandResponse::error(message),
+    };
+    let in_set1 = |c: char| set1.contains(&c) != complement;
+
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        if delete {
+            if !in_set1(c) {
+                out.push(c);
+            }
+            continue;
+        }
+        let translated = match &set2 {
+            // With -c every character outside SET1 maps to SET2's last character
+            Some(set2) if in_set1(c) => {
+                let mapped = if complement {
+                    set2.last()
+                } else {
+                    let position = set1.iter().rposition(|member| *member == c).unwrap_or(0);
+                    set2.get(position).or(set2.last())
+                };
+                mapped.copied().unwrap_or(c)
+            }
+            _ => c,
+        };
+        out.push(translated);
+    }
+
+    if squeeze {
+        // Squeeze uses SET2 after translating or deleting, otherwise SET1
+        let squeezable = |c: char| match &set2 {
+            Some(set2) => set2.contains(&c),
+            None => in_set1(c),
+        };
+        let mut squeezed = String::with_capacity(out.len());
+        for c in out.chars() {
+            if squeezed.ends_with(c) && squeezable(c) {
+                continue;
+            }
+            squeezed.push(c);
+        }
+        out = squeezed;
+    }
+    CommandResponse::ok(out)
+}
+
+/// rev - Reverse the characters of each line
+fn handle_rev(
+    fs: &FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> CommandResponse {
+    if args.is_empty() && stdin.is_none() {
+        return CommandResponse::error("Usage: rev <file>...");
+    }
+
+    let mut errors = Vec::new();
+    let inputs = filter_inputs(fs, creds, "rev", args, stdin, &mut errors);
+    let out: Vec<String> = filter_lines(&inputs)
+        .into_iter()
+        .map(|line| line.chars().rev().collect())
+        .collect();
+    filter_response(out.join("\n"), errors)
+}
+
+/// Parsed `diff` flags
+struct DiffOptions<'a> {
+    /// Context lines for unified output; `None` selects normal output
+    unified: Option<usize>,
+    brief: bool,
+    recursive: bool,
+    /// Flags as written, repeated in the `diff ...` line before each file of a tree
+    flags: Vec<&'a str>,
+}
+
+/// diff - Compare files line by line, or directory trees with -r
+///
+/// Output is normal format unless -u (or -U N) asks for unified hunks; -q
+/// only reports whether files differ. Exits 0 when the inputs are the same,
+/// 1 when they differ and 2 on errors.
+fn handle_diff(
+    fs: &FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> CommandResponse {
+    let trouble = |message: String| CommandResponse {
+        status: 2,
+        ..CommandResponse::error(message)
+    };
+
+    let mut opts = DiffOptions {
+        unified: None,
+        brief: false,
+        recursive: false,
+        flags: Vec::new(),
+    };
+    let mut operands = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        if *arg == "-" || !arg.starts_with('-') {
+            operands.push(*arg);
+            continue;
+        }
+        opts.flags.push(*arg);
+        for (idx, flag) in arg.char_indices().skip(1) {
+            match flag {
+                'u' => opts.unified = Some(3),
+                'q' => opts.brief = true,
+                'r' => opts.recursive = true,
+                'U' => {
+                    let value = match option_value("diff", flag, &arg[idx + 1..], &mut rest) {
+                        Ok(value) => value,
+                        Err(response) => return CommandResponse { status: 2, ..response },
+                    };
+                    if arg.len() == 2 {
+                        opts.flags.push(value);
+                    }
+                    match value.parse() {
+                        Ok(context) => opts.unified = Some(context),
+                        Err(_) => {
+                            return trouble(format!("diff: invalid context length '{}'", value));
+                        }
+                    }
+                    break;
+                }
+                _ => return trouble(format!("diff: invalid option -- '{}'", flag)),
+            }
+        }
+    }
+
+    let (old, new) = match operands.as_slice() {
+        [old, new] => (*old, *new),
+        [] => return trouble("Usage: diff [-u] [-U N] [-q] [-r] <file1> <file2>".to_string()),
+        [only] => return trouble(format!("diff: missing operand after '{}'", only)),
+        _ => return trouble(format!("diff: extra operand '{}'", operands[2])),
+    };
+
+    let mut out = Vec::new();
+    let mut errors = Vec::new();
+    let differ = diff_paths(fs, creds, &opts, stdin, old, new, &mut out, &mut errors);
+    CommandResponse {
+        stdout: out.join("\n"),
+        stderr: errors.join("\n"),
+        status: if !errors.is_empty() {
+            2
+        } else if differ {
+            1
+        } else {
+            0
+        },
+        ..CommandResponse::default()
+    }
+}
+
+/// Reads one side of a diff: a file's decrypted text, or stdin for `-`
+fn diff_input(
+    fs: &FileSystem,
+    creds: &Credentials,
+    stdin: Option<&str>,
+    name: &str,
+) -> Result<(Vec<u8>, bool), String> {
+    if name == "-" {
+        return Ok((stdin.unwrap_or("").as_bytes().to_vec(), false));
+    }
+    let path = fs.resolve_path(name);
+    fs.access(&path, creds, PERM_READ)
+        .and_then(|()| fs.read_file(&path))
+        .map(|content| {
+            let binary = is_binary_file(&path, &content);
+            (content, binary)
+        })
+        .map_err(|err| format!("diff: {}: {}", name, err))
+}
+
+/// Compares two operands, descending into directories
+///
+/// # Returns
+/// Whether a difference was found
+#[allow(clippy::too_many_arguments)]
+fn diff_paths(
+    fs: &FileSystem,
+    creds: &Credentials,
+    opts: &DiffOptions,
+    stdin: Option<&str>,
+    old: &str,
+    new: &str,
+    out: &mut Vec<String>,
+    errors: &mut Vec<String>,
+) -> bool {
+    let is_dir = |name: &str| name != "-" && fs.is_dir(&fs.resolve_path(name));
+    for name in [old, new] {
+        if name != "-" && !fs.exists(&fs.resolve_path(name)) {
+            let err = fs.metadata(&fs.resolve_path(name)).err().unwrap_or(FsError::NotFound);
+            errors.push(format!("diff: {}: {}", name, err));
+            return false;
+        }
+    }
+
+    // `diff file dir` compares against the file of the same name inside dir
+    let (old, new) = match (is_dir(old), is_dir(new)) {
+        (true, true) => return diff_dirs(fs, creds, opts, old, new, out, errors),
+        (true, false) => (join_path(old, basename(new)), new.to_string()),
+        (false, true) => (old.to_string(), join_path(new, basename(old))),
+        (false, false) => (old.to_string(), new.to_string()),
+    };
+
+    let (old_content, old_binary) = match diff_input(fs, creds, stdin, &old) {
+        Ok(input) => input,
+        Err(message) => {
+            errors.push(message);
+            return false;
+        }
+    };
+    let (new_content, new_binary) = match diff_input(fs, creds, stdin, &new) {
+        Ok(input) => input,
+        Err(message) => {
+            errors.push(message);
+            return false;
+        }
+    };
+    if old_content == new_content {
+        return false;
+    }
+    if opts.brief {
+        out.push(format!("Files {} and {} differ", old, new));
+        return true;
+    }
+    if old_binary || new_binary {
+        out.push(format!("Binary files {} and {} differ", old, new));
+        return true;
+    }
+
+    let old_text = String::from_utf8_lossy(&old_content);
+    let new_text = String::from_utf8_lossy(&new_content);
+    let old_lines = diff::lines(&old_text);
+    let new_lines = diff::lines(&new_text);
+    let edits = diff::diff(&old_lines, &new_lines);
+
+    match opts.unified {
+        Some(context) => {
+            let stamp = |name: &str| {
+                let time = match name {
+                    "-" => 0.0,
+                    _ => fs.metadata(&fs.resolve_path(name)).map_or(0.0, |node| node.timestamp),
+                };
+                clock::format_date(time)
+            };
+            out.push(format!("--- {}\t{}", old, stamp(&old)));
+            out.push(format!("+++ {}\t{}", new, stamp(&new)));
+            let hunks = diff::hunks(&edits, context);
+            out.extend(diff::unified(&old_lines, &new_lines, &hunks));
+        }
+        None => {
+            let hunks = diff::hunks(&edits, 0);
+            out.extend(diff::normal(&old_lines, &new_lines, &hunks));
+        }
+    }
+    true
+}
+
+/// Compares two directories entry by entry; subdirectories are only entered with -r
+///
+/// Symlinks are compared by target rather than followed, so -r cannot loop.
+fn diff_dirs(
+    fs: &FileSystem,
+    creds: &Credentials,
+    opts: &DiffOptions,
+    old: &str,
+    new: &str,
+    out: &mut Vec<String>,
+    errors: &mut Vec<String>,
+) -> bool {
+    let mut listings = Vec::new();
+    for dir in [old, new] {
+        let path = fs.resolve_path(dir);
+        let listed = fs
+            .access(&path, creds, PERM_READ)
+            .and_then(|()| fs.list_directory(&path));
+        match listed {
+            Ok(entries) => listings.push(
+                entries
+                    .into_iter()
+                    .map(|entry| entry.trim_end_matches('/').to_string())
+                    .collect::<Vec<_>>(),
+            ),
+            Err(err) => {
+                errors.push(format!("diff: {}: {}", dir, err));
+                return false;
+            }
+        }
+    }
+
+    let mut names: Vec<&String> = listings[0].iter().chain(&listings[1]).collect();
+    names.sort();
+    names.dedup();
+
+    let mut differ = false;
+    for name in names {
+        let old_entry = join_path(old, name);
+        let new_entry = join_path(new, name);
+        let (in_old, in_new) = (listings[0].contains(name), listings[1].contains(name));
+        if !in_old || !in_new {
+            let dir = if in_old { old } else { new };
+            out.push(format!("Only in {}: {}", dir, name));
+            differ = true;
+            continue;
+        }
+
+        let (old_path, new_path) = (fs.resolve_path(&old_entry), fs.resolve_path(&new_entry));
+        let kind = |path: &str| {
+            if fs.is_symlink(path) {
+                "symbolic link"
+            } else if fs.is_dir(path) {
+                "directory"
+            } else {
+                "regular file"
+            }
+        };
+        let (old_kind, new_kind) = (kind(&old_path), kind(&new_path));
+        if old_kind != new_kind {
+            out.push(format!(
+                "File {} is a {} while file {} is a {}",
+                old_entry, old_kind, new_entry, new_kind
+            ));
+            differ = true;
+        } else if old_kind == "symbolic link" {
+            if fs.read_link(&old_path).ok() != fs.read_link(&new_path).ok() {
+                out.push(format!("Symbolic links {} and {} differ", old_entry, new_entry));
+                differ = true;
+            }
+        } else if old_kind == "directory" {
+            if opts.recursive {
+                differ |= diff_dirs(fs, creds, opts, &old_entry, &new_entry, out, errors);
+            } else {
+                out.push(format!("Common subdirectories: {} and {}", old_entry, new_entry));
+            }
+        } else {
+            let mark = out.len();
+            if diff_paths(fs, creds, opts, None, &old_entry, &new_entry, out, errors) {
+                differ = true;
+                if !opts.brief {
+                    let mut command = vec!["diff"];
+                    command.extend(&opts.flags);
+                    let command = format!("{} {} {}", command.join(" "), old_entry, new_entry);
+                    out.insert(mark, command);
+                }
+            }
+        }
+    }
+    differ
+}
+
+/// Removes `count` leading components from a patch path (`-p` option)
+fn strip_patch_path(path: &str, count: usize) -> Option<String> {
+    if count == 0 {
+        return Some(path.to_string());
+    }
+    let mut rest = path;
+    for _ in 0..count {
+        let (_, tail) = rest.split_once('/')?;
+        rest = tail.trim_start_matches('/');
+    }
+    (!rest.is_empty()).then(|| rest.to_string())
+}
+
+/// patch - Apply a unified diff (from stdin or -i FILE) to files in the filesystem
+///
+/// File names come from the `---`/`+++` headers with -p N leading components
+/// removed (without -p, only the base name is kept), unless a FILE operand
+/// names the target. A file is only written when all of its hunks apply;
+/// hunks whose context moved are applied at the new position.
+fn handle_patch(
+    fs: &mut FileSystem,
+    creds: &Credentials,
+    umask: u16,
+    args: &[&str],
+    stdin: Option<&str>,
+    now: f64,
+) -> CommandResponse {
+    let trouble = |message: String| CommandResponse {
+        status: 2,
+        ..CommandResponse::error(message)
+    };
+
+    let mut strip = None;
+    let mut reverse = false;
+    let mut dry_run = false;
+    let mut input = None;
+    let mut operands = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match *arg {
+            "-R" | "--reverse" => reverse = true,
+            "--dry-run" => dry_run = true,
+            "-i" | "-p" => {
+                let Some(value) = rest.next() else {
+                    let flag = &arg[1..];
+                    return trouble(format!("patch: option requires an argument -- '{}'", flag));
+                };
+                if *arg == "-i" {
+                    input = Some(*value);
+                } else {
+                    match value.parse() {
+                        Ok(count) => strip = Some(count),
+                        Err(_) => {
+                            return trouble(format!("patch: strip count {} is not a number", value));
+                        }
+                    }
+                }
+            }
+            _ if arg.starts_with("-p") => match arg[2..].parse() {
+                Ok(count) => strip = Some(count),
+                Err(_) => {
+                    return trouble(format!("patch: strip count {} is not a number", &arg[2..]));
+                }
+            },
+            _ if arg.starts_with("-i") => input = Some(&arg[2..]),
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return trouble(format!("patch: unrecognized option '{}'", arg));
+            }
+            _ => operands.push(*arg),
+        }
+    }
+    if operands.len() > 1 {
+        return trouble(format!("patch: extra operand '{}'", operands[1]));
+    }
+
+    let text = match input {
+        Some(name) => {
+            let path = fs.resolve_path(name);
+            match fs.access(&path, creds, PERM_READ).and_then(|()| fs.read_file(&path)) {
+                Ok(content) => String::from_utf8_lossy(&content).into_owned(),
+                Err(err) => return trouble(format!("patch: {}: {}", name, err)),
+            }
+        }
+        None => match stdin {
+            Some(text) => text.to_string(),
+            None => {
+                let usage = "Usage: patch [-pN] [-R] [--dry-run] [-i patchfile] [file]";
+                return trouble(usage.to_string());
+            }
+        },
+    };
+    let patches = match diff::parse_unified(&text) {
+        Ok(patches) => patches,
+        Err(message) => return trouble(format!("patch: **** {}", message)),
+    };
+
+    let mut out = Vec::new();
+    let mut errors = Vec::new();
+    let mut failed = false;
+    for patch in patches.iter().filter(|patch| !patch.hunks.is_empty()) {
+        let (old_name, new_name) = (&patch.old_path, &patch.new_path);
+        let strip_name = |name: &str| match strip {
+            Some(count) => strip_patch_path(name, count),
+            None => Some(basename(name).to_string()),
+        };
+        let (mut creating, mut deleting) = (old_name == "/dev/null", new_name == "/dev/null");
+        if reverse {
+            (creating, deleting) = (deleting, creating);
+        }
+
+        // The first named file that exists, or the new name when creating one
+        let target = match operands.first() {
+            Some(file) => file.to_string(),
+            None => {
+                let candidates: Vec<String> = [old_name, new_name]
+                    .into_iter()
+                    .filter(|name| *name != "/dev/null")
+                    .filter_map(|name| strip_name(name))
+                    .collect();
+                let existing = candidates.iter().find(|name| fs.exists(&fs.resolve_path(name)));
+                match existing.or(if creating { candidates.first() } else { None }) {
+                    Some(name) => name.clone(),
+                    None => {
+                        errors.push(format!(
+                            "patch: can't find file to patch ({} or {})",
+                            old_name, new_name
+                        ));
+                        failed = true;
+                        continue;
+                    }
+                }
+            }
+        };
+        let path = fs.resolve_path(&target);
+
+        let original = if creating && !fs.exists(&path) {
+            Vec::new()
+        } else {
+            match fs.access(&path, creds, PERM_READ).and_then(|()| fs.read_file(&path)) {
+                Ok(content) => content,
+                Err(err) => {
+                    errors.push(format!("patch: {}: {}", target, err));
+                    failed = true;
+                    continue;
+                }
+            }
+        };
+        let original = String::from_utf8_lossy(&original);
+        let lines: Vec<String> = diff::lines(&original).into_iter().map(str::to_string).collect();
+
+        let hunks: Vec<diff::PatchHunk> = if reverse {
+            patch.hunks.iter().map(diff::PatchHunk::reversed).collect()
+        } else {
+            patch.hunks.clone()
+        };
+        let verb = if dry_run { "checking" } else { "patching" };
+        out.push(format!("{} file {}", verb, target));
+
+        let (patched, results) = diff::apply(&lines, &hunks);
+        let failures = results
+            .iter()
+            .filter(|result| matches!(result, diff::HunkResult::Failed(_)))
+            .count();
+        if failures > 0 {
+            // Hunks that apply backwards mean the patch is already in place
+            let undo: Vec<diff::PatchHunk> = hunks.iter().map(diff::PatchHunk::reversed).collect();
+            let (_, undone) = diff::apply(&lines, &undo);
+            if undone.iter().all(|result| !matches!(result, diff::HunkResult::Failed(_))) {
+                let skipped = "Reversed (or previously applied) patch detected!  Skipping patch.";
+                out.push(skipped.to_string());
+                out.push(format!(
+                    "{} out of {} hunk{} ignored",
+                    hunks.len(),
+                    hunks.len(),
+                    if hunks.len() == 1 { "" } else { "s" }
+                ));
+                failed = true;
+                continue;
+            }
+        }
+        for (number, result) in results.iter().enumerate() {
+            match result {
+                diff::HunkResult::Applied => {}
+                diff::HunkResult::Offset(lines) => out.push(format!(
+                    "Hunk #{} succeeded at {} (offset {} line{}).",
+                    number + 1,
+                    hunks[number].old_start as isize + lines,
+                    lines,
+                    if lines.abs() == 1 { "" } else { "s" }
+                )),
+                diff::HunkResult::Failed(line) => {
+                    out.push(format!("Hunk #{} FAILED at {}.", number + 1, line));
+                }
+            }
+        }
+        if failures > 0 {
+            out.push(format!(
+                "{} out of {} hunk{} FAILED -- no changes written to {}",
+                failures,
+                hunks.len(),
+                if hunks.len() == 1 { "" } else { "s" },
+                target
+            ));
+            failed = true;
+            continue;
+        }
+        if dry_run {
+            continue;
+        }
+
+        let written = if deleting && patched.is_empty() {
+            fs.access_entry(&path, creds).and_then(|()| fs.delete(&path))
+        } else {
+            write_output(fs, creds, umask, &target, patched.concat().as_bytes(), now)
+        };
+        if let Err(err) = written {
+            errors.push(format!("patch: {}: {}", target, err));
+            failed = true;
+        }
+    }
+
+    CommandResponse {
+        stdout: out.join("\n"),
+        stderr: errors.join("\n"),
+        status: i32::from(failed),
+        ..CommandResponse::default()
+    }
+}
+
+/// Gives a freshly created node its creator's ownership and umask-filtered mode
+///
+/// Inside a setgid directory the node inherits the directory's group.
+fn assign_new_node(
+    fs: &mut FileSystem,
+    path: &str,
+    creds: &Credentials,
+    umask: u16,
+) -> Result<(), FsError> {
+    let is_dir = fs.is_dir(path);
+    let base = if is_dir { DEFAULT_DIR_MODE } else { DEFAULT_FILE_MODE };
+    let parent = FileSystem::parent_path(path).and_then(|parent| fs.metadata(&parent).ok());
+    let gid = match parent {
+        Some(dir) if dir.mode & MODE_SETGID != 0 => dir.gid,
+        _ => creds.gid,
+    };
+
+    fs.set_mode(path, base & !umask)?;
+    fs.set_owner(path, Some(creds.uid), Some(gid))
+}
+
+/// mkdir - Create directory
+fn handle_mkdir(
+    fs: &mut FileSystem,
+    creds: &Credentials,
+    umask: u16,
+    args: &[&str],
+    now: f64,
+) -> CommandResponse {
+    if args.is_empty() {
+        return CommandResponse::error("Usage: mkdir <directory>");
+    }
+
+    let target = fs.resolve_path(args[0]);
+
+    // An existing name wins over a permission problem, as with mkdir(2)
+    let created = if fs.lexists(&target) {
+        Err(FsError::AlreadyExists)
+    } else {
+        fs.access_entry(&target, creds)
+            .and_then(|()| fs.create_dir(&target, now))
+            .and_then(|()| assign_new_node(fs, &target, creds, umask))
+    };
+
+    match created {
+        Ok(()) => CommandResponse::empty(),
+        Err(err) => fs_error(format!("mkdir: cannot create directory '{}'", args[0]), err),
+    }
+}
+
+/// touch - Create empty file or update timestamp
+fn handle_touch(
+    fs: &mut FileSystem,
+    creds: &Credentials,
+    umask: u16,
+    args: &[&str],
+    now: f64,
+) -> CommandResponse {
+    if args.is_empty() {
+        return CommandResponse::error("Usage: touch <filename>");
+    }
+
+    let target = fs.resolve_path(args[0]);
+
+    let touched = if fs.exists(&target) {
+        // Owners may update timestamps even without write permission
+        let owner = fs.metadata(&target).map(|node| node.uid) == Ok(creds.uid);
+        let allowed = if owner {
+            Ok(())
+        } else {
+            fs.access(&target, creds, PERM_WRITE)
+        };
+        allowed.and_then(|()| fs.touch(&target, now))
+    } else {
+        fs.access_entry(&target, creds)
+            .and_then(|()| fs.write_file(&target, &[], Cipher::default(), now))
+            .and_then(|()| assign_new_node(fs, &target, creds, umask))
+    };
+
+    match touched {
+        Ok(()) => CommandResponse::empty(),
+        Err(err) => fs_error(format!("touch: cannot touch '{}'", args[0]), err),
+    }
+}
+
+/// cp - Copy files and directories
+fn handle_cp(
+    fs: &mut FileSystem,
+    creds: &Credentials,
+    umask: u16,
+    args: &[&str],
+) -> CommandResponse {
+    if args.len() < 2 {
+        return CommandResponse::error("Usage: cp <source> <destination>");
+    }
+
+    let source = fs.resolve_path(args[0]);
+    if let Err(err) = fs.metadata(&source) {
+        return fs_error(format!("cp: cannot stat '{}'", args[0]), err);
+    }
+
+    let destination = resolve_copy_move_destination(fs, &source, args[1]);
+    if source == destination {
+        return CommandResponse::error(format!(
+            "cp: '{}' and '{}' are the same file",
+            args[0], args[1]
+        ));
+    }
+
+    // Every copied file must be readable and every copied directory listable
+    for path in fs.walk(&source).unwrap_or_default() {
+        let want = if fs.is_dir(&path) {
+            PERM_READ | PERM_EXEC
+        } else {
+            PERM_READ
+        };
+        if let Err(err) = fs.access(&path, creds, want) {
+            return fs_error(format!("cp: cannot open '{}' for reading", path), err);
+        }
+    }
+
+    let copied = fs
+        .access_entry(&destination, creds)
+        .and_then(|()| fs.copy_path(&source, &destination));
+    match copied {
+        Ok(()) => {}
+        Err(FsError::IntoItself) => {
+            return CommandResponse::error(format!(
+                "cp: cannot copy a directory, '{}', into itself, '{}'",
+                args[0], args[1]
+            ));
+        }
+        Err(err) => return fs_error(format!("cp: cannot create '{}'", args[1]), err),
+    }
+
+    // Copies belong to whoever made them; special bits are dropped
+    for path in fs.walk(&destination).unwrap_or_default() {
+        let owned = if fs.is_symlink(&path) {
+            fs.lchown(&path, Some(creds.uid), Some(creds.gid))
+        } else {
+            let mode = fs.metadata(&path).map(|node| node.mode).unwrap_or(0);
+            fs.set_mode(&path, mode & 0o777 & !umask)
+                .and_then(|()| fs.set_owner(&path, Some(creds.uid), Some(creds.gid)))
+        };
+        if let Err(err) = owned {
+            return fs_error(format!("cp: preserving ownership for '{}'", path), err);
+        }
+    }
+    CommandResponse::empty()
+}
+
+/// mv - Move or rename files and directories
+fn handle_mv(fs: &mut FileSystem, creds: &Credentials, args: &[&str]) -> CommandResponse {
+    if args.len() < 2 {
+        return CommandResponse::error("Usage: mv <source> <destination>");
+    }
+
+    let source = fs.resolve_path(args[0]);
+    if let Err(err) = fs.symlink_metadata(&source) {
+        return fs_error(format!("mv: cannot stat '{}'", args[0]), err);
+    }
+
+    let destination = resolve_copy_move_destination(fs, &source, args[1]);
+    if source == destination {
+        return CommandResponse::empty();
+    }
+
+    let moved = fs
+        .access_entry(&source, creds)
+        .and_then(|()| fs.access_entry(&destination, creds))
+        .and_then(|()| fs.move_path(&source, &destination));
+    match moved {
+        Ok(()) => CommandResponse::empty(),
+        Err(FsError::IntoItself) => CommandResponse::error(format!(
+            "mv: cannot move '{}' to a subdirectory of itself, '{}'",
+            args[0], args[1]
+        )),
+        Err(err) => fs_error(format!("mv: cannot move '{}' to '{}'", args[0], args[1]), err),
+    }
+}
+
+/// Flags shared by `rm` and its pending `-i` questions
+#[derive(Clone, Copy, Debug)]
+struct RmOptions {
+    recursive: bool,
+    force: bool,
+}
+
+/// `rm -i` waiting for a yes/no answer before removing its next operand
+#[derive(Clone, Debug)]
+pub struct PendingRemoval {
+    operands: Vec<String>, // Operands still to go, the one asked about last
+    recursive: bool,
+    creds: Credentials,
+    failed: bool,
+    question: String, // Shown as the prompt until the answer arrives
+}
+
+/// rm - Remove files, or whole trees with -r, into the user's trash
+///
+/// -f ignores missing operands and -i asks before each operand (answers come
+/// from stdin when piped, otherwise from the next input line). `/`, top-level
+/// system directories and directories holding the working directory are
+/// refused. A tree is only removed if every node in it may be.
+fn handle_rm(
+    fs: &mut FileSystem,
+    term: &mut TerminalState,
+    creds: &Credentials,
+    args: &[&str],
+    stdin: Option<&str>,
+    now: f64,
+) -> CommandResponse {
+    let mut opts = RmOptions {
+        recursive: false,
+        force: false,
+    };
+    let mut interactive = false;
+    let mut operands = Vec::new();
+    let mut flags_done = false;
+    for arg in args {
+        match *arg {
+            _ if flags_done || !arg.starts_with('-') || *arg == "-" => operands.push(*arg),
+            "--" => flags_done = true,
+            "--recursive" => opts.recursive = true,
+            "--force" => (opts.force, interactive) = (true, false),
+            "--interactive" => (opts.force, interactive) = (false, true),
+            _ if arg.starts_with("--") => {
+                return CommandResponse::error(format!("rm: unrecognized option '{}'", arg));
+            }
+            _ => {
+                for flag in arg.chars().skip(1) {
+                    match flag {
+                        'r' | 'R' => opts.recursive = true,
+                        // The last of -f and -i wins
+                        'f' => (opts.force, interactive) = (true, false),
+                        'i' => (opts.force, interactive) = (false, true),
+                        _ => {
+                            return CommandResponse::error(format!(
+                                "rm: invalid option -- '{}'",
+                                flag
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if operands.is_empty() {
+        return if opts.force {
+            CommandResponse::empty()
+        } else {
+            CommandResponse::error("rm: missing operand")
+        };
+    }
+
+    if interactive {
+        let pending = PendingRemoval {
+            operands: operands.iter().rev().map(|operand| operand.to_string()).collect(),
+            recursive: opts.recursive,
+            creds: creds.clone(),
+            failed: false,
+            question: String::new(),
+        };
+        let mut answers = stdin.map(str::lines);
+        return rm_ask_next(fs, term, pending, answers.as_mut(), CommandResponse::empty(), now);
+    }
+
+    let mut notes = Vec::new();
+    let mut errors = Vec::new();
+    for operand in operands {
+        match rm_check(fs, creds, operand, opts) {
+            Ok(Some(path)) => match rm_discard(fs, creds, operand, &path, now) {
+                Ok(note) => notes.extend(note),
+                Err(message) => errors.push(message),
+            },
+            Ok(None) => {}
+            Err(message) => errors.push(message),
+        }
+    }
+    filter_response(notes.join("\n"), errors)
+}
+
+/// Checks that an `rm` operand may be removed, including everything below it
+///
+/// # Returns
+/// The operand's absolute path, `None` for a missing operand under -f, or
+/// the message to report
+fn rm_check(
+    fs: &FileSystem,
+    creds: &Credentials,
+    operand: &str,
+    opts: RmOptions,
+) -> Result<Option<String>, String> {
+    if matches!(basename(operand.trim_end_matches('/')), "." | "..") {
+        return Err(format!(
+            "rm: refusing to remove '.' or '..' directory: skipping '{}'",
+            operand
+        ));
+    }
+
+    let path = fs.resolve_path(operand);
+    let cannot = |err: FsError| format!("rm: cannot remove '{}': {}", operand, err);
+    let node = match fs.symlink_metadata(&path) {
+        Ok(node) => node,
+        Err(FsError::NotFound) if opts.force => return Ok(None),
+        Err(err) => return Err(cannot(err)),
+    };
+    if node.ftype != FileType::Directory {
+        fs.access_entry(&path, creds).map_err(cannot)?;
+        return Ok(Some(path));
+    }
+
+    if path == "/" && opts.recursive {
+        return Err("rm: it is dangerous to operate recursively on '/'".to_string());
+    }
+    if !opts.recursive {
+        return Err(cannot(FsError::IsADirectory));
+    }
+    if FileSystem::parent_path(&path).as_deref() == Some("/") {
+        return Err(format!("rm: refusing to remove system directory '{}'", operand));
+    }
+    let physical = fs.canonicalize(&path).map_err(cannot)?;
+    let cwd = &fs.current_path;
+    if [&path, &physical]
+        .iter()
+        .any(|dir| cwd == *dir || cwd.starts_with(&format!("{}/", dir)))
+    {
+        return Err(cannot(FsError::Busy));
+    }
+    fs.access_entry(&path, creds).map_err(cannot)?;
+
+    // Emptying a directory means reading it and unlinking every entry in it
+    let base = operand.trim_end_matches('/');
+    for sub in fs.walk(&physical).map_err(cannot)? {
+        let shown = format!("{}{}", base, &sub[physical.len()..]);
+        if sub != physical {
+            fs.access_entry(&sub, creds)
+                .map_err(|err| format!("rm: cannot remove '{}': {}", shown, err))?;
+        }
+        if fs.symlink_metadata(&sub).is_ok_and(|node| node.ftype == FileType::Directory) {
+            fs.access(&sub, creds, PERM_READ)
+                .map_err(|err| format!("rm: cannot open directory '{}': {}", shown, err))?;
+        }
+    }
+    Ok(Some(path))
+}
+
+/// Moves a checked `rm` operand into the user's trash
+///
+/// Nodes already in the trash, and nodes of users without a home, are
+/// deleted for good; so is a node whose trash directory does not fit on the
+/// disk, which is reported in the returned note.
+fn rm_discard(
+    fs: &mut FileSystem,
+    creds: &Credentials,
+    operand: &str,
+    path: &str,
+    now: f64,
+) -> Result<Option<String>, String> {
+    let cannot = |err: FsError| format!("rm: cannot remove '{}': {}", operand, err);
+    let physical = match FileSystem::parent_path(path) {
+        Some(parent) => join_path(&fs.canonicalize(&parent).map_err(cannot)?, basename(path)),
+        None => path.to_string(),
+    };
+    if trash::contains(fs, creds.uid, &physical) || trash::trash_dir(fs, creds.uid).is_none() {
+        return fs.delete_tree(path).map(|()| None).map_err(cannot);
+    }
+
+    match trash::put(fs, creds, path, now) {
+        Ok(()) => Ok(None),
+        Err(FsError::NoSpace) => {
+            fs.delete_tree(path).map_err(cannot)?;
+            Ok(Some(format!("rm: no room for the trash; '{}' removed permanently", operand)))
+        }
+        Err(err) => Err(cannot(err)),
+    }
+}
+
+/// Describes a node the way `rm -i` asks about it
+fn rm_kind(node: &FileNode) -> &'static str {
+    match node.ftype {
+        FileType::Directory => "directory",
+        FileType::Symlink => "symbolic link",
+        FileType::File if node.size() == 0 => "regular empty file",
+        FileType::File => "regular file",
+    }
+}
+
+/// Works through `rm -i` operands until one needs an answer
+///
+/// With `answers` (piped stdin) every question is answered from it and a
+/// missing line means no. Otherwise the question is parked in
+/// `term.pending_removal` and becomes the prompt for the next input line.
+fn rm_ask_next(
+    fs: &mut FileSystem,
+    term: &mut TerminalState,
+    mut pending: PendingRemoval,
+    mut answers: Option<&mut std::str::Lines>,
+    mut response: CommandResponse,
+    now: f64,
+) -> CommandResponse {
+    let opts = RmOptions {
+        recursive: pending.recursive,
+        force: false,
+    };
+    while let Some(operand) = pending.operands.last().cloned() {
+        let path = match rm_check(fs, &pending.creds, &operand, opts) {
+            Ok(Some(path)) => path,
+            Ok(None) => {
+                pending.operands.pop();
+                continue;
+            }
+            Err(message) => {
+                pending.operands.pop();
+                pending.failed = true;
+                response.append(CommandResponse::error(message));
+                continue;
+            }
+        };
+        let kind = fs.symlink_metadata(&path).map(rm_kind).unwrap_or("file");
+        pending.question = format!("rm: remove {} '{}'?", kind, operand);
+
+        let Some(lines) = answers.as_mut() else {
+            term.pending_removal = Some(pending);
+            response.status = 0;
+            return response;
+        };
+        response.append(CommandResponse::error(format!("{} ", pending.question)));
+        let yes = lines.next().is_some_and(is_yes);
+        pending.operands.pop();
+        if yes {
+            rm_answered(fs, &mut pending, &operand, &mut response, now);
+        }
+    }
+
+    response.status = i32::from(pending.failed);
+    response
+}
+
+/// Whether an answer to a question means yes
+fn is_yes(answer: &str) -> bool {
+    answer.trim_start().starts_with(['y', 'Y'])
+}
+
+/// Removes the operand an `rm -i` question was answered yes for
+fn rm_answered(
+    fs: &mut FileSystem,
+    pending: &mut PendingRemoval,
+    operand: &str,
+    response: &mut CommandResponse,
+    now: f64,
+) {
+    let opts = RmOptions {
+        recursive: pending.recursive,
+        force: false,
+    };
+    let removed = match rm_check(fs, &pending.creds, operand, opts) {
+        Ok(Some(path)) => rm_discard(fs, &pending.creds, operand, &path, now),
+        Ok(None) => Ok(None),
+        Err(message) => Err(message),
+    };
+    match removed {
+        Ok(note) => response.append(CommandResponse::ok(note.unwrap_or_default())),
+        Err(message) => {
+            pending.failed = true;
+            response.append(CommandResponse::error(message));
+        }
+    }
+}
+
+/// Handles the answer to a pending `rm -i` question
+///
+/// `y` or `Y` removes the operand; anything else keeps it. `interrupt()`
+/// abandons the remaining operands.
+fn handle_removal_answer(
+    fs: &mut FileSystem,
+    term: &mut TerminalState,
+    answer: &str,
+    now: f64,
+) -> CommandResponse {
+    let Some(mut pending) = term.pending_removal.take() else {
+        return CommandResponse::empty();
+    };
+
+    let mut response = CommandResponse::empty();
+    if let Some(operand) = pending.operands.pop()
+        && is_yes(answer)
+    {
+        rm_answered(fs, &mut pending, &operand, &mut response, now);
+    }
+    let response = rm_ask_next(fs, term, pending, None, response, now);
+    if term.pending_removal.is_none() {
+        term.last_status = response.status;
+    }
+    response
+}
+
+/// rmdir - Remove empty directories; -p also removes each emptied parent
+fn handle_rmdir(fs: &mut FileSystem, creds: &Credentials, args: &[&str]) -> CommandResponse {
+    let mut parents = false;
+    let mut operands = Vec::new();
+    for arg in args {
+        match *arg {
+            "-p" | "--parents" => parents = true,
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return CommandResponse::error(format!("rmdir: invalid option -- '{}'", arg));
+            }
+            _ => operands.push(*arg),
+        }
+    }
+    if operands.is_empty() {
+        return CommandResponse::error("rmdir: missing operand");
+    }
+
+    let mut errors = Vec::new();
+    for operand in operands {
+        let mut current = match operand.trim_end_matches('/') {
+            "" => "/",
+            trimmed => trimmed,
+        };
+        loop {
+            if let Err(message) = rmdir_one(fs, creds, current) {
+                errors.push(message);
+                break;
+            }
+            match current.rfind('/') {
+                Some(idx) if parents && idx > 0 => current = current[..idx].trim_end_matches('/'),
+                _ => break,
+            }
+        }
+    }
+    filter_response(String::new(), errors)
+}
+
+/// Removes one empty directory for `rmdir`
+fn rmdir_one(fs: &mut FileSystem, creds: &Credentials, operand: &str) -> Result<(), String> {
+    let failed = |err: FsError| format!("rmdir: failed to remove '{}': {}", operand, err);
+    if matches!(basename(operand), "." | "..") {
+        return Err(failed(FsError::InvalidPath));
+    }
+
+    let path = fs.resolve_path(operand);
+    match fs.symlink_metadata(&path) {
+        Ok(node) if node.ftype != FileType::Directory => return Err(failed(FsError::NotADirectory)),
+        Ok(_) => {}
+        Err(err) => return Err(failed(err)),
+    }
+    if path == "/" || path == fs.current_path {
+        return Err(failed(FsError::Busy));
+    }
+    if FileSystem::parent_path(&path).as_deref() == Some("/") {
+        return Err(format!("rmdir: refusing to remove system directory '{}'", operand));
+    }
+    fs.access_entry(&path, creds)
+        .and_then(|()| fs.delete(&path))
+        .map_err(failed)
+}
+
+/// trash - List, restore or permanently empty the user's trash
+///
+/// `trash restore` takes an original path or a name from the trash
+/// directory; when a path was removed more than once, the most recent
+/// removal comes back.
+fn handle_trash(fs: &mut FileSystem, creds: &Credentials, args: &[&str]) -> CommandResponse {
+    match args {
+        [] | ["list"] => {
+            let entries = trash::entries(fs, creds.uid);
+            if entries.is_empty() {
+                return CommandResponse::ok("Trash is empty");
+            }
+            let lines: Vec<String> = entries
+                .iter()
+                .map(|entry| {
+                    let time = clock::format_timestamp(entry.deleted_at);
+                    let suffix = if entry.is_dir { "/" } else { "" };
+                    format!("{}  {}{}", &time[..19], entry.original, suffix)
+                })
+                .collect();
+            CommandResponse::ok(lines.join("\n"))
+        }
+        ["restore", target] => trash_restore(fs, creds, target),
+        ["empty"] => {
+            let Some(dir) = trash::trash_dir(fs, creds.uid) else {
+                return CommandResponse::ok("Trash is empty");
+            };
+            let entries = trash::entries(fs, creds.uid);
+            if entries.is_empty() {
+                return CommandResponse::ok("Trash is empty");
+            }
+            for entry in &entries {
+                if let Err(err) = fs.delete_tree(&join_path(&dir, &entry.name)) {
+                    return fs_error(format!("trash: cannot remove '{}'", entry.original), err);
+                }
+            }
+            CommandResponse::ok(format!("Removed {} item(s) permanently", entries.len()))
+        }
+        _ => CommandResponse::error("Usage: trash [list | restore <path> | empty]"),
+    }
+}
+
+/// Moves a trashed node back to where it was removed from
+fn trash_restore(fs: &mut FileSystem, creds: &Credentials, target: &str) -> CommandResponse {
+    let Some(dir) = trash::trash_dir(fs, creds.uid) else {
+        return CommandResponse::error(format!("trash: '{}' is not in the trash", target));
+    };
+    let original = fs.resolve_path(target);
+    let Some(entry) = trash::entries(fs, creds.uid)
+        .into_iter()
+        .rev()
+        .find(|entry| entry.name == target || entry.original == original)
+    else {
+        return CommandResponse::error(format!("trash: '{}' is not in the trash", target));
+    };
+
+    let restored = if fs.lexists(&entry.original) {
+        Err(FsError::AlreadyExists)
+    } else {
+        fs.access_entry(&entry.original, creds)
+            .and_then(|()| fs.move_path(&join_path(&dir, &entry.name), &entry.original))
+    };
+    match restored {
+        Ok(()) => CommandResponse::ok(format!("Restored {}", entry.original)),
+        Err(err) => fs_error(format!("trash: cannot restore '{}'", entry.original), err),
+    }
+}
+
+/// ln - Create hard links, or symbolic links with -s
+fn handle_ln(
+    fs: &mut FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    now: f64,
+) -> CommandResponse {
+    let mut symbolic = false;
+    let mut force = false;
+    let mut operands = Vec::new();
+    for arg in args {
+        match *arg {
+            "-s" => symbolic = true,
+            "-f" => force = true,
+            "-sf" | "-fs" => {
+                symbolic = true;
+                force = true;
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return CommandResponse::error(format!("ln: invalid option -- '{}'", arg));
+            }
+            _ => operands.push(*arg),
+        }
+    }
+
+    let (target, link_arg) = match operands.as_slice() {
+        [target] => (*target, basename(target)),
+        [target, link] => (*target, *link),
+        _ => return CommandResponse::error("Usage: ln [-s] [-f] <target> [link_name]"),
+    };
+
+    // Like `ln target dir/`, a directory destination receives the link under the target's name
+    let mut link = fs.resolve_path(link_arg);
+    if fs.is_dir(&link) {
+        link = join_path(&link, basename(target));
+    }
+
+    if !symbolic {
+        let source = fs.resolve_path(target);
+        if let Err(err) = fs.symlink_metadata(&source) {
+            return fs_error(format!("ln: failed to access '{}'", target), err);
+        }
+    }
+
+    if fs.lexists(&link) {
+        if !force {
+            return fs_error(
+                format!("ln: failed to create link '{}'", link_arg),
+                FsError::AlreadyExists,
+            );
+        }
+        if fs.is_dir(&link) && !fs.is_symlink(&link) {
+            return CommandResponse::error(format!(
+                "ln: '{}': cannot overwrite directory",
+                link_arg
+            ));
+        }
+        let removed = fs
+            .access_entry(&link, creds)
+            .and_then(|()| fs.delete(&link));
+        if let Err(err) = removed {
+            return fs_error(format!("ln: cannot remove '{}'", link_arg), err);
+        }
+    }
+
+    // Symlinks store the target verbatim, relative targets included
+    let created = fs.access_entry(&link, creds).and_then(|()| {
+        if symbolic {
+            fs.create_symlink(&link, target, now)
+                .and_then(|()| fs.lchown(&link, Some(creds.uid), Some(creds.gid)))
+        } else {
+            fs.create_hard_link(&fs.resolve_path(target), &link)
+        }
+    });
+
+    match created {
+        Ok(()) => CommandResponse::empty(),
+        Err(FsError::NotPermitted) if !symbolic => {
+            CommandResponse::error(format!("ln: {}: hard link not allowed for directory", target))
+        }
+        Err(err) => fs_error(format!("ln: failed to create link '{}'", link_arg), err),
+    }
+}
+
+/// readlink - Print a symlink's target (-f: fully resolved path)
+fn handle_readlink(fs: &FileSystem, args: &[&str]) -> CommandResponse {
+    let (canonical, path) = match args {
+        ["-f", path] => (true, *path),
+        [path] => (false, *path),
+        _ => return CommandResponse::error("Usage: readlink [-f] <path>"),
+    };
+
+    let target = fs.resolve_path(path);
+    if canonical {
+        return match fs.canonicalize(&target) {
+            Ok(resolved) => CommandResponse::ok(resolved),
+            Err(err) => fs_error(format!("readlink: {}", path), err),
+        };
+    }
+
+    // Like GNU readlink, a path that is not a symlink fails silently
+    match fs.read_link(&target) {
+        Ok(link) => CommandResponse::ok(link),
+        Err(_) => CommandResponse {
+            status: 1,
+            ..CommandResponse::empty()
+        },
+    }
+}
+
+/// Applies an octal (`750`) or symbolic (`u+x,go-w`, `a=r`, `+t`) mode spec
+fn parse_mode(spec: &str, mode: u16, is_dir: bool) -> Option<u16> {
+    if !spec.is_empty() && spec.chars().all(|c| c.is_digit(8)) {
+        return u16::from_str_radix(spec, 8).ok().filter(|m| *m <= 0o7777);
+    }
+
+    let mut mode = mode;
+    for clause in spec.split(',') {
+        let op_start = clause.find(['+', '-', '='])?;
+        let (who, ops) = clause.split_at(op_start);
+
+        let mut who_mask = 0u16;
+        for c in who.chars() {
+            who_mask |= match c {
+                'u' => 0o4700,
+                'g' => 0o2070,
+                'o' => 0o1007,
+                'a' => 0o7777,
+                _ => return None,
+            };
+        }
+        if who.is_empty() {
+            who_mask = 0o7777;
+        }
+
+        let mut chars = ops.chars().peekable();
+        while let Some(op) = chars.next() {
+            let mut bits = 0u16;
+            while let Some(&c) = chars.peek() {
+                if matches!(c, '+' | '-' | '=') {
+                    break;
+                }
+                bits |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    'X' if is_dir || mode & 0o111 != 0 => 0o111,
+                    'X' => 0,
+                    's' => MODE_SETUID | MODE_SETGID,
+                    't' => MODE_STICKY,
+                    _ => return None,
+                };
+                chars.next();
+            }
+
+            let bits = bits & who_mask;
+            mode = match op {
+                '+' => mode | bits,
+                '-' => mode & !bits,
+                '=' => (mode & !who_mask) | bits,
+                _ => return None,
+            };
+        }
+    }
+
+    Some(mode)
+}
+
+/// Splits a leading `-R` flag off chmod/chown/chgrp arguments
+fn split_recursive<'a>(args: &'a [&'a str]) -> (bool, &'a [&'a str]) {
+    match args.first() {
+        Some(&"-R") => (true, &args[1..]),
+        _ => (false, args),
+    }
+}
+
+/// Resolves targets for chmod/chown/chgrp, expanding them recursively with -R
+fn change_targets(
+    fs: &FileSystem,
+    cmd: &str,
+    files: &[&str],
+    recursive: bool,
+) -> Result<Vec<String>, CommandResponse> {
+    let mut targets = Vec::new();
+    for file in files {
+        let path = fs.resolve_path(file);
+        let expanded = if recursive {
+            fs.walk(&path)
+        } else {
+            fs.metadata(&path).map(|_| vec![path])
+        };
+        match expanded {
+            Ok(paths) => targets.extend(paths),
+            Err(err) => return Err(fs_error(format!("{}: cannot access '{}'", cmd, file), err)),
+        }
+    }
+    Ok(targets)
+}
+
+/// chmod - Change permission bits (owner or root only)
+fn handle_chmod(
+    fs: &mut FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    now: f64,
+) -> CommandResponse {
+    let (recursive, args) = split_recursive(args);
+    if args.len() < 2 {
+        return CommandResponse::error(
+            "Usage: chmod [-R] <mode> <file>...\nExample: chmod u+x,go-w script.sh",
+        );
+    }
+
+    let targets = match change_targets(fs, "chmod", &args[1..], recursive) {
+        Ok(targets) => targets,
+        Err(response) => return response,
+    };
+
+    for path in targets {
+        let Ok(node) = fs.metadata(&path) else {
+            continue;
+        };
+        if !creds.is_root() && node.uid != creds.uid {
+            return CommandResponse::error(format!(
+                "chmod: changing permissions of '{}': Operation not permitted",
+                path
+            ));
+        }
+
+        let is_dir = node.ftype == FileType::Directory;
+        match parse_mode(args[0], node.mode, is_dir) {
+            Some(mode) => {
+                let changed = fs
+                    .set_mode(&path, mode)
+                    .and_then(|()| fs.mark_changed(&path, true, now));
+                if let Err(err) = changed {
+                    return fs_error(format!("chmod: changing permissions of '{}'", path), err);
+                }
+            }
+            None => {
+                return CommandResponse::error(format!("chmod: invalid mode: '{}'", args[0]));
+            }
+        }
+    }
+
+    CommandResponse::empty()
+}
+
+/// chown - Change owner and optionally group (root only)
+fn handle_chown(
+    fs: &mut FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    now: f64,
+) -> CommandResponse {
+    let (recursive, args) = split_recursive(args);
+    if args.len() < 2 {
+        return CommandResponse::error("Usage: chown [-R] <owner>[:<group>] <file>...");
+    }
+
+    let (user_spec, group_spec) = match args[0].split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (args[0], None),
+    };
+
+    let uid = if user_spec.is_empty() {
+        None
+    } else {
+        match users::resolve_user(fs, user_spec) {
+            Some(uid) => Some(uid),
+            None => return CommandResponse::error(format!("chown: invalid user: '{}'", args[0])),
+        }
+    };
+    let gid = match group_spec {
+        // "user:" means the user's login group from /etc/passwd
+        Some("") => match uid.map(|uid| users::account_by_uid(fs, uid)) {
+            Some(Some(account)) => Some(account.gid),
+            Some(None) => {
+                return CommandResponse::error(format!("chown: invalid spec: '{}'", args[0]));
+            }
+            None => None,
+        },
+        Some(group) => match users::resolve_group(fs, group) {
+            Some(gid) => Some(gid),
+            None => return CommandResponse::error(format!("chown: invalid group: '{}'", args[0])),
+        },
+        None => None,
+    };
+
+    let targets = match change_targets(fs, "chown", &args[1..], recursive) {
+        Ok(targets) => targets,
+        Err(response) => return response,
+    };
+
+    for path in targets {
+        if let Err(response) = check_ownership_change(fs, creds, "chown", &path, uid, gid) {
+            return response;
+        }
+        let changed = fs
+            .set_owner(&path, uid, gid)
+            .and_then(|()| fs.mark_changed(&path, true, now));
+        if let Err(err) = changed {
+            return fs_error(format!("chown: changing ownership of '{}'", path), err);
+        }
+    }
+
+    CommandResponse::empty()
+}
+
+/// chgrp - Change group (owner who belongs to the group, or root)
+fn handle_chgrp(
+    fs: &mut FileSystem,
+    creds: &Credentials,
+    args: &[&str],
+    now: f64,
+) -> CommandResponse {
+    let (recursive, args) = split_recursive(args);
+    if args.len() < 2 {
+        return CommandResponse::error("Usage: chgrp [-R] <group> <file>...");
+    }
+
+    let Some(gid) = users::resolve_group(fs, args[0]) else {
+        return CommandResponse::error(format!("chgrp: invalid group: '{}'", args[0]));
+    };
+
+    let targets = match change_targets(fs, "chgrp", &args[1..], recursive) {
+        Ok(targets) => targets,
+        Err(response) => return response,
+    };
+
+    for path in targets {
+        if let Err(response) = check_ownership_change(fs, creds, "chgrp", &path, None, Some(gid)) {
+            return response;
+        }
+        let changed = fs
+            .set_owner(&path, None, Some(gid))
+            .and_then(|()| fs.mark_changed(&path, true, now));
+        if let Err(err) = changed {
+            return fs_error(format!("chgrp: changing group of '{}'", path), err);
+        }
+    }
+
+    CommandResponse::empty()
+}
+
+/// Only root may give files away; owners may only move them between their own groups
+fn check_ownership_change(
+    fs: &FileSystem,
+    creds: &Credentials,
+    cmd: &str,
+    path: &str,
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> Result<(), CommandResponse> {
+    if creds.is_root() {
+        return Ok(());
+    }
+
+    let node_uid = fs.metadata(path).ok().map(|node| node.uid);
+    let keeps_owner = uid.is_none() || uid == node_uid;
+    let allowed_group = gid.is_none_or(|gid| creds.in_group(gid));
+
+    if node_uid == Some(creds.uid) && keeps_owner && allowed_group {
+        Ok(())
+    } else {
+        Err(fs_error(
+            format!("{}: changing ownership of '{}'", cmd, path),
+            FsError::NotPermitted,
+        ))
+    }
+}
+
+/// umask - Show or set the file creation mask
+fn handle_umask(term: &mut TerminalState, args: &[&str]) -> CommandResponse {
+    let Some(spec) = args.first() else {
+        return CommandResponse::ok(format!("{:04o}", term.umask));
+    };
+
+    match u16::from_str_radix(spec, 8) {
+        Ok(mask) if mask <= 0o777 => {
+            term.umask = mask;
+            CommandResponse::empty()
+        }
+        _ => CommandResponse::error(format!("umask: {}: octal number out of range", spec)),
+    }
+}
+
+/// date - Display current date/time
+fn handle_date(now: f64) -> CommandResponse {
+    CommandResponse::ok(clock::format_date(now))
+}
+
+/// echo - Print arguments
+fn handle_echo(args: &[&str]) -> CommandResponse {
+    CommandResponse::ok(args.join(" "))
+}
+
+/// export - Set shell variables (`export NAME=value` or `export NAME`)
+fn handle_export(term: &mut TerminalState, args: &[&str]) -> CommandResponse {
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (*arg, None),
+        };
+
+        if !shell::is_valid_name(name) {
+            return CommandResponse::error(format!(
+                "export: `{}': not a valid identifier",
+                arg
+            ));
+        }
+
+        match value {
+            Some(value) => {
+                term.env.insert(name.to_string(), value.to_string());
+            }
+            None => {
+                term.env.entry(name.to_string()).or_default();
+            }
+        }
+    }
+
+    CommandResponse::empty()
+}
+
+/// unset - Remove shell variables
+fn handle_unset(term: &mut TerminalState, args: &[&str]) -> CommandResponse {
+    for name in args {
+        term.env.remove(*name);
+    }
+    CommandResponse::empty()
+}
+
+/// env - Print shell variables
+fn handle_env(fs: &FileSystem, term: &TerminalState) -> CommandResponse {
+    let mut vars: Vec<String> = term
+        .env
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    vars.push(format!("PWD={}", fs.current_path));
+    vars.sort();
+    CommandResponse::ok(vars.join("\n"))
+}
+
+/// whoami - Display current user (encrypted)
+fn handle_whoami(fs: &FileSystem, creds: &Credentials) -> CommandResponse {
+    CommandResponse::ok(users::user_name(fs, creds.uid))
+}
+
+/// Primary group followed by supplementary groups, without duplicates
+fn all_groups(creds: &Credentials) -> Vec<u32> {
+    let mut gids = vec![creds.gid];
+    gids.extend(creds.groups.iter().filter(|gid| **gid != creds.gid));
+    gids
+}
+
+/// Credentials of the session, or of the account named in `args`
+fn identity_for(
+    fs: &FileSystem,
+    creds: &Credentials,
+    cmd: &str,
+    args: &[&str],
+) -> Result<Credentials, CommandResponse> {
+    match args.first() {
+        Some(name) => users::find_account(fs, name)
+            .map(|account| users::credentials_for(fs, &account))
+            .ok_or_else(|| CommandResponse::error(format!("{}: '{}': no such user", cmd, name))),
+        None => Ok(creds.clone()),
+    }
+}
+
+/// id - Print user and group ids
+fn handle_id(fs: &FileSystem, creds: &Credentials, args: &[&str]) -> CommandResponse {
+    let creds = match identity_for(fs, creds, "id", args) {
+        Ok(creds) => creds,
+        Err(response) => return response,
+    };
+
+    let groups: Vec<String> = all_groups(&creds)
+        .into_iter()
+        .map(|gid| format!("{}({})", gid, users::group_name(fs, gid)))
+        .collect();
+    CommandResponse::ok(format!(
+        "uid={}({}) gid={}({}) groups={}",
+        creds.uid,
+        users::user_name(fs, creds.uid),
+        creds.gid,
+        users::group_name(fs, creds.gid),
+        groups.join(",")
+    ))
+}
+
+/// groups - Print group memberships
+fn handle_groups(fs: &FileSystem, creds: &Credentials, args: &[&str]) -> CommandResponse {
+    let member = match identity_for(fs, creds, "groups", args) {
+        Ok(creds) => creds,
+        Err(response) => return response,
+    };
+
+    let names: Vec<String> = all_groups(&member)
+        .into_iter()
+        .map(|gid| users::group_name(fs, gid))
+        .collect();
+    match args.first() {
+        Some(name) => CommandResponse::ok(format!("{} : {}", name, names.join(" "))),
+        None => CommandResponse::ok(names.join(" ")),
+    }
+}
+
+/// Switches the session to `account`, updating identity variables
+///
+/// Login sessions (`su -`, `login`) also move to the account's home directory.
+fn start_session(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    account: &Account,
+    login: bool,
+) {
+    term.user = users::credentials_for(fs, account);
+    for key in ["USER", "LOGNAME"] {
+        term.env.insert(key.to_string(), account.name.clone());
+    }
+    term.env.insert("HOME".to_string(), account.home.clone());
+    term.env.insert("SHELL".to_string(), account.shell.clone());
+
+    let home_ok = fs.is_dir(&account.home) && fs.access(&account.home, &term.user, PERM_EXEC).is_ok();
+    if login && home_ok {
+        fs.current_path = account.home.clone();
+    }
+
+    // Cached sudo credentials belong to the previous identity
+    sudo.invalidate();
+}
+
+/// Saves the current identity so `exit` can return to it, then switches to `account`
+fn push_session(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    account: &Account,
+    login: bool,
+) {
+    term.sessions.push(Session {
+        user: term.user.clone(),
+        cwd: fs.current_path.clone(),
+        env: term.env.clone(),
+    });
+    start_session(fs, sudo, term, account, login);
+}
+
+/// Looks up an account that may start an interactive session
+fn login_account(fs: &FileSystem, cmd: &str, name: &str) -> Result<Account, CommandResponse> {
+    let Some(account) = users::find_account(fs, name) else {
+        return Err(CommandResponse::error(format!("{}: user {} does not exist", cmd, name)));
+    };
+    if !account.can_login() {
+        return Err(CommandResponse::error("This account is currently not available."));
+    }
+    Ok(account)
+}
+
+/// su - Switch user (root by default; `su -` starts a login session)
+fn handle_su(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    args: &[&str],
+) -> CommandResponse {
+    let mut login = false;
+    let mut name = "root";
+    for arg in args {
+        match *arg {
+            "-" | "-l" | "--login" => login = true,
+            _ if arg.starts_with('-') => {
+                return CommandResponse::error(format!("su: invalid option -- '{}'", arg));
+            }
+            _ => name = arg,
+        }
+    }
+
+    let account = match login_account(fs, "su", name) {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+
+    // Root may become anyone without a password
+    if credentials(sudo, term).is_root() || !users::requires_password(fs, &account.name) {
+        push_session(fs, sudo, term, &account, login);
+        return CommandResponse::empty();
+    }
+
+    term.pending_auth = Some(PendingAuth::Su {
+        account: account.name,
+        login,
+    });
+    CommandResponse::effect(hidden_prompt("Password: "))
+}
+
+/// login - Replace the whole session with another account
+fn handle_login(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    args: &[&str],
+) -> CommandResponse {
+    let Some(name) = args.first() else {
+        return CommandResponse::error("Usage: login <user>");
+    };
+
+    let account = match login_account(fs, "login", name) {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+
+    if !users::requires_password(fs, &account.name) {
+        term.sessions.clear();
+        start_session(fs, sudo, term, &account, true);
+        return CommandResponse::empty();
+    }
+
+    term.pending_auth = Some(PendingAuth::Login {
+        account: account.name,
+    });
+    CommandResponse::effect(hidden_prompt("Password: "))
+}
+
+/// logout / exit - Return to the identity that ran `su`
+///
+/// Leaving a `login` session falls back to the player's account.
+fn handle_logout(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    cmd: &str,
+) -> CommandResponse {
+    if let Some(session) = term.sessions.pop() {
+        term.user = session.user;
+        term.env = session.env;
+        if fs.is_dir(&session.cwd) {
+            fs.current_path = session.cwd;
+        }
+        sudo.invalidate();
+        return CommandResponse::ok("logout");
+    }
+
+    if term.user != users::player_credentials()
+        && let Some(account) = users::find_account(fs, &users::player_name())
+    {
+        start_session(fs, sudo, term, &account, true);
+        return CommandResponse::ok("logout");
+    }
+
+    CommandResponse::error(format!("{}: not in a nested session", cmd))
+}
+
+/// passwd - Change an account password (others' only as root)
+fn handle_passwd(
+    fs: &FileSystem,
+    term: &mut TerminalState,
+    creds: &Credentials,
+    args: &[&str],
+) -> CommandResponse {
+    let current = users::user_name(fs, creds.uid);
+    let name = args.first().map(|name| name.to_string()).unwrap_or(current.clone());
+
+    if users::find_account(fs, &name).is_none() {
+        return CommandResponse::error(format!("passwd: user '{}' does not exist", name));
+    }
+    if !creds.is_root() && name != current {
+        return CommandResponse::error(format!(
+            "passwd: You may not view or modify password information for {}.",
+            name
+        ));
+    }
+
+    let (stage, prompt) = if creds.is_root() || !users::requires_password(fs, &name) {
+        (PasswdStage::New, "New password: ")
+    } else {
+        (PasswdStage::Current, "Current password: ")
+    };
+    let message = format!("Changing password for {}.", name);
+    term.pending_auth = Some(PendingAuth::Passwd {
+        account: name,
+        stage,
+    });
+    CommandResponse::ok(message).with_effect(hidden_prompt(prompt))
+}
+
+/// Handles the line typed at a su/login/passwd password prompt
+fn handle_auth_password(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+    password: &str,
+    now: f64,
+) -> CommandResponse {
+    let Some(pending) = term.pending_auth.take() else {
+        return CommandResponse::empty();
+    };
+
+    let response = match pending {
+        PendingAuth::Su { account, login } => match users::find_account(fs, &account) {
+            Some(account) if users::check_password(fs, &account.name, password) => {
+                push_session(fs, sudo, term, &account, login);
+                CommandResponse::empty()
+            }
+            _ => CommandResponse::error("su: Authentication failure"),
+        },
+        PendingAuth::Login { account } => match users::find_account(fs, &account) {
+            Some(account) if users::check_password(fs, &account.name, password) => {
+                term.sessions.clear();
+                start_session(fs, sudo, term, &account, true);
+                CommandResponse::empty()
+            }
+            _ => CommandResponse::error("Login incorrect"),
+        },
+        PendingAuth::Passwd { account, stage } => {
+            let unchanged =
+                "passwd: Authentication token manipulation error\npasswd: password unchanged";
+            match stage {
+                PasswdStage::Current if users::check_password(fs, &account, password) => {
+                    term.pending_auth = Some(PendingAuth::Passwd {
+                        account,
+                        stage: PasswdStage::New,
+                    });
+                    CommandResponse::effect(hidden_prompt("New password: "))
+                }
+                PasswdStage::Current => CommandResponse::error(unchanged),
+                PasswdStage::New if password.is_empty() => CommandResponse::error(
+                    "No password has been supplied.\npasswd: password unchanged",
+                ),
+                PasswdStage::New => {
+                    term.pending_auth = Some(PendingAuth::Passwd {
+                        account,
+                        stage: PasswdStage::Retype(password.to_string()),
+                    });
+                    CommandResponse::effect(hidden_prompt("Retype new password: "))
+                }
+                PasswdStage::Retype(first) if first == password => {
+                    match users::set_password(fs, &account, password, now) {
+                        Ok(()) => CommandResponse::ok("passwd: password updated successfully"),
+                        Err(err) => CommandResponse {
+                            status: err.status(),
+                            ..CommandResponse::error(format!("passwd: {}\n{}", err, unchanged))
+                        },
+                    }
+                }
+                PasswdStage::Retype(_) => {
+                    CommandResponse::error(format!("Sorry, passwords do not match.\n{}", unchanged))
+                }
+            }
+        }
+    };
+
+    term.last_status = response.status;
+    response
+}
+
+/// history - Show command history from backend session
+fn handle_history(term: &TerminalState) -> CommandResponse {
+    if term.history.is_empty() {
+        return CommandResponse::ok("No history yet");
+    }
+
+    CommandResponse::ok(
+        term.history
+            .iter()
+            .enumerate()
+            .map(|(i, cmd)| format!("{}  {}", i + 1, cmd))
+            .collect::<Vec<String>>()
+            .join("\n"),
+    )
+}
+
+/// theme - Set terminal theme (frontend applies, backend validates)
+fn handle_theme(term: &mut TerminalState, args: &[&str]) -> CommandResponse {
+    if args.is_empty() {
+        return CommandResponse::error(format!(
+            "Usage: theme <name>\nAvailable themes: {}",
+            THEMES.join(", ")
+        ));
+    }
+
+    let name = args[0];
+    if !THEMES.contains(&name) {
+        return CommandResponse::error(format!(
+            "theme: unknown theme '{}'. Available themes: {}",
+            name,
+            THEMES.join(", ")
+        ));
+    }
+
+    term.theme = name.to_string();
+    CommandResponse::effect(Effect::Theme {
+        name: name.to_string(),
+    })
+}
+
+/// reboot - Reset terminal state (filesystem, sudo, history, theme)
+fn handle_reboot(
+    fs: &mut FileSystem,
+    sudo: &mut SudoState,
+    term: &mut TerminalState,
+) -> CommandResponse {
+    // The machine resets; the player's solves and the screen do not
+    let progress = std::mem::take(&mut term.progress);
+    let columns = term.columns;
+    *fs = FileSystem::new();
+    fs.capacity = term.config.disk_capacity;
+    *sudo = SudoState::with_config(sudo.config.clone());
+    *term = TerminalState::with_config(term.config.clone());
+    term.progress = progress;
+    term.columns = columns;
+    CommandResponse::effect(Effect::Reboot)
+}
+
+/// submit - Check a flag (argument or piped input) against the challenge registry
+fn handle_submit(
+    term: &mut TerminalState,
+    args: &[&str],
+    stdin: Option<&str>,
+    now: f64,
+) -> CommandResponse {
+    let flag = if args.is_empty() {
+        stdin.unwrap_or("").trim().to_string()
+    } else {
+        args.join(" ")
+    };
+    if flag.is_empty() {
+        return CommandResponse::error("Usage: submit <flag>  (quote flags containing spaces or #)");
+    }
+
+    match term.progress.submit(&flag, now) {
+        Submission::Solved(challenge) => {
+            let solve_time = term
+                .progress
+                .solve(challenge.id)
+                .map(|solve| term.progress.solve_time(solve))
+                .unwrap_or(0.0);
+            CommandResponse::ok(format!(
+                "Correct! '{}' solved (+{} points) after {}\n{}",
+                challenge.name,
+                challenge.points,
+                scoring::format_duration(solve_time),
+                score_line(term)
+            ))
+        }
+        Submission::AlreadySolved(challenge) => CommandResponse::ok(format!(
+            "'{}' is already solved\n{}",
+            challenge.name,
+            score_line(term)
+        )),
+        Submission::Incorrect => CommandResponse::error("submit: incorrect flag"),
+    }
+}
+
+fn score_line(term: &TerminalState) -> String {
+    format!(
+        "Score: {}/{} points ({}/{} challenges solved)",
+        term.progress.score(),
+        scoring::max_score(),
+        term.progress.solves.len(),
+        CHALLENGES.len()
+    )
+}
+
+/// score - One-line score summary
+fn handle_score(term: &TerminalState) -> CommandResponse {
+    CommandResponse::ok(score_line(term))
+}
+
+/// hint - List hint availability, or reveal a challenge's next hint tier
+///
+/// Tiers are revealed in order; a tier may wait for a delay after the session
+/// starts and may cost points, which requires `--buy`.
+fn handle_hint(term: &mut TerminalState, args: &[&str], now: f64) -> CommandResponse {
+    let buy = args.contains(&"--buy");
+    let ids: Vec<&str> = args.iter().copied().filter(|arg| *arg != "--buy").collect();
+
+    let id = match ids.as_slice() {
+        [] => {
+            if CHALLENGES.is_empty() {
+                return CommandResponse::ok("No challenges in this pack");
+            }
+            let width = CHALLENGES.iter().map(|c| c.id.len()).max().unwrap_or(0);
+            let mut lines: Vec<String> = CHALLENGES
+                .iter()
+                .map(|challenge| {
+                    format!(
+                        "{:<width$}  {}/{} hints  {}",
+                        challenge.id,
+                        term.progress.hints_revealed(challenge.id),
+                        challenge.hints.len(),
+                        challenge.name,
+                        width = width
+                    )
+                })
+                .collect();
+            lines.push("Usage: hint <challenge> [--buy]".to_string());
+            return CommandResponse::ok(lines.join("\n"));
+        }
+        [id] => *id,
+        _ => return CommandResponse::error("Usage: hint [challenge] [--buy]"),
+    };
+
+    let Some(challenge) = scoring::find_challenge(id) else {
+        return CommandResponse::error(format!(
+            "hint: unknown challenge '{}' (run 'hint' for the list)",
+            id
+        ));
+    };
+    if challenge.hints.is_empty() {
+        return CommandResponse::ok(format!("No hints for '{}'", challenge.name));
+    }
+
+    let outcome = term.progress.reveal_hint(challenge, buy, now);
+    let total = challenge.hints.len();
+    let revealed = term.progress.hints_revealed(challenge.id);
+    let mut lines: Vec<String> = challenge.hints[..revealed]
+        .iter()
+        .enumerate()
+        .map(|(tier, hint)| format!("[{}/{}] {}", tier + 1, total, hint.reveal()))
+        .collect();
+
+    match outcome {
+        HintReveal::Revealed if challenge.hints[revealed - 1].cost > 0 => lines.push(format!(
+            "(-{} points) {}",
+            challenge.hints[revealed - 1].cost,
+            score_line(term)
+        )),
+        HintReveal::Revealed => {}
+        HintReveal::Exhausted => lines.push(format!("No more hints for '{}'", challenge.name)),
+        HintReveal::Locked(remaining) => lines.push(format!(
+            "Hint {}/{} unlocks in {}",
+            revealed + 1,
+            total,
+            format_minutes(remaining)
+        )),
+        HintReveal::NeedsPurchase(cost) => lines.push(format!(
+            "Hint {}/{} costs {} points: run 'hint {} --buy'",
+            revealed + 1,
+            total,
+            cost,
+            challenge.id
+        )),
+    }
+    CommandResponse::ok(lines.join("\n"))
+}
+
+/// progress - Every challenge with its points and solve time
+fn handle_progress(term: &TerminalState) -> CommandResponse {
+    if CHALLENGES.is_empty() {
+        return CommandResponse::ok("No challenges in this pack");
+    }
+
+    let width = CHALLENGES.iter().map(|c| c.name.chars().count()).max().unwrap_or(0);
+    let mut lines: Vec<String> = CHALLENGES
+        .iter()
+        .map(|challenge| {
+            let status = match term.progress.solve(challenge.id) {
+                Some(solve) => format!(
+                    "solved after {}",
+                    scoring::format_duration(term.progress.solve_time(solve))
+                ),
+                None => challenge.description.to_string(),
+            };
+            format!(
+                "[{}] {:<width$}  {:>4}  {}",
+                if term.progress.is_solved(challenge.id) { 'x' } else { ' ' },
+                challenge.name,
+                challenge.points,
+                status,
+                width = width
+            )
+        })
+        .collect();
+    lines.push(score_line(term));
+    CommandResponse::ok(lines.join("\n"))
+}
+
+/// help - Display available commands
+fn handle_help() -> CommandResponse {
+    CommandResponse::ok(
+        [
+            "Available commands:",
+            "ls      - List directory contents",
+            "cd      - Change current directory",
+            "pwd     - Show current directory",
+            "cat     - Display file content",
+            "grep    - Search files for a pattern (-i -v -n -r -l -c -E -A/-B/-C)",
+            "find    - Search for files (-name -type -size -perm -user -exec ...)",
+            "head    - Show the first lines of a file (-n N, -c N)",
+            "tail    - Show the last lines of a file (-n N, -n +N, -c N)",
+            "wc      - Count lines, words and bytes (-l -w -c -m)",
+            "sort    - Sort lines (-n -r -u -k N[,M] -t SEP)",
+            "uniq    - Collapse repeated lines (-c -d -u -i)",
+            "cut     - Select fields or characters (-d SEP -f LIST, -c LIST)",
+            "tr      - Translate, delete (-d) or squeeze (-s) characters",
+            "rev     - Reverse each line",
+            "stat    - Show size, mode, owner, inode and times (-L follows links)",
+            "du      - Disk usage of directory trees (-h, -s, --max-depth=N)",
+            "df      - Size and free space of the virtual disk (-h)",
+            "tree    - Draw a directory tree (-a hidden entries, -L depth)",
+            "diff    - Compare files or trees (-u unified, -q brief, -r recursive)",
+            "patch   - Apply a unified diff (patch [-pN] [-R] < changes.diff)",
+            "mkdir   - Create a directory",
+            "touch   - Create an empty file",
+            "cp      - Copy file or directory",
+            "mv      - Move or rename file/directory",
+            "rm      - Move files to the trash (-r trees, -f force, -i ask)",
+            "rmdir   - Remove empty directories (-p parents too)",
+            "trash   - List, restore or empty removed files",
+            "ln      - Create a hard link (ln -s: symbolic link)",
+            "readlink - Show a symlink target (-f: resolve fully)",
+            "chmod   - Change permission bits (octal or u+x style)",
+            "chown   - Change file owner and group (root)",
+            "chgrp   - Change file group",
+            "umask   - Show or set the file creation mask",
+            "date    - Show current date/time",
+            "echo    - Print text",
+            "true    - Do nothing, successfully (false: unsuccessfully)",
+            "export  - Set a shell variable (NAME=value)",
+            "unset   - Remove a shell variable",
+            "env     - List shell variables",
+            "whoami  - Show current user",
+            "id      - Show user and group ids (id [user])",
+            "groups  - Show group memberships",
+            "su      - Switch user (su [-] [user]; exit to return)",
+            "login   - Start a new session as another user",
+            "logout  - Leave the current su/login session",
+            "passwd  - Change a password",
+            "sudo    - Run a command as root (sudo -k to drop privileges)",
+            "history - Show command history",
+            "theme   - Change terminal theme",
+            "reboot  - Reset terminal state",
+            "submit  - Submit a flag (submit 'CSE{...}')",
+            "score   - Show your score",
+            "progress - List challenges and solve times",
+            "hint    - Reveal the next hint for a challenge (hint [challenge] [--buy])",
+            "downld  - Download a file",
+            "clear   - Clear terminal output",
+        ]
+        .join("\n"),
+    )
+}
+
+/// downld - Download file (returns base64 encoded content)
+fn handle_download(fs: &FileSystem, creds: &Credentials, args: &[&str]) -> CommandResponse {
+    if args.is_empty() {
+        return CommandResponse::error("Usage: download <filename>");
+    }
+
+    let target = fs.resolve_path(args[0]);
+    let content = match fs
+        .access(&target, creds, PERM_READ)
+        .and_then(|()| fs.read_file(&target))
+    {
+        Ok(content) => content,
+        Err(err) => return fs_error(format!("download: {}", args[0]), err),
+    };
+
+    CommandResponse::effect(Effect::Download {
+        filename: basename(args[0]).to_string(),
+        data: base64_encode(&content),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+
+    const NOW: f64 = 1_700_000_000_000.0;
+
+    /// A fresh machine whose player account has the password "pw"
+    struct Shell {
+        fs: FileSystem,
+        sudo: SudoState,
+        term: TerminalState,
+        now: f64, // Milliseconds, advanced by tests that need time to pass
+    }
+
+    impl Shell {
+        fn new() -> Shell {
+            let mut fs = FileSystem::new();
+            users::set_password(&mut fs, &users::player_name(), "pw", NOW).expect("player exists");
+            Shell {
+                fs,
+                sudo: SudoState::new(),
+                term: TerminalState::new(),
+                now: NOW,
+            }
+        }
+
+        fn run(&mut self, line: &str) -> CommandResponse {
+            let clock = FixedClock(self.now);
+            execute_command(&mut self.fs, &mut self.sudo, &mut self.term, &clock, line)
+        }
+
+        fn stdout(&mut self, line: &str) -> String {
+            self.run(line).stdout
+        }
+    }
+
+    #[test]
+    fn sudo_prompt_parks_the_rest_of_the_list() {
+        let mut shell = Shell::new();
+        let asked = shell.run("sudo echo hi; echo after && false || echo fallback");
+        assert!(matches!(asked.effects[..], [Effect::PasswordPrompt { .. }]));
+        assert_eq!(asked.stdout, "");
+
+        let done = shell.run("pw");
+        assert_eq!(done.stdout, "hi\nafter\nfallback");
+        assert_eq!(done.status, 0);
+        assert_eq!(shell.stdout("echo $?"), "0");
+    }
+
+    #[test]
+    fn sudo_prompt_keeps_stdin_redirects_and_pipeline() {
+        let mut shell = Shell::new();
+        shell.run("echo hello | sudo cat > /tmp/out");
+        assert_eq!(shell.run("pw").stdout, "");
+        assert_eq!(shell.stdout("cat /tmp/out"), "hello");
+
+        shell.run("sudo -k");
+        shell.run("echo b > /tmp/in; echo a >> /tmp/in");
+        shell.run("sudo cat < /tmp/in | sort | head -n 1");
+        assert_eq!(shell.run("pw").stdout, "a");
+    }
+
+    #[test]
+    fn failed_sudo_continues_with_its_status() {
+        let mut shell = Shell::new();
+        shell.run("sudo echo secret > /tmp/never && echo no || echo status $?");
+        shell.run("wrong");
+        shell.run("wrong");
+        let done = shell.run("wrong");
+        assert_eq!(done.stdout, "status 1");
+        assert!(done.stderr.contains("3 incorrect password attempts"));
+        assert!(done.effects.is_empty());
+        // The shell opened the redirect target before sudo ran
+        assert_eq!(shell.stdout("cat /tmp/never"), "");
+    }
+
+    #[test]
+    fn parked_group_keeps_its_subshell_until_it_ends() {
+        let mut shell = Shell::new();
+        shell.run("cd /tmp");
+        shell.run("(echo start; cd /etc; X=1; sudo pwd; echo x=$X) > /tmp/group; pwd; echo [$X]");
+        // Output from before the prompt already went to the file
+        assert_eq!(shell.stdout("pw"), "/tmp\n[]");
+        assert_eq!(shell.stdout("cat /tmp/group"), "start\n/etc\nx=1");
+    }
+
+    #[test]
+    fn interrupt_drops_the_rest_and_leaves_the_group() {
+        let mut shell = Shell::new();
+        shell.run("cd /tmp");
+        shell.run("(cd /etc; X=1; sudo true; touch /tmp/later)");
+        interrupt(&mut shell.fs, &mut shell.sudo, &mut shell.term);
+        assert_eq!(shell.fs.current_path, "/tmp");
+        assert_eq!(shell.stdout("echo $? [$X]"), "130 []");
+        assert!(!shell.fs.exists("/tmp/later"));
+    }
+
+    #[test]
+    fn ls_operands_need_search_permission_on_their_parents() {
+        let mut shell = Shell::new();
+        shell.run("mkdir /tmp/d; touch /tmp/d/f; chmod 000 /tmp/d");
+        let response = shell.run("ls -l /tmp/d/f");
+        assert_eq!(response.stdout, "");
+        assert_eq!(response.stderr, "ls: cannot access '/tmp/d/f': Permission denied");
+        assert_eq!(response.status, 2);
+
+        let response = shell.run("ls /root/nope");
+        assert_eq!(response.stderr, "ls: cannot access '/root/nope': Permission denied");
+    }
+
+    #[test]
+    fn ls_unreadable_subdirectory_is_a_minor_failure() {
+        let mut shell = Shell::new();
+        shell.run("mkdir /tmp/top; mkdir /tmp/top/shut; chmod 000 /tmp/top/shut");
+        let response = shell.run("ls -R /tmp/top");
+        assert_eq!(response.stdout, "/tmp/top:\nshut/\n\n/tmp/top/shut:");
+        assert_eq!(response.status, 1);
+
+        assert_eq!(shell.run("ls /tmp/top/shut").status, 2);
+    }
+
+    #[test]
+    fn sudo_session_lasts_until_its_timeout() {
+        let mut shell = Shell::new();
+        shell.run("sudo true");
+        shell.run("pw");
+        assert!(shell.run("sudo whoami").effects.is_empty());
+
+        shell.now += 4.0 * 60_000.0;
+        assert_eq!(shell.stdout("sudo whoami"), "root");
+
+        shell.now += 60_000.0;
+        let asked = shell.run("sudo whoami");
+        assert!(matches!(asked.effects[..], [Effect::PasswordPrompt { .. }]));
+        assert_eq!(shell.stdout("pw"), "root");
+    }
+
+    #[test]
+    fn sudo_session_counts_commands_when_limited() {
+        let mut shell = Shell::new();
+        shell.sudo.config.max_commands = Some(2);
+        shell.run("sudo true");
+        shell.run("pw");
+        assert_eq!(shell.stdout("sudo whoami"), "root");
+        assert!(!shell.run("sudo whoami").effects.is_empty());
+    }
+
+    #[test]
+    fn sudo_locks_out_after_failed_attempts() {
+        let mut shell = Shell::new();
+        shell.run("sudo whoami");
+        assert_eq!(shell.run("nope").stderr, "Sorry, try again.");
+        shell.run("nope");
+        assert_eq!(shell.run("nope").stderr, "sudo: 3 incorrect password attempts");
+
+        shell.now += 30_000.0;
+        let locked = shell.run("sudo whoami");
+        assert!(locked.stderr.starts_with("sudo: account locked after too many failed attempts"));
+        assert!(locked.effects.is_empty());
+        assert!(!shell.sudo.waiting_for_password);
+
+        shell.now += 30_000.0;
+        shell.run("sudo whoami");
+        assert_eq!(shell.stdout("pw"), "root");
+        assert_eq!(shell.sudo.failed_attempts, 0);
+    }
+
+    #[test]
+    fn sudo_k_forgets_the_session() {
+        let mut shell = Shell::new();
+        shell.run("sudo true");
+        shell.run("pw");
+        shell.run("sudo -k");
+        assert!(!shell.run("sudo whoami").effects.is_empty());
+    }
+
+    #[test]
+    fn parse_mode_takes_octal_modes() {
+        assert_eq!(parse_mode("755", 0o600, false), Some(0o755));
+        assert_eq!(parse_mode("0644", 0o777, true), Some(0o644));
+        assert_eq!(parse_mode("4755", 0, false), Some(0o4755));
+        assert_eq!(parse_mode("17777", 0, false), None);
+        assert_eq!(parse_mode("758", 0, false), None);
+    }
+
+    #[test]
+    fn parse_mode_applies_symbolic_clauses() {
+        assert_eq!(parse_mode("u+x", 0o644, false), Some(0o744));
+        assert_eq!(parse_mode("go-r", 0o644, false), Some(0o600));
+        assert_eq!(parse_mode("+x", 0o644, false), Some(0o755));
+        assert_eq!(parse_mode("o=", 0o777, false), Some(0o770));
+        assert_eq!(parse_mode("u=rw,g-w,o+r", 0o660, false), Some(0o644));
+        assert_eq!(parse_mode("u+s,g+s", 0o755, false), Some(0o6755));
+        assert_eq!(parse_mode("+t", 0o777, true), Some(0o1777));
+        // X only grants search on directories or already executable files
+        assert_eq!(parse_mode("a+X", 0o644, false), Some(0o644));
+        assert_eq!(parse_mode("a+X", 0o644, true), Some(0o755));
+        assert_eq!(parse_mode("a+X", 0o744, false), Some(0o755));
+
+        assert_eq!(parse_mode("z+x", 0o644, false), None);
+        assert_eq!(parse_mode("u+q", 0o644, false), None);
+        assert_eq!(parse_mode("u", 0o644, false), None);
+    }
+
+    #[test]
+    fn only_root_gives_files_away() {
+        let mut fs = FileSystem::new();
+        let player = users::player_credentials();
+        fs.write_file("/tmp/f", b"", Cipher::Plain, NOW).unwrap();
+        fs.set_owner("/tmp/f", Some(player.uid), Some(player.gid)).unwrap();
+        let change = |creds: &Credentials, uid, gid| {
+            check_ownership_change(&fs, creds, "chown", "/tmp/f", uid, gid).is_ok()
+        };
+
+        assert!(change(&player, Some(player.uid), None));
+        assert!(!change(&player, Some(0), None));
+        // An owner may move a file into any group they belong to
+        assert!(change(&player, None, Some(users::SUDO_GID)));
+        assert!(!change(&player, None, Some(0)));
+        assert!(change(&Credentials::root(), Some(0), Some(0)));
+
+        let stranger = Credentials {
+            uid: 1001,
+            gid: player.gid,
+            groups: Vec::new(),
+        };
+        assert!(!change(&stranger, None, Some(player.gid)));
+    }
+
+    #[test]
+    fn setgid_directory_passes_its_group_on() {
+        let mut shell = Shell::new();
+        shell.run("mkdir /tmp/team; chgrp sudo /tmp/team; chmod g+s /tmp/team");
+        shell.run("touch /tmp/team/f; mkdir /tmp/team/d; touch /tmp/plain");
+
+        let gid = |path| shell.fs.metadata(path).unwrap().gid;
+        assert_eq!(gid("/tmp/team/f"), users::SUDO_GID);
+        assert_eq!(gid("/tmp/team/d"), users::SUDO_GID);
+        assert_eq!(gid("/tmp/plain"), users::player_credentials().gid);
+    }
+
+    #[test]
+    fn sticky_tmp_keeps_other_users_files() {
+        let mut shell = Shell::new();
+        shell.fs.write_file("/tmp/roots", b"", Cipher::Plain, NOW).unwrap();
+        shell.fs.set_mode("/tmp/roots", 0o666).unwrap();
+        let response = shell.run("rm /tmp/roots");
+        assert_eq!(response.stderr, "rm: cannot remove '/tmp/roots': Operation not permitted");
+        assert!(shell.fs.exists("/tmp/roots"));
+    }
+
+    #[test]
+    fn trash_restores_the_latest_removal() {
+        let mut shell = Shell::new();
+        shell.run("echo old > /tmp/a; rm /tmp/a");
+        shell.now += 1000.0;
+        shell.run("echo new > /tmp/a; rm /tmp/a");
+
+        assert_eq!(shell.stdout("trash restore /tmp/a"), "Restored /tmp/a");
+        assert_eq!(shell.stdout("cat /tmp/a"), "new");
+        let taken = shell.run("trash restore /tmp/a");
+        assert_eq!(taken.stderr, "trash: cannot restore '/tmp/a': File exists");
+
+        shell.run("rm /tmp/a");
+        assert_eq!(shell.stdout("trash restore %2Ftmp%2Fa"), "Restored /tmp/a");
+        assert_eq!(shell.stdout("cat /tmp/a"), "old");
+        assert_eq!(shell.run("trash restore /tmp/b").stderr, "trash: '/tmp/b' is not in the trash");
+    }
+
+    #[test]
+    fn full_disk_reports_no_space() {
+        let mut shell = Shell::new();
+        shell.fs.capacity = shell.fs.usage() + BLOCK_SIZE;
+        assert_eq!(shell.run("echo hi > /tmp/a").status, 0);
+        let response = shell.run("cp /tmp/a /tmp/b");
+        assert!(response.stderr.ends_with("No space left on device"), "{}", response.stderr);
+        assert_eq!(response.status, 1);
+        assert!(!shell.fs.lexists("/tmp/b"));
+    }
+
+    #[test]
+    fn head_and_tail_bytes_keep_characters_whole() {
+        let mut shell = Shell::new();
+        shell.fs.write_file("/tmp/t", "añb".as_bytes(), Cipher::Plain, NOW).unwrap();
+        assert_eq!(shell.stdout("head -c 2 /tmp/t"), "a");
+        assert_eq!(shell.stdout("head -c 3 /tmp/t"), "añ");
+        assert_eq!(shell.stdout("head -c 99 /tmp/t"), "añb");
+        assert_eq!(shell.stdout("head -c -2 /tmp/t"), "a");
+        assert_eq!(shell.stdout("tail -c 2 /tmp/t"), "b");
+        assert_eq!(shell.stdout("tail -c 3 /tmp/t"), "ñb");
+        assert_eq!(shell.stdout("tail -c +3 /tmp/t"), "b");
+        assert_eq!(shell.stdout("tail -c 99 /tmp/t"), "añb");
+    }
+
+    #[test]
+    fn find_perm_rejects_a_multibyte_mode() {
+        let mut shell = Shell::new();
+        let response = shell.run("find /tmp -perm é");
+        assert_eq!(response.stderr, "find: invalid mode `é'");
+        assert_eq!(response.status, 1);
+    }
+
+    #[test]
+    fn second_prompt_parks_what_is_still_left() {
+        let mut shell = Shell::new();
+        shell.run("touch /tmp/a");
+        shell.run("sudo echo one; rm -i /tmp/a; echo three");
+        let asked = shell.run("pw");
+        assert_eq!(asked.stdout, "one");
+        assert!(prompt(&shell.fs, &shell.sudo, &shell.term).starts_with("rm: remove"));
+
+        assert_eq!(shell.stdout("y"), "three");
+        assert!(!shell.fs.exists("/tmp/a"));
     }
 }
@@ -0,0 +1,615 @@
+//! Diff module
+//! Line diffs with Myers' O(ND) algorithm, rendered as unified or normal
+//! (`diff` without flags) output, plus parsing and applying unified patches
+//!
+//! Lines keep their `\n`, so a last line without one differs from the same
+//! text with one and is rendered with a `\ No newline at end of file` marker.
+
+/// Marker following a line that has no `\n`
+const NO_NEWLINE: &str = "\\ No newline at end of file";
+
+/// Splits text into lines that keep their terminators
+pub fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Appends a tagged line without its `\n`, adding the marker when it has none
+fn push_line(out: &mut Vec<String>, prefix: &str, line: &str) {
+    match line.strip_suffix('\n') {
+        Some(text) => out.push(format!("{}{}", prefix, text)),
+        None => {
+            out.push(format!("{}{}", prefix, line));
+            out.push(NO_NEWLINE.to_string());
+        }
+    }
+}
+
+/// One step of an edit script turning the old lines into the new ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edit {
+    /// Old line `.0` is kept as new line `.1`
+    Keep(usize, usize),
+    /// Old line is removed
+    Delete(usize),
+    /// New line is added
+    Insert(usize),
+}
+
+/// A run of edits with its position in both files (0-based line indexes)
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub edits: Vec<Edit>,
+}
+
+/// Computes a shortest edit script between two line lists
+///
+/// Common prefixes and suffixes are stripped before running Myers'
+/// algorithm; within each changed run, deletions come before insertions.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|idx| Edit::Keep(idx, idx)).collect();
+    let middle = myers(
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    edits.extend(middle.into_iter().map(|edit| match edit {
+        Edit::Keep(a, b) => Edit::Keep(a + prefix, b + prefix),
+        Edit::Delete(a) => Edit::Delete(a + prefix),
+        Edit::Insert(b) => Edit::Insert(b + prefix),
+    }));
+    edits.extend(
+        (0..suffix).map(|idx| Edit::Keep(old.len() - suffix + idx, new.len() - suffix + idx)),
+    );
+
+    // Deletions first within each change, like GNU diff
+    let mut ordered = Vec::with_capacity(edits.len());
+    let mut inserts = Vec::new();
+    for edit in edits {
+        match edit {
+            Edit::Insert(_) => inserts.push(edit),
+            Edit::Delete(_) => ordered.push(edit),
+            Edit::Keep(..) => {
+                ordered.append(&mut inserts);
+                ordered.push(edit);
+            }
+        }
+    }
+    ordered.append(&mut inserts);
+    ordered
+}
+
+/// Myers' greedy algorithm, recording each round's furthest reaching paths for the backtrack
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    if n == 0 && m == 0 {
+        return Vec::new();
+    }
+    let offset = n + m;
+    let mut v = vec![0isize; 2 * (n + m) as usize + 2];
+    let mut trace = Vec::new();
+
+    // x on diagonal k (x - y = k) is stored at v[k + offset]
+    let from_below = |v: &[isize], k: isize, d: isize| {
+        k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize])
+    };
+    'search: for d in 0..=n + m {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if from_below(&v, k, d) {
+                v[(k + 1 + offset) as usize]
+            } else {
+                v[(k - 1 + offset) as usize] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(k + offset) as usize] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k = if from_below(v, k, d) { k + 1 } else { k - 1 };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Keep(x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == prev_x {
+                y -= 1;
+                edits.push(Edit::Insert(y as usize));
+            } else {
+                x -= 1;
+                edits.push(Edit::Delete(x as usize));
+            }
+        }
+    }
+    edits.reverse();
+    edits
+}
+
+/// Groups an edit script into hunks with `context` unchanged lines around each change
+///
+/// Changes closer than twice the context share a hunk; with a context of 0
+/// every run of changes is its own hunk, as normal diff output needs.
+pub fn hunks(edits: &[Edit], context: usize) -> Vec<Hunk> {
+    // Line positions in both files before each edit
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for edit in edits {
+        positions.push((old_pos, new_pos));
+        match edit {
+            Edit::Keep(..) => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Edit::Delete(_) => old_pos += 1,
+            Edit::Insert(_) => new_pos += 1,
+        }
+    }
+    positions.push((old_pos, new_pos));
+
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (idx, edit) in edits.iter().enumerate() {
+        if matches!(edit, Edit::Keep(..)) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(last) if idx <= last.1 + 2 * context => last.1 = idx + 1,
+            _ => ranges.push((idx, idx + 1)),
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let start = start.saturating_sub(context);
+            let end = (end + context).min(edits.len());
+            let (old_start, new_start) = positions[start];
+            let (old_end, new_end) = positions[end];
+            Hunk {
+                old_start,
+                old_len: old_end - old_start,
+                new_start,
+                new_len: new_end - new_start,
+                edits: edits[start..end].to_vec(),
+            }
+        })
+        .collect()
+}
+
+/// Renders hunks in unified format (`@@ -1,3 +1,4 @@` followed by ` `/`-`/`+` lines)
+pub fn unified(old: &[&str], new: &[&str], hunks: &[Hunk]) -> Vec<String> {
+    // Empty ranges name the line before them; a length of 1 is implied
+    let range = |start: usize, len: usize| match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    };
+
+    let mut out = Vec::new();
+    for hunk in hunks {
+        out.push(format!(
+            "@@ -{} +{} @@",
+            range(hunk.old_start, hunk.old_len),
+            range(hunk.new_start, hunk.new_len)
+        ));
+        for edit in &hunk.edits {
+            match *edit {
+                Edit::Keep(a, _) => push_line(&mut out, " ", old[a]),
+                Edit::Delete(a) => push_line(&mut out, "-", old[a]),
+                Edit::Insert(b) => push_line(&mut out, "+", new[b]),
+            }
+        }
+    }
+    out
+}
+
+/// Renders context-free hunks in normal format (`2,3c2`, `< old`, `---`, `> new`)
+pub fn normal(old: &[&str], new: &[&str], hunks: &[Hunk]) -> Vec<String> {
+    let range = |start: usize, len: usize| match len {
+        0 => format!("{}", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, start + len),
+    };
+
+    let mut out = Vec::new();
+    for hunk in hunks {
+        let command = match (hunk.old_len, hunk.new_len) {
+            (_, 0) => 'd',
+            (0, _) => 'a',
+            _ => 'c',
+        };
+        out.push(format!(
+            "{}{}{}",
+            range(hunk.old_start, hunk.old_len),
+            command,
+            range(hunk.new_start, hunk.new_len)
+        ));
+        for edit in &hunk.edits {
+            if let Edit::Delete(a) = *edit {
+                push_line(&mut out, "< ", old[a]);
+            }
+        }
+        if command == 'c' {
+            out.push("---".to_string());
+        }
+        for edit in &hunk.edits {
+            if let Edit::Insert(b) = *edit {
+                push_line(&mut out, "> ", new[b]);
+            }
+        }
+    }
+    out
+}
+
+/// A hunk read from a unified patch; starts are 1-based as written in the `@@` line
+#[derive(Clone, Debug)]
+pub struct PatchHunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    /// Lines tagged `' '`, `'-'` or `'+'`, each ending in `\n` unless the
+    /// patch marks it as having none
+    pub lines: Vec<(char, String)>,
+}
+
+impl PatchHunk {
+    /// The hunk undone: additions become deletions and the ranges swap
+    pub fn reversed(&self) -> PatchHunk {
+        PatchHunk {
+            old_start: self.new_start,
+            old_len: self.new_len,
+            new_start: self.old_start,
+            new_len: self.old_len,
+            lines: self
+                .lines
+                .iter()
+                .map(|(tag, line)| {
+                    let tag = match tag {
+                        '-' => '+',
+                        '+' => '-',
+                        other => *other,
+                    };
+                    (tag, line.clone())
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The changes to one file in a unified patch
+#[derive(Clone, Debug)]
+pub struct FilePatch {
+    /// Name on the `---` line, without its timestamp
+    pub old_path: String,
+    /// Name on the `+++` line, without its timestamp
+    pub new_path: String,
+    pub hunks: Vec<PatchHunk>,
+}
+
+/// Parses every file section of a unified diff
+///
+/// Lines outside sections (`diff ...` commands, `Only in`, prose) are ignored.
+pub fn parse_unified(text: &str) -> Result<Vec<FilePatch>, String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let header_path = |line: &str, marker: &str| {
+        let name = &line[marker.len()..];
+        name.split('\t').next().unwrap_or(name).trim_end().to_string()
+    };
+
+    let mut patches: Vec<FilePatch> = Vec::new();
+    let mut idx = 0;
+    while idx < lines.len() {
+        let line = lines[idx];
+        let has_new_header = lines.get(idx + 1).is_some_and(|next| next.starts_with("+++ "));
+        if line.starts_with("--- ") && has_new_header {
+            patches.push(FilePatch {
+                old_path: header_path(line, "--- "),
+                new_path: header_path(lines[idx + 1], "+++ "),
+                hunks: Vec::new(),
+            });
+            idx += 2;
+            continue;
+        }
+        if !line.starts_with("@@ ") {
+            idx += 1;
+            continue;
+        }
+
+        let Some(patch) = patches.last_mut() else {
+            return Err(format!("hunk without file header at line {}", idx + 1));
+        };
+        let mut hunk = parse_hunk_header(line)
+            .ok_or_else(|| format!("malformed hunk header at line {}: {}", idx + 1, line))?;
+        idx += 1;
+
+        let (mut old_seen, mut new_seen) = (0, 0);
+        while old_seen < hunk.old_len || new_seen < hunk.new_len {
+            let Some(body) = lines.get(idx) else {
+                return Err(format!("unexpected end of patch in hunk at line {}", idx));
+            };
+            let mut chars = body.chars();
+            // Editors often strip the space from empty context lines
+            let tag = chars.next().unwrap_or(' ');
+            let rest = chars.as_str().to_string();
+            match tag {
+                ' ' => {
+                    old_seen += 1;
+                    new_seen += 1;
+                }
+                '-' => old_seen += 1,
+                '+' => new_seen += 1,
+                _ => return Err(format!("malformed patch at line {}: {}", idx + 1, body)),
+            }
+            idx += 1;
+            let no_newline = lines.get(idx).is_some_and(|next| next.starts_with('\\'));
+            if no_newline {
+                idx += 1;
+            }
+            hunk.lines.push((tag, if no_newline { rest } else { rest + "\n" }));
+        }
+        if old_seen > hunk.old_len || new_seen > hunk.new_len {
+            return Err(format!("malformed patch at line {}: hunk is too long", idx));
+        }
+        patch.hunks.push(hunk);
+    }
+
+    if patches.iter().all(|patch| patch.hunks.is_empty()) {
+        return Err("Only garbage was found in the patch input.".to_string());
+    }
+    Ok(patches)
+}
+
+/// Parses `@@ -a,b +c,d @@` (counts default to 1)
+fn parse_hunk_header(line: &str) -> Option<PatchHunk> {
+    let inner = line.strip_prefix("@@ -")?;
+    let (ranges, _) = inner.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let range = |text: &str| -> Option<(usize, usize)> {
+        match text.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((text.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_len) = range(old)?;
+    let (new_start, new_len) = range(new)?;
+    Some(PatchHunk {
+        old_start,
+        old_len,
+        new_start,
+        new_len,
+        lines: Vec::new(),
+    })
+}
+
+/// Result of applying one hunk
+pub enum HunkResult {
+    /// Applied where the header said
+    Applied,
+    /// Applied after moving by this many lines
+    Offset(isize),
+    /// The hunk's old lines were not found; the line it expected
+    Failed(usize),
+}
+
+/// Applies hunks in order, moving a hunk when its context sits at another line
+///
+/// # Returns
+/// The patched lines (hunks that failed are left out) and one result per hunk
+pub fn apply(lines: &[String], hunks: &[PatchHunk]) -> (Vec<String>, Vec<HunkResult>) {
+    let mut output = lines.to_vec();
+    let mut results = Vec::new();
+    // Net lines added by earlier hunks, and the first line later hunks may touch
+    let mut shift: isize = 0;
+    let mut floor = 0;
+
+    for hunk in hunks {
+        let old: Vec<&str> = hunk
+            .lines
+            .iter()
+            .filter(|(tag, _)| *tag != '+')
+            .map(|(_, line)| line.as_str())
+            .collect();
+        let new: Vec<String> = hunk
+            .lines
+            .iter()
+            .filter(|(tag, _)| *tag != '-')
+            .map(|(_, line)| line.clone())
+            .collect();
+
+        // An empty old range names the line before the insertion point
+        let stated = if hunk.old_len == 0 {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+        let expected = (stated as isize + shift).max(0) as usize;
+
+        // Less context on one side than the other means the hunk sits at that end of the file
+        let leading = hunk.lines.iter().take_while(|(tag, _)| *tag == ' ').count();
+        let trailing = hunk.lines.iter().rev().take_while(|(tag, _)| *tag == ' ').count();
+        let at_start = trailing > leading;
+        let at_end = leading > trailing;
+        let fits = |at: usize| {
+            at >= floor
+                && (!at_start || at == 0)
+                && (!at_end || at + old.len() == output.len())
+                && at + old.len() <= output.len()
+                && output[at..at + old.len()].iter().zip(&old).all(|(have, want)| have == want)
+        };
+
+        // Search outwards from the expected line
+        let limit = output.len().max(expected) + 1;
+        let found = (0..=limit).find_map(|distance| {
+            [expected.checked_add(distance), expected.checked_sub(distance)]
+                .into_iter()
+                .flatten()
+                .find(|at| fits(*at))
+        });
+
+        match found {
+            Some(at) => {
+                output.splice(at..at + old.len(), new.iter().cloned());
+                let moved = at as isize - expected as isize;
+                results.push(if moved == 0 {
+                    HunkResult::Applied
+                } else {
+                    HunkResult::Offset(moved)
+                });
+                shift += moved + new.len() as isize - old.len() as isize;
+                floor = at + new.len();
+            }
+            None => results.push(HunkResult::Failed(expected + 1)),
+        }
+    }
+    (output, results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unified diff of two texts, with file headers so it parses back as a patch
+    fn unified_patch(old: &str, new: &str) -> String {
+        let (old_lines, new_lines) = (lines(old), lines(new));
+        let hunks = hunks(&diff(&old_lines, &new_lines), 3);
+        let mut out = vec!["--- old".to_string(), "+++ new".to_string()];
+        out.extend(unified(&old_lines, &new_lines, &hunks));
+        out.join("\n") + "\n"
+    }
+
+    /// Applies a patch to a text, failing the test if any hunk is rejected
+    fn patch(text: &str, patch: &str, reverse: bool) -> String {
+        let parsed = parse_unified(patch).expect("patch parses");
+        let mut hunks = parsed[0].hunks.clone();
+        if reverse {
+            hunks = hunks.iter().map(PatchHunk::reversed).collect();
+        }
+        let original: Vec<String> = lines(text).into_iter().map(str::to_string).collect();
+        let (patched, results) = apply(&original, &hunks);
+        assert!(results.iter().all(|result| !matches!(result, HunkResult::Failed(_))));
+        patched.concat()
+    }
+
+    fn round_trip(old: &str, new: &str) {
+        let patch_text = unified_patch(old, new);
+        assert_eq!(patch(old, &patch_text, false), new);
+        assert_eq!(patch(new, &patch_text, true), old);
+    }
+
+    #[test]
+    fn identical_input_has_no_hunks() {
+        let text = lines("a\nb\nc\n");
+        let edits = diff(&text, &text);
+        assert!(edits.iter().all(|edit| matches!(edit, Edit::Keep(..))));
+        assert!(hunks(&edits, 3).is_empty());
+        assert!(diff::<&str>(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn empty_side() {
+        let text = lines("a\nb\n");
+        let added = hunks(&diff(&[], &text), 3);
+        assert_eq!(unified(&[], &text, &added), ["@@ -0,0 +1,2 @@", "+a", "+b"]);
+        assert_eq!(normal(&[], &text, &hunks(&diff(&[], &text), 0)), ["0a1,2", "> a", "> b"]);
+
+        let removed = hunks(&diff(&text, &[]), 3);
+        assert_eq!(unified(&text, &[], &removed), ["@@ -1,2 +0,0 @@", "-a", "-b"]);
+        assert_eq!(normal(&text, &[], &hunks(&diff(&text, &[]), 0)), ["1,2d0", "< a", "< b"]);
+
+        round_trip("", "a\nb\n");
+        round_trip("a\nb\n", "");
+    }
+
+    #[test]
+    fn normal_format() {
+        let (old, new) = (lines("a\nb\nc\nd\n"), lines("a\nx\nc\nd\ne\n"));
+        let hunks = hunks(&diff(&old, &new), 0);
+        assert_eq!(normal(&old, &new, &hunks), ["2c2", "< b", "---", "> x", "4a5", "> e"]);
+    }
+
+    #[test]
+    fn missing_trailing_newline() {
+        let (old, new) = (lines("a\nb"), lines("a\nb\n"));
+        let hunks = hunks(&diff(&old, &new), 3);
+        assert_eq!(
+            unified(&old, &new, &hunks),
+            ["@@ -1,2 +1,2 @@", " a", "-b", "\\ No newline at end of file", "+b"]
+        );
+
+        round_trip("a\nb", "a\nb\n");
+        round_trip("a\nb\n", "a\nc");
+        round_trip("x", "y");
+    }
+
+    #[test]
+    fn reverse_round_trip() {
+        let old: String = (1..=20).map(|n| format!("{}\n", n)).collect();
+        let new = format!("0\n{}", old.replace("\n3\n", "\nthree\n").replace("\n17\n", "\n"));
+        let patch_text = unified_patch(&old, &new);
+        assert_eq!(parse_unified(&patch_text).expect("patch parses")[0].hunks.len(), 2);
+        round_trip(&old, &new);
+    }
+
+    #[test]
+    fn hunk_moves_by_offset() {
+        let old = "a\nb\nc\nd\ne\n";
+        let patch_text = unified_patch(old, "a\nb\nC\nd\ne\n");
+
+        // Two lines were added at the top since the patch was made
+        let shifted: Vec<String> = lines("y\nz\na\nb\nc\nd\ne\n")
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        let hunks = &parse_unified(&patch_text).expect("patch parses")[0].hunks;
+        let (patched, results) = apply(&shifted, hunks);
+        assert!(matches!(results[..], [HunkResult::Offset(2)]));
+        assert_eq!(patched.concat(), "y\nz\na\nb\nC\nd\ne\n");
+    }
+
+    #[test]
+    fn mismatched_hunk_is_rejected() {
+        let patch_text = unified_patch("a\nb\nc\n", "a\nB\nc\n");
+        let other: Vec<String> = lines("a\nx\nc\n").into_iter().map(str::to_string).collect();
+        let hunks = &parse_unified(&patch_text).expect("patch parses")[0].hunks;
+        let (patched, results) = apply(&other, hunks);
+        assert!(matches!(results[..], [HunkResult::Failed(1)]));
+        assert_eq!(patched, other);
+
+        // Only the missing newline differs, which is enough to reject the hunk
+        let no_newline: Vec<String> = lines("a\nb\nc").into_iter().map(str::to_string).collect();
+        let (_, results) = apply(&no_newline, hunks);
+        assert!(matches!(results[..], [HunkResult::Failed(_)]));
+    }
+
+    #[test]
+    fn malformed_patches() {
+        assert!(parse_unified("no patch here\n").is_err());
+        assert!(parse_unified("@@ -1 +1 @@\n-a\n+b\n").is_err());
+        assert!(parse_unified("--- a\n+++ b\n@@ -1,2 +1,2 @@\n-a\n+b\n").is_err());
+        assert!(parse_unified("--- a\n+++ b\n@@ -1 +1 @@\n?a\n+b\n").is_err());
+    }
+}
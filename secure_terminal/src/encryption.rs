@@ -1,42 +1,197 @@
-/// Encryption module for handling data encoding/decoding operations
-/// Uses XOR cipher with a fixed secret key for simple obfuscation
+//! Encryption module for handling data encoding/decoding operations
+//! Each file picks a cipher: XOR for intentionally weak puzzles,
+//! AES-256-GCM for protected content, or plain storage for raw assets
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 
 const SECRET_KEY: u64 = 144935935482u64;
 
-/// Decrypts XOR-encoded data to a UTF-8 string
+/// Passphrase used when `SECURE_TERMINAL_PASSPHRASE` is not set at build time
+const DEFAULT_PASSPHRASE: &str = "cse23-secure-terminal";
+const KDF_SALT: &[u8] = b"secure_terminal/fs-key/v1";
+const KDF_ROUNDS: u32 = 10_000;
+const NONCE_LEN: usize = 12;
+
+/// Prefix of stored secret hashes: `$pbkdf2-sha256$<salt>$<hex hash>`
+const HASH_SCHEME: &str = "$pbkdf2-sha256$";
+
+lazy_static! {
+    /// Filesystem key, derived once from the build-time passphrase
+    static ref FS_KEY: [u8; 32] =
+        derive_key(option_env!("SECURE_TERMINAL_PASSPHRASE").unwrap_or(DEFAULT_PASSPHRASE));
+}
+
+/// Cipher used to store a file's content
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum Cipher {
+    /// Stored as-is (binary assets such as images)
+    Plain,
+    /// Repeating 8-byte XOR with `SECRET_KEY` - recoverable by design
+    Xor,
+    /// AES-256-GCM with a random per-file nonce prefixed to the ciphertext
+    #[default]
+    Aes,
+}
+
+impl Cipher {
+    /// Encrypts raw bytes
+    ///
+    /// # Arguments
+    /// * `plaintext` - Bytes to encrypt
+    ///
+    /// # Returns
+    /// Encrypted bytes in this cipher's storage format
+    pub fn encrypt(self, plaintext: &[u8]) -> Vec<u8> {
+        match self {
+            Cipher::Plain => plaintext.to_vec(),
+            Cipher::Xor => xor(plaintext),
+            Cipher::Aes => aes_encrypt(&FS_KEY, plaintext),
+        }
+    }
+
+    /// Decrypts bytes produced by `encrypt`
+    ///
+    /// # Arguments
+    /// * `data` - Encrypted bytes
+    ///
+    /// # Returns
+    /// Plaintext bytes, or `None` if authentication fails
+    pub fn decrypt(self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Cipher::Plain => Some(data.to_vec()),
+            Cipher::Xor => Some(xor(data)),
+            Cipher::Aes => aes_decrypt(&FS_KEY, data),
+        }
+    }
+
+    /// Decrypts data to a UTF-8 string, or "??" if decryption or conversion fails
+    pub fn decode(self, data: &[u8]) -> String {
+        self.decrypt(data)
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .unwrap_or_else(|| "??".to_string())
+    }
+}
+
+/// Derives a 256-bit key from a passphrase (PBKDF2-HMAC-SHA256)
 ///
 /// # Arguments
-/// * `data` - Byte slice containing XOR-encrypted data
+/// * `passphrase` - Secret passphrase
 ///
 /// # Returns
-/// Decrypted string, or "??" if UTF-8 conversion fails
-pub fn decode(data: &[u8]) -> String {
-    let key_bytes = SECRET_KEY.to_le_bytes();
-    let decoded: Vec<u8> = data
-        .iter()
-        .enumerate()
-        .map(|(i, &b)| b ^ key_bytes[i % key_bytes.len()])
-        .collect();
-    String::from_utf8(decoded).unwrap_or_else(|_| "??".to_string())
+/// 32-byte AES key
+pub fn derive_key(passphrase: &str) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), KDF_SALT, KDF_ROUNDS, &mut key);
+    key
 }
 
-/// Encrypts a string using XOR cipher
+/// Hashes a password with a salt (PBKDF2-HMAC-SHA256)
 ///
 /// # Arguments
-/// * `text` - String to encrypt
+/// * `password` - Password to hash
+/// * `salt` - Per-credential salt
 ///
 /// # Returns
-/// Vector of encrypted bytes
-#[allow(dead_code)]
-pub fn encode(text: &str) -> Vec<u8> {
-    let key_bytes = SECRET_KEY.to_le_bytes();
-    text.as_bytes()
+/// 32-byte password hash
+pub fn hash_password(password: &str, salt: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, KDF_ROUNDS, &mut hash);
+    hash
+}
+
+/// Verifies a password against a stored hash without early exit on mismatch
+pub fn verify_password(password: &str, salt: &[u8], expected: &[u8; 32]) -> bool {
+    let actual = hash_password(password, salt);
+    actual
         .iter()
+        .zip(expected.iter())
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+/// Formats a salted hash as stored in /etc/shadow and challenge packs
+///
+/// # Returns
+/// `$pbkdf2-sha256$<salt>$<hex hash>`
+pub fn hash_string(secret: &str, salt: &str) -> String {
+    let hash = hash_password(secret, salt.as_bytes());
+    format!("{}{}${}", HASH_SCHEME, salt, encode_hex(&hash))
+}
+
+/// Checks a secret against a `hash_string` value; malformed hashes never match
+pub fn verify_hash_string(secret: &str, stored: &str) -> bool {
+    match split_hash_string(stored) {
+        Some((salt, expected)) => verify_password(secret, salt.as_bytes(), &expected),
+        None => false,
+    }
+}
+
+/// Whether `stored` is a well-formed `hash_string` value
+pub fn is_hash_string(stored: &str) -> bool {
+    split_hash_string(stored).is_some_and(|(salt, _)| !salt.is_empty())
+}
+
+fn split_hash_string(stored: &str) -> Option<(&str, [u8; 32])> {
+    let (salt, hex) = stored.strip_prefix(HASH_SCHEME)?.rsplit_once('$')?;
+    Some((salt, decode_hex(hex)?))
+}
+
+/// Generates a random salt (16 hex characters) for new password hashes
+pub fn random_salt() -> String {
+    let mut bytes = [0u8; 8];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut out = [0u8; 32];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
+
+fn xor(data: &[u8]) -> Vec<u8> {
+    let key_bytes = SECRET_KEY.to_le_bytes();
+    data.iter()
         .enumerate()
         .map(|(i, &b)| b ^ key_bytes[i % key_bytes.len()])
         .collect()
 }
 
+fn aes_encrypt(key: &[u8; 32], plaintext: &[u8]) -> Vec<u8> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .expect("AES-GCM encryption cannot fail for in-memory buffers");
+
+    let mut out = nonce.to_vec();
+    out.extend(ciphertext);
+    out
+}
+
+fn aes_decrypt(key: &[u8; 32], data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < NONCE_LEN {
+        return None;
+    }
+
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()
+}
+
 /// Encodes binary data to Base64
 ///
 /// # Arguments
@@ -73,3 +228,35 @@ pub fn base64_encode(input: &[u8]) -> String {
 
     result
 }
+
+/// Decodes Base64 data (standard alphabet, padding optional)
+///
+/// # Arguments
+/// * `input` - Base64 text; whitespace is ignored
+///
+/// # Returns
+/// Decoded bytes, or `None` if the input contains characters outside the alphabet
+pub fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in input.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(result)
+}
@@ -1,34 +1,303 @@
-/// Virtual filesystem module
-/// Simulates a Unix-like file system with directories and files
+//! Virtual filesystem module
+//! Simulates a Unix-like file system with directories and files
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
 
-const LOGO_PNG: &[u8] = include_bytes!("../assets/secret.png");
+use serde::{Deserialize, Serialize};
+
+use crate::challenge;
+use crate::encryption::Cipher;
+use crate::snapshot;
+use crate::users;
 
 /// Represents the type of a filesystem node
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum FileType {
     File,
     Directory,
+    Symlink, // Content holds the target path
+}
+
+/// Maximum symlinks followed while resolving one path (Linux MAXSYMLINKS)
+pub const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// Reasons a filesystem operation can fail, modelled on Unix errno values
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FsError {
+    NotFound,          // ENOENT
+    AlreadyExists,     // EEXIST
+    NotADirectory,     // ENOTDIR
+    IsADirectory,      // EISDIR
+    DirectoryNotEmpty, // ENOTEMPTY
+    PermissionDenied,  // EACCES
+    NotPermitted,      // EPERM
+    InvalidPath,       // EINVAL
+    IntoItself,        // EINVAL from rename(2) into a subdirectory of itself
+    TooManyLinks,      // ELOOP
+    Busy,              // EBUSY
+    Io,                // EIO: stored content cannot be decrypted
+    NoSpace,           // ENOSPC: the write would exceed the filesystem's capacity
+}
+
+impl FsError {
+    /// Exit status of a command failing with this error
+    ///
+    /// Like coreutils, operational failures exit 1; a path that can never
+    /// name an entry (such as `.` or `..` as a final component) is misuse
+    /// and exits 2.
+    pub fn status(self) -> i32 {
+        match self {
+            FsError::InvalidPath => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FsError::NotFound => "No such file or directory",
+            FsError::AlreadyExists => "File exists",
+            FsError::NotADirectory => "Not a directory",
+            FsError::IsADirectory => "Is a directory",
+            FsError::DirectoryNotEmpty => "Directory not empty",
+            FsError::PermissionDenied => "Permission denied",
+            FsError::NotPermitted => "Operation not permitted",
+            FsError::InvalidPath | FsError::IntoItself => "Invalid argument",
+            FsError::TooManyLinks => "Too many levels of symbolic links",
+            FsError::Busy => "Device or resource busy",
+            FsError::Io => "Input/output error",
+            FsError::NoSpace => "No space left on device",
+        })
+    }
+}
+
+impl std::error::Error for FsError {}
+
+/// Permission bits requested in access checks
+pub const PERM_READ: u16 = 0o4;
+pub const PERM_WRITE: u16 = 0o2;
+pub const PERM_EXEC: u16 = 0o1;
+
+/// Special mode bits
+pub const MODE_SETUID: u16 = 0o4000;
+pub const MODE_SETGID: u16 = 0o2000;
+pub const MODE_STICKY: u16 = 0o1000;
+
+/// Default modes before the umask is applied
+pub const DEFAULT_DIR_MODE: u16 = 0o777;
+pub const DEFAULT_FILE_MODE: u16 = 0o666;
+
+/// Allocation unit: file contents take whole blocks, directories one each
+pub const BLOCK_SIZE: u64 = 4096;
+
+/// Capacity of a filesystem unless the terminal is configured otherwise (bytes)
+pub const DEFAULT_CAPACITY: u64 = 16 * 1024 * 1024;
+
+/// Owner of the player's home tree
+pub const PLAYER_UID: u32 = 1000;
+pub const PLAYER_GID: u32 = 1000;
+
+/// Identity used for permission checks
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub groups: Vec<u32>, // Supplementary groups
+}
+
+impl Credentials {
+    pub fn root() -> Self {
+        Credentials {
+            uid: 0,
+            gid: 0,
+            groups: Vec::new(),
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.gid == gid || self.groups.contains(&gid)
+    }
 }
 
-/// Represents a single node in the filesystem (file or directory)
-#[derive(Clone)]
+/// Represents a single node in the filesystem (an inode)
+///
+/// Directories own a sorted name → inode map; files and symlinks may be
+/// named by several directory entries (hard links).
+#[derive(Clone, Serialize, Deserialize)]
 pub struct FileNode {
     pub ftype: FileType,
+    #[serde(with = "snapshot::base64_bytes")]
     pub content: Vec<u8>,
-    #[allow(dead_code)]
-    pub timestamp: f64,
+    pub cipher: Cipher,
+    pub timestamp: f64, // Modification time, ms since the epoch
+    #[serde(default)]
+    pub atime: f64, // Access time: set on creation and by `touch` (reads do not update it)
+    #[serde(default)]
+    pub ctime: f64, // Change time: content, mode or ownership last changed
+    pub mode: u16, // Permission bits, including setuid/setgid/sticky
+    pub uid: u32,
+    pub gid: u32,
+    pub ino: u64,
+    pub nlink: u32, // Directory entries naming this inode
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    children: BTreeMap<String, u64>,
+    /// Plaintext length of a file, kept so sizes never need a decryption
+    #[serde(skip)]
+    len: u64,
+}
+
+impl FileNode {
+    fn new(ftype: FileType, content: Vec<u8>, cipher: Cipher, mode: u16, time: f64) -> Self {
+        FileNode {
+            ftype,
+            cipher,
+            timestamp: time,
+            atime: time,
+            ctime: time,
+            mode,
+            uid: 0,
+            gid: 0,
+            ino: 0,
+            nlink: 0,
+            children: BTreeMap::new(),
+            len: content.len() as u64,
+            content,
+        }
+    }
+
+    /// Checks whether `creds` hold all `want` bits (PERM_READ/WRITE/EXEC) on this node
+    pub fn permits(&self, creds: &Credentials, want: u16) -> bool {
+        if creds.is_root() {
+            // Root bypasses checks, except that files need some execute bit
+            return want & PERM_EXEC == 0
+                || self.ftype == FileType::Directory
+                || self.mode & 0o111 != 0;
+        }
+
+        let bits = if creds.uid == self.uid {
+            self.mode >> 6
+        } else if creds.in_group(self.gid) {
+            self.mode >> 3
+        } else {
+            self.mode
+        } & 0o7;
+
+        bits & want == want
+    }
+
+    /// Apparent size in bytes: the plaintext length of a file, the target
+    /// length of a symlink, and 4096 for directories
+    pub fn size(&self) -> u64 {
+        match self.ftype {
+            FileType::Directory => BLOCK_SIZE,
+            FileType::Symlink | FileType::File => self.len,
+        }
+    }
+
+    /// Recomputes the cached plaintext length from the stored content
+    fn measure(&mut self) {
+        self.len = match self.ftype {
+            FileType::File => self
+                .cipher
+                .decrypt(&self.content)
+                .map_or(self.content.len(), |plain| plain.len()),
+            _ => self.content.len(),
+        } as u64;
+    }
+
+    /// Bytes the node takes on the virtual disk: a file's size rounded up to
+    /// whole blocks, one block for a directory and none for a symlink
+    pub fn allocated(&self) -> u64 {
+        match self.ftype {
+            FileType::Directory => BLOCK_SIZE,
+            FileType::Symlink => 0,
+            FileType::File => blocks_for(self.size()),
+        }
+    }
+
+    /// Renders the type and mode like `ls -l`, e.g. `drwxrwxrwt`
+    pub fn mode_string(&self) -> String {
+        let kind = match self.ftype {
+            FileType::Directory => 'd',
+            FileType::Symlink => 'l',
+            FileType::File => '-',
+        };
+        let special = [(MODE_SETUID, 's'), (MODE_SETGID, 's'), (MODE_STICKY, 't')];
+
+        let mut out = String::from(kind);
+        for (shift, (flag, mark)) in [6, 3, 0].into_iter().zip(special) {
+            let bits = self.mode >> shift;
+            out.push(if bits & PERM_READ != 0 { 'r' } else { '-' });
+            out.push(if bits & PERM_WRITE != 0 { 'w' } else { '-' });
+            out.push(match (bits & PERM_EXEC != 0, self.mode & flag != 0) {
+                (true, true) => mark,
+                (false, true) => mark.to_ascii_uppercase(),
+                (true, false) => 'x',
+                (false, false) => '-',
+            });
+        }
+        out
+    }
+}
+
+/// Bytes allocated for `len` bytes of file content
+fn blocks_for(len: u64) -> u64 {
+    len.div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+}
+
+fn default_capacity() -> u64 {
+    DEFAULT_CAPACITY
+}
+
+/// A resolved path: the inode and the physical (symlink-free) path leading to it
+struct Resolved {
+    ino: u64,
+    path: String,
+}
+
+/// A directory entry location: the parent directory and the entry's name
+struct Entry {
+    parent: Resolved,
+    name: String,
+}
+
+impl Entry {
+    fn path(&self) -> String {
+        if self.parent.path == "/" {
+            format!("/{}", self.name)
+        } else {
+            format!("{}/{}", self.parent.path, self.name)
+        }
+    }
 }
 
 /// Virtual filesystem implementation
+///
+/// Nodes live in an inode table; directories map names to inode numbers, so
+/// listing costs O(children) and renaming only touches two directories.
+#[derive(Serialize, Deserialize)]
 pub struct FileSystem {
-    nodes: HashMap<String, FileNode>,
+    inodes: HashMap<u64, FileNode>,
+    root: u64,
+    next_ino: u64,
     pub current_path: String,
+    /// Bytes that nodes may allocate; set from the terminal's configuration
+    #[serde(skip, default = "default_capacity")]
+    pub capacity: u64,
+    /// Bytes allocated by all inodes, updated as they are created, written and freed
+    #[serde(skip)]
+    used: u64,
 }
 
 impl FileSystem {
-    fn parent_path(path: &str) -> Option<String> {
+    /// Returns the parent directory of an absolute path (`None` for "/")
+    pub fn parent_path(path: &str) -> Option<String> {
         if path == "/" {
             return None;
         }
@@ -43,7 +312,7 @@ impl FileSystem {
     }
 
     fn has_path_prefix(path: &str, base: &str) -> bool {
-        path == base || path.starts_with(&(base.to_string() + "/"))
+        path == base || base == "/" || path.starts_with(&(base.to_string() + "/"))
     }
 
     fn remap_path(path: &str, src: &str, dst: &str) -> String {
@@ -56,69 +325,552 @@ impl FileSystem {
 
     /// Creates a new filesystem with default structure and files
     pub fn new() -> Self {
+        let mut root = FileNode::new(FileType::Directory, Vec::new(), Cipher::Plain, 0o755, 0.0);
+        root.ino = 1;
+        root.nlink = 1;
+
         let mut fs = FileSystem {
-            nodes: HashMap::new(),
+            used: root.allocated(),
+            inodes: HashMap::from([(1, root)]),
+            root: 1,
+            next_ino: 2,
             current_path: "/home".to_string(),
+            capacity: DEFAULT_CAPACITY,
+        };
+
+        Self::populate(&mut fs).expect("default filesystem layout is valid");
+        fs
+    }
+
+    /// Creates the challenge pack's directories and files, then the user database
+    fn populate(fs: &mut FileSystem) -> Result<(), FsError> {
+        challenge::install(fs, 0.0)?;
+        users::seed(fs)
+    }
+
+    fn inode(&self, ino: u64) -> &FileNode {
+        &self.inodes[&ino]
+    }
+
+    /// Bytes allocated by all nodes; hard-linked files count once
+    pub fn usage(&self) -> u64 {
+        self.used
+    }
+
+    /// Rebuilds the cached file sizes and the usage counter of a deserialized
+    /// filesystem, which stores neither
+    pub fn recount(&mut self) {
+        for node in self.inodes.values_mut() {
+            node.measure();
+        }
+        self.used = self.inodes.values().map(FileNode::allocated).sum();
+    }
+
+    /// Checks that allocating `added` bytes while releasing `freed` stays within capacity
+    fn reserve(&self, added: u64, freed: u64) -> Result<(), FsError> {
+        if added > freed && self.used.saturating_sub(freed) + added > self.capacity {
+            Err(FsError::NoSpace)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Bytes allocated by an inode and everything below it, each inode counted once
+    fn tree_allocated(&self, ino: u64, seen: &mut HashSet<u64>) -> u64 {
+        if !seen.insert(ino) {
+            return 0;
+        }
+        let node = self.inode(ino);
+        let below: u64 = node
+            .children
+            .values()
+            .map(|child| self.tree_allocated(*child, seen))
+            .sum();
+        node.allocated() + below
+    }
+
+    /// Stores a new inode and links it into `parent` under `name`
+    fn insert(&mut self, parent: u64, name: &str, mut node: FileNode) -> u64 {
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        node.ino = ino;
+        self.used += node.allocated();
+        self.inodes.insert(ino, node);
+        self.attach(parent, name, ino);
+        ino
+    }
+
+    /// Adds a directory entry for an existing inode
+    fn attach(&mut self, parent: u64, name: &str, ino: u64) {
+        if let Some(node) = self.inodes.get_mut(&ino) {
+            node.nlink += 1;
+        }
+        if let Some(dir) = self.inodes.get_mut(&parent) {
+            dir.children.insert(name.to_string(), ino);
+        }
+    }
+
+    /// Removes a directory entry, freeing the inode once no entry names it
+    fn detach(&mut self, parent: u64, name: &str) {
+        let Some(ino) = self
+            .inodes
+            .get_mut(&parent)
+            .and_then(|dir| dir.children.remove(name))
+        else {
+            return;
+        };
+        let orphaned = self.inodes.get_mut(&ino).is_some_and(|node| {
+            node.nlink = node.nlink.saturating_sub(1);
+            node.nlink == 0
+        });
+        if orphaned && let Some(node) = self.inodes.remove(&ino) {
+            self.used = self.used.saturating_sub(node.allocated());
+        }
+    }
+
+    /// Resolves an absolute path to an inode, walking the directory tree
+    ///
+    /// Intermediate components are always followed; the last one only when
+    /// `follow_last` is set. More than `MAX_SYMLINK_DEPTH` links fail with
+    /// `TooManyLinks`. With `creds`, every directory searched must grant execute.
+    fn lookup_as(
+        &self,
+        path: &str,
+        follow_last: bool,
+        creds: Option<&Credentials>,
+    ) -> Result<Resolved, FsError> {
+        let mut pending: VecDeque<String> = path
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .map(str::to_string)
+            .collect();
+        // Physical path walked so far, as (name, inode) pairs below the root
+        let mut stack: Vec<(String, u64)> = Vec::new();
+        let mut hops = 0;
+
+        while let Some(part) = pending.pop_front() {
+            if part == "." {
+                continue;
+            }
+            if part == ".." {
+                stack.pop();
+                continue;
+            }
+
+            let dir = self.inode(stack.last().map(|(_, ino)| *ino).unwrap_or(self.root));
+            if dir.ftype != FileType::Directory {
+                return Err(FsError::NotADirectory);
+            }
+            if creds.is_some_and(|creds| !dir.permits(creds, PERM_EXEC)) {
+                return Err(FsError::PermissionDenied);
+            }
+
+            let child = *dir.children.get(&part).ok_or(FsError::NotFound)?;
+            let node = self.inode(child);
+            if node.ftype == FileType::Symlink && (follow_last || !pending.is_empty()) {
+                hops += 1;
+                if hops > MAX_SYMLINK_DEPTH {
+                    return Err(FsError::TooManyLinks);
+                }
+                let target = String::from_utf8_lossy(&node.content).into_owned();
+                if target.starts_with('/') {
+                    stack.clear();
+                }
+                for part in target.split('/').filter(|part| !part.is_empty()).rev() {
+                    pending.push_front(part.to_string());
+                }
+            } else {
+                stack.push((part, child));
+            }
+        }
+
+        let path = if stack.is_empty() {
+            "/".to_string()
+        } else {
+            stack.iter().map(|(name, _)| format!("/{}", name)).collect()
         };
+        Ok(Resolved {
+            ino: stack.last().map(|(_, ino)| *ino).unwrap_or(self.root),
+            path,
+        })
+    }
 
-        // Initialize directory structure
-        fs.create_dir("/", 0.0);
-        fs.create_dir("/home", 0.0);
-        fs.create_dir("/bin", 0.0);
-        fs.create_dir("/etc", 0.0);
-        fs.create_dir("/boot", 0.0);
-          fs.create_dir("/sys", 0.0);
-            fs.create_dir("/env", 0.0);
-             fs.create_dir("/home/document", 0.0);
-        fs.create_dir("/home/media", 0.0);
+    fn lookup(&self, path: &str, follow_last: bool) -> Result<Resolved, FsError> {
+        self.lookup_as(path, follow_last, None)
+    }
 
+    /// Resolves the directory part of a path, keeping the last component as-is
+    ///
+    /// Used for creating, removing and renaming entries, which act on the
+    /// link itself rather than its target.
+    fn lookup_entry_as(&self, path: &str, creds: Option<&Credentials>) -> Result<Entry, FsError> {
+        let Some(parent) = Self::parent_path(path) else {
+            return Err(FsError::Busy); // The root directory has no entry to change
+        };
+        let name = path[path.rfind('/').map(|idx| idx + 1).unwrap_or(0)..].to_string();
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(FsError::InvalidPath);
+        }
 
-        // Initialize default encrypted files
-        // Encrypted: "1. WASM Terminal (Rust)\n2. Encrypted admin"
-        let projects_content = vec![170, 141, 235, 158, 109, 65, 66, 58, 215, 253, 151, 223, 88, 32, 98, 101, 218, 184, 187, 205, 88, 32, 116, 104, 155, 179, 250, 210, 64, 115, 116, 32, 131, 184, 187, 204, 43, 80, 80, 50, 218, 145, 155, 252, 27, 45, 32, 68, 149, 179, 253, 202, 1, 102, 111, 103, 159, 169, 250, 202, 78, 32, 97, 100, 158, 253, 185, 209, 76, 109, 101, 110, 142, 174, 250, 215, 79, 115, 105, 100, 159, 253, 174, 214, 68, 32, 99, 111, 158, 184, 246, 158, 104, 102, 32, 99, 149, 185, 191, 158, 79, 111, 116, 32, 141, 178, 168, 213, 82, 45, 62, 32, 163, 178, 175, 158, 70, 101, 116, 32, 142, 181, 191, 158, 76, 97, 114, 107, 137, 253, 188, 209, 83, 32, 116, 104, 149, 184, 169, 158, 66, 111, 109, 109, 159, 179, 174, 205, 15];
-        fs.create_file("/home/document/programming_fundamentals.txt", projects_content, 0.0);
+        let parent = self.lookup_as(&parent, true, creds)?;
+        if self.inode(parent.ino).ftype != FileType::Directory {
+            return Err(FsError::NotADirectory);
+        }
+        Ok(Entry { parent, name })
+    }
 
-        // Encrypted: "GitHub: @cse23\nEmail: hello@cse23.org"
-        let contact_content = vec![185, 178, 180, 208, 68, 99, 116, 32, 141, 180, 174, 214, 1, 85, 115, 58, 240, 63, 90, 28, 1, 71, 105, 116, 178, 168, 184, 132, 1, 64, 99, 115, 159, 239, 233, 211, 78, 114, 97, 10, 240, 63, 90, 28, 1, 76, 105, 110, 145, 184, 190, 247, 79, 58, 32, 105, 148, 182, 191, 218, 72, 110, 46, 99, 149, 176, 245, 221, 78, 109, 112, 97, 148, 164, 245, 221, 82, 101, 50, 51, 151, 178, 168, 223, 43, 10, 226, 128, 88, 253, 156, 223, 66, 101, 98, 111, 149, 182, 224, 158, 97, 99, 115, 101, 200, 238, 183, 209, 83, 97, 10, 10, 24, 93, 120, 158, 100, 109, 97, 105, 150, 231, 250, 221, 78, 110, 116, 97, 153, 169, 154, 221, 82, 101, 50, 51, 212, 178, 168, 217];
-        fs.create_file("/home/document/contact.txt", contact_content, 0.0);
+    fn lookup_entry(&self, path: &str) -> Result<Entry, FsError> {
+        self.lookup_entry_as(path, None)
+    }
+
+    /// Inode named by an entry, if any
+    fn entry_ino(&self, entry: &Entry) -> Option<u64> {
+        self.inode(entry.parent.ino).children.get(&entry.name).copied()
+    }
 
+    /// Resolves where a write to `path` lands, following a dangling symlink to its target
+    fn lookup_write(&self, path: &str) -> Result<Entry, FsError> {
+        let entry = self.lookup_entry(path)?;
+        match self.entry_ino(&entry).map(|ino| self.inode(ino)) {
+            Some(node) if node.ftype == FileType::Symlink => {
+                let target = String::from_utf8_lossy(&node.content).into_owned();
+                let target = if target.starts_with('/') {
+                    target
+                } else {
+                    format!("{}/{}", entry.parent.path, target)
+                };
+                match self.lookup(&target, true) {
+                    Ok(found) => self.lookup_entry(&found.path),
+                    Err(FsError::NotFound) => self.lookup_entry(&self.normalize_path(&target)),
+                    Err(err) => Err(err),
+                }
+            }
+            _ => Ok(entry),
+        }
+    }
 
+    /// Returns the node a path refers to
+    fn node(&self, path: &str, follow: bool) -> Result<&FileNode, FsError> {
+        self.lookup(path, follow).map(|found| self.inode(found.ino))
+    }
 
-         let about_content =vec![178, 180, 180, 202, 27, 32, 32, 73, 156, 253, 163, 209, 84, 32, 97, 114, 159, 253, 187, 158, 69, 114, 105, 118, 159, 175, 246, 158, 88, 111, 117, 32, 153, 188, 180, 158, 76, 111, 118, 101, 218, 169, 178, 219, 1, 118, 101, 104, 147, 190, 182, 219, 1, 102, 111, 114, 141, 188, 168, 218, 1, 97, 110, 100, 218, 175, 191, 200, 68, 114, 115, 101, 212];
-        fs.create_file("/home/document/ctf.txt", about_content, 0.0);
+    fn node_mut(&mut self, path: &str, follow: bool) -> Result<&mut FileNode, FsError> {
+        let ino = self.lookup(path, follow)?.ino;
+        self.inodes.get_mut(&ino).ok_or(FsError::NotFound)
+    }
 
-        let cv_content = vec![217, 144, 151, 251, 99, 10, 45, 32, 183, 188, 189, 219, 1, 77, 97, 116, 146, 184, 250, 251, 74, 97, 32, 66, 155, 253, 208, 158, 1, 32, 32, 32, 218, 253, 129, 243, 84, 116, 116, 105, 155, 181, 250, 243, 84, 114, 97, 108, 147, 169, 178, 223, 83, 97, 110, 93];
-        fs.create_file("/home/document/golden_Key.txt", cv_content, 0.0);
+    /// Location for a new entry, failing if the name is already taken
+    fn vacant_entry(&self, path: &str) -> Result<Entry, FsError> {
+        let entry = self.lookup_entry(path)?;
+        match self.entry_ino(&entry) {
+            Some(_) => Err(FsError::AlreadyExists),
+            None => Ok(entry),
+        }
+    }
 
-        let sudo_pssed_content = vec![185, 142, 159, 197, 98, 48, 109, 101, 165, 142, 191, 219, 126, 69, 110, 106, 149, 164, 167];
-        fs.create_file("/env/mypass.txt", sudo_pssed_content, 0.0);
-        fs.create_file("/home/media/secret.png", LOGO_PNG.to_vec(), 0.0);
+    /// Creates a new directory at the specified path (root-owned, mode 755)
+    pub fn create_dir(&mut self, path: &str, time: f64) -> Result<(), FsError> {
+        let entry = self.vacant_entry(path)?;
+        self.reserve(BLOCK_SIZE, 0)?;
+        let node = FileNode::new(FileType::Directory, Vec::new(), Cipher::Plain, 0o755, time);
+        self.insert(entry.parent.ino, &entry.name, node);
+        Ok(())
+    }
 
-        fs
+    /// Creates a new file with already-encrypted content at the specified path
+    /// (root-owned, mode 644), replacing an existing file or symlink
+    ///
+    /// Fails with `NoSpace` if the content does not fit in the remaining capacity.
+    pub fn create_file(
+        &mut self,
+        path: &str,
+        content: Vec<u8>,
+        cipher: Cipher,
+        time: f64,
+    ) -> Result<(), FsError> {
+        let entry = self.lookup_entry(path)?;
+        let replaced = self.entry_ino(&entry).map(|ino| self.inode(ino));
+        if replaced.is_some_and(|node| node.ftype == FileType::Directory) {
+            return Err(FsError::IsADirectory);
+        }
+        // A replaced file's blocks are only released if no other link keeps them
+        let freed = replaced
+            .filter(|node| node.nlink == 1)
+            .map_or(0, FileNode::allocated);
+        let mut node = FileNode::new(FileType::File, content, cipher, 0o644, time);
+        node.measure();
+        self.reserve(node.allocated(), freed)?;
+        if replaced.is_some() {
+            self.detach(entry.parent.ino, &entry.name);
+        }
+        self.insert(entry.parent.ino, &entry.name, node);
+        Ok(())
     }
 
-    /// Creates a new directory at the specified path
-    pub fn create_dir(&mut self, path: &str, time: f64) {
-        self.nodes.insert(
-            path.to_string(),
-            FileNode {
-                ftype: FileType::Directory,
-                content: Vec::new(),
-                timestamp: time,
-            },
+    /// Creates a symbolic link at `path` pointing to `target` (stored verbatim)
+    pub fn create_symlink(&mut self, path: &str, target: &str, time: f64) -> Result<(), FsError> {
+        let entry = self.vacant_entry(path)?;
+        let node = FileNode::new(
+            FileType::Symlink,
+            target.as_bytes().to_vec(),
+            Cipher::Plain,
+            0o777,
+            time,
         );
+        self.insert(entry.parent.ino, &entry.name, node);
+        Ok(())
     }
 
-    /// Creates a new file with content at the specified path
-    pub fn create_file(&mut self, path: &str, content: Vec<u8>, time: f64) {
-        self.nodes.insert(
-            path.to_string(),
-            FileNode {
-                ftype: FileType::File,
-                content,
-                timestamp: time,
-            },
-        );
+    /// Adds a second name for an existing file; both names share content and metadata
+    ///
+    /// Directories cannot be hard linked (`NotPermitted`).
+    pub fn create_hard_link(&mut self, existing: &str, path: &str) -> Result<(), FsError> {
+        let source = self.lookup(existing, false)?;
+        if self.inode(source.ino).ftype == FileType::Directory {
+            return Err(FsError::NotPermitted);
+        }
+
+        let entry = self.vacant_entry(path)?;
+        self.attach(entry.parent.ino, &entry.name, source.ino);
+        Ok(())
+    }
+
+    /// Returns the target of a symbolic link (without following it)
+    ///
+    /// Fails with `InvalidPath` if the entry is not a symlink.
+    pub fn read_link(&self, path: &str) -> Result<String, FsError> {
+        let node = self.symlink_metadata(path)?;
+        if node.ftype != FileType::Symlink {
+            return Err(FsError::InvalidPath);
+        }
+        Ok(String::from_utf8_lossy(&node.content).into_owned())
+    }
+
+    /// Resolves every symlink in a path, returning the physical path
+    pub fn canonicalize(&self, path: &str) -> Result<String, FsError> {
+        self.lookup(path, true).map(|found| found.path)
+    }
+
+    /// Encrypts plaintext with the given cipher and stores it as a file
+    ///
+    /// Writes through symlinks; an existing file keeps its mode, ownership
+    /// and hard links. Fails with `NoSpace` if the new content does not fit.
+    pub fn write_file(
+        &mut self,
+        path: &str,
+        plaintext: &[u8],
+        cipher: Cipher,
+        time: f64,
+    ) -> Result<(), FsError> {
+        let entry = self.lookup_write(path)?;
+        let existing = self.entry_ino(&entry);
+        let content = cipher.encrypt(plaintext);
+        if let Some(ino) = existing
+            && self.inode(ino).ftype != FileType::File
+        {
+            return Err(FsError::IsADirectory);
+        }
+        let freed = existing.map_or(0, |ino| self.inode(ino).allocated());
+        let added = blocks_for(plaintext.len() as u64);
+        self.reserve(added, freed)?;
+        match existing.and_then(|ino| self.inodes.get_mut(&ino)) {
+            Some(node) => {
+                node.content = content;
+                node.cipher = cipher;
+                node.len = plaintext.len() as u64;
+                node.timestamp = time;
+                node.ctime = time;
+                self.used = self.used.saturating_sub(freed) + added;
+            }
+            None => {
+                let mut node = FileNode::new(FileType::File, content, cipher, 0o644, time);
+                node.len = plaintext.len() as u64;
+                self.insert(entry.parent.ino, &entry.name, node);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the node at the given path (following symlinks), for metadata inspection
+    pub fn metadata(&self, path: &str) -> Result<&FileNode, FsError> {
+        self.node(path, true)
+    }
+
+    /// Returns the node at the given path without following a final symlink
+    pub fn symlink_metadata(&self, path: &str) -> Result<&FileNode, FsError> {
+        self.node(path, false)
+    }
+
+    /// Sets the permission bits of a node
+    pub fn set_mode(&mut self, path: &str, mode: u16) -> Result<(), FsError> {
+        self.node_mut(path, true)?.mode = mode & 0o7777;
+        Ok(())
+    }
+
+    /// Changes the owner and/or group of a node
+    pub fn set_owner(
+        &mut self,
+        path: &str,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> Result<(), FsError> {
+        Self::apply_owner(self.node_mut(path, true)?, uid, gid);
+        Ok(())
+    }
+
+    /// Changes the owner and/or group of a symlink itself rather than its target
+    pub fn lchown(
+        &mut self,
+        path: &str,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> Result<(), FsError> {
+        Self::apply_owner(self.node_mut(path, false)?, uid, gid);
+        Ok(())
+    }
+
+    fn apply_owner(node: &mut FileNode, uid: Option<u32>, gid: Option<u32>) {
+        if let Some(uid) = uid {
+            node.uid = uid;
+        }
+        if let Some(gid) = gid {
+            node.gid = gid;
+        }
+    }
+
+    /// Sets a node's access and modification times (and so its change time)
+    pub fn touch(&mut self, path: &str, time: f64) -> Result<(), FsError> {
+        let node = self.node_mut(path, true)?;
+        node.timestamp = time;
+        node.atime = time;
+        node.ctime = time;
+        Ok(())
+    }
+
+    /// Records a metadata change (mode, owner) in a node's change time
+    ///
+    /// # Arguments
+    /// * `follow` - Whether a final symlink is followed (false for `lchown`)
+    pub fn mark_changed(&mut self, path: &str, follow: bool, time: f64) -> Result<(), FsError> {
+        self.node_mut(path, follow)?.ctime = time;
+        Ok(())
+    }
+
+    /// Returns a path and all its descendants as physical paths, parents first
+    ///
+    /// Symlinks inside the tree are listed but not descended into.
+    pub fn walk(&self, path: &str) -> Result<Vec<String>, FsError> {
+        let start = self.lookup(path, true)?;
+
+        let mut paths = Vec::new();
+        let mut stack = vec![(start.path, start.ino)];
+        while let Some((path, ino)) = stack.pop() {
+            let node = self.inode(ino);
+            if node.ftype == FileType::Directory {
+                for (name, child) in node.children.iter().rev() {
+                    let child_path = if path == "/" {
+                        format!("/{}", name)
+                    } else {
+                        format!("{}/{}", path, name)
+                    };
+                    stack.push((child_path, *child));
+                }
+            }
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// Verifies the inode table of a deserialized filesystem
+    ///
+    /// Every entry must name an existing inode, every directory must have
+    /// exactly one parent, all inodes must be reachable from the root and
+    /// link counts must match the number of entries.
+    ///
+    /// # Returns
+    /// A description of the first inconsistency found
+    pub fn check_integrity(&self) -> Result<(), String> {
+        match self.inodes.get(&self.root) {
+            Some(node) if node.ftype == FileType::Directory => {}
+            _ => return Err(format!("root inode {} is not a directory", self.root)),
+        }
+
+        let mut links: HashMap<u64, u32> = HashMap::from([(self.root, 1)]);
+        let mut stack = vec![self.root];
+        while let Some(ino) = stack.pop() {
+            for (name, child) in &self.inode(ino).children {
+                if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+                    return Err(format!("inode {} has an invalid entry name '{}'", ino, name));
+                }
+                let Some(node) = self.inodes.get(child) else {
+                    return Err(format!("entry '{}' names missing inode {}", name, child));
+                };
+
+                let count = links.entry(*child).or_insert(0);
+                *count += 1;
+                if node.ftype == FileType::Directory {
+                    if *count > 1 {
+                        return Err(format!("directory inode {} has several parents", child));
+                    }
+                    stack.push(*child);
+                } else if !node.children.is_empty() {
+                    return Err(format!("non-directory inode {} has entries", child));
+                }
+            }
+        }
+
+        for (ino, node) in &self.inodes {
+            if node.ino != *ino || *ino >= self.next_ino {
+                return Err(format!("inode {} is numbered inconsistently", ino));
+            }
+            match links.get(ino) {
+                None => return Err(format!("inode {} is unreachable", ino)),
+                Some(count) if *count != node.nlink => {
+                    return Err(format!("inode {} has a wrong link count", ino))
+                }
+                _ => {}
+            }
+        }
+
+        if !self.is_dir(&self.current_path) {
+            return Err(format!("working directory {} does not exist", self.current_path));
+        }
+        Ok(())
+    }
+
+    /// Checks `want` permission bits on a path, plus search (x) on every directory traversed
+    pub fn access(&self, path: &str, creds: &Credentials, want: u16) -> Result<(), FsError> {
+        let found = self.lookup_as(path, true, Some(creds))?;
+        if self.inode(found.ino).permits(creds, want) {
+            Ok(())
+        } else {
+            Err(FsError::PermissionDenied)
+        }
+    }
+
+    /// Checks whether `creds` may create, remove or rename the entry at `path`
+    ///
+    /// Requires write and search on the parent directory. In a sticky
+    /// directory only the entry's owner, the directory's owner or root may
+    /// remove or rename existing entries (`NotPermitted` otherwise).
+    pub fn access_entry(&self, path: &str, creds: &Credentials) -> Result<(), FsError> {
+        let entry = self.lookup_entry_as(path, Some(creds))?;
+        let dir = self.inode(entry.parent.ino);
+        if !dir.permits(creds, PERM_WRITE | PERM_EXEC) {
+            return Err(FsError::PermissionDenied);
+        }
+
+        match self.entry_ino(&entry).map(|ino| self.inode(ino)) {
+            Some(node)
+                if dir.mode & MODE_STICKY != 0
+                    && !creds.is_root()
+                    && creds.uid != node.uid
+                    && creds.uid != dir.uid =>
+            {
+                Err(FsError::NotPermitted)
+            }
+            _ => Ok(()),
+        }
     }
 
     /// Resolves a path (relative or absolute) to an absolute path
@@ -139,7 +891,7 @@ impl FileSystem {
     fn normalize_path(&self, path: &str) -> String {
         let mut stack = Vec::new();
         for part in path.split('/') {
-            if part == "" || part == "." {
+            if part.is_empty() || part == "." {
                 continue;
             }
             if part == ".." {
@@ -156,15 +908,19 @@ impl FileSystem {
         }
     }
 
-    /// Checks if a node exists at the given path
+    /// Checks if a node exists at the given path (a dangling symlink does not)
     pub fn exists(&self, path: &str) -> bool {
-        self.nodes.contains_key(path)
+        self.metadata(path).is_ok()
+    }
+
+    /// Checks if an entry exists at the given path, including dangling symlinks
+    pub fn lexists(&self, path: &str) -> bool {
+        self.symlink_metadata(path).is_ok()
     }
 
     /// Checks if a path points to a directory
     pub fn is_dir(&self, path: &str) -> bool {
-        self.nodes
-            .get(path)
+        self.metadata(path)
             .map(|node| node.ftype == FileType::Directory)
             .unwrap_or(false)
     }
@@ -172,141 +928,170 @@ impl FileSystem {
     /// Checks if a path points to a file
     #[allow(dead_code)]
     pub fn is_file(&self, path: &str) -> bool {
-        self.nodes
-            .get(path)
+        self.metadata(path)
             .map(|node| node.ftype == FileType::File)
             .unwrap_or(false)
     }
 
-    /// Retrieves file content at the given path
-    pub fn get_file_content(&self, path: &str) -> Option<&Vec<u8>> {
-        self.nodes.get(path).map(|node| &node.content)
+    /// Checks if a path is itself a symbolic link
+    pub fn is_symlink(&self, path: &str) -> bool {
+        self.read_link(path).is_ok()
     }
 
-    /// Lists direct children of a directory
-    pub fn list_directory(&self, dir_path: &str) -> Vec<String> {
-        let current = if dir_path == "/" {
-            "/".to_string()
-        } else {
-            dir_path.to_string() + "/"
-        };
-
-        let mut entries = Vec::new();
-
-        for (path, node) in &self.nodes {
-            if path.starts_with(&current) {
-                let relative = &path[current.len()..];
-                if !relative.contains('/') && !relative.is_empty() {
-                    let suffix = if node.ftype == FileType::Directory {
-                        "/"
-                    } else {
-                        ""
-                    };
-                    entries.push(format!("{}{}", relative, suffix));
-                }
-            }
+    /// Returns the metadata of a regular file (`IsADirectory` for directories)
+    fn file_node(&self, path: &str) -> Result<&FileNode, FsError> {
+        let node = self.metadata(path)?;
+        if node.ftype == FileType::Directory {
+            return Err(FsError::IsADirectory);
         }
-
-        entries.sort();
-        entries
+        Ok(node)
     }
 
-    /// Deletes a file or empty directory
-    pub fn delete(&mut self, path: &str) -> bool {
-        if !self.exists(path) {
-            return false;
-        }
-
-        if self.is_dir(path) {
-            let prefix = path.to_string() + "/";
-            if self.nodes.keys().any(|k| k.starts_with(&prefix)) {
-                return false; // Directory not empty
-            }
-        }
+    /// Returns the cipher a file is stored with
+    pub fn file_cipher(&self, path: &str) -> Result<Cipher, FsError> {
+        self.file_node(path).map(|node| node.cipher)
+    }
 
-        self.nodes.remove(path).is_some()
+    /// Retrieves decrypted file content at the given path
+    ///
+    /// Content that fails to decrypt is reported as `Io`.
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>, FsError> {
+        let node = self.file_node(path)?;
+        node.cipher.decrypt(&node.content).ok_or(FsError::Io)
     }
 
-    /// Copies a file or directory recursively to a new path
-    pub fn copy_path(&mut self, src: &str, dst: &str) -> Result<(), String> {
-        if src == "/" {
-            return Err("cannot copy root directory".to_string());
+    /// Lists direct children of a directory, sorted, with a "/" suffix on subdirectories
+    pub fn list_directory(&self, dir_path: &str) -> Result<Vec<String>, FsError> {
+        let dir = self.metadata(dir_path)?;
+        if dir.ftype != FileType::Directory {
+            return Err(FsError::NotADirectory);
         }
 
-        if !self.exists(src) {
-            return Err(format!("cannot stat '{}': No such file or directory", src));
-        }
+        Ok(dir
+            .children
+            .iter()
+            .map(|(name, ino)| {
+                if self.inode(*ino).ftype == FileType::Directory {
+                    format!("{}/", name)
+                } else {
+                    name.clone()
+                }
+            })
+            .collect())
+    }
 
-        if self.exists(dst) {
-            return Err(format!("cannot create '{}': File exists", dst));
+    /// Deletes a file, symlink (not its target) or empty directory
+    pub fn delete(&mut self, path: &str) -> Result<(), FsError> {
+        let entry = self.lookup_entry(path)?;
+        let ino = self.entry_ino(&entry).ok_or(FsError::NotFound)?;
+        if !self.inode(ino).children.is_empty() {
+            return Err(FsError::DirectoryNotEmpty);
         }
 
-        let parent = Self::parent_path(dst)
-            .ok_or_else(|| format!("cannot create '{}': Invalid destination", dst))?;
-        if !self.exists(&parent) || !self.is_dir(&parent) {
-            return Err(format!("cannot create '{}': No such directory", dst));
-        }
+        self.detach(entry.parent.ino, &entry.name);
+        Ok(())
+    }
 
-        if self.is_dir(src) && Self::has_path_prefix(dst, src) {
-            return Err("cannot copy a directory into itself".to_string());
-        }
+    /// Deletes a file, symlink or directory together with everything below it
+    ///
+    /// Inodes still named by a hard link outside the tree survive.
+    pub fn delete_tree(&mut self, path: &str) -> Result<(), FsError> {
+        let entry = self.lookup_entry(path)?;
+        let ino = self.entry_ino(&entry).ok_or(FsError::NotFound)?;
+        self.detach_children(ino);
+        self.detach(entry.parent.ino, &entry.name);
+        Ok(())
+    }
 
-        let entries: Vec<(String, FileNode)> = self
-            .nodes
+    /// Empties a directory inode recursively, children before their parent
+    fn detach_children(&mut self, dir: u64) {
+        let names: Vec<(String, u64)> = self
+            .inode(dir)
+            .children
             .iter()
-            .filter(|(path, _)| Self::has_path_prefix(path, src))
-            .map(|(path, node)| (Self::remap_path(path, src, dst), node.clone()))
+            .map(|(name, ino)| (name.clone(), *ino))
             .collect();
-
-        for (new_path, node) in entries {
-            self.nodes.insert(new_path, node);
+        for (name, child) in names {
+            if self.inode(child).ftype == FileType::Directory {
+                self.detach_children(child);
+            }
+            self.detach(dir, &name);
         }
-
-        Ok(())
     }
 
-    /// Moves (renames) a file or directory recursively to a new path
-    pub fn move_path(&mut self, src: &str, dst: &str) -> Result<(), String> {
-        if src == "/" {
-            return Err("cannot move root directory".to_string());
+    /// Recursively duplicates an inode, keeping hard links inside the copy linked
+    fn clone_tree(&mut self, ino: u64, copies: &mut HashMap<u64, u64>) -> u64 {
+        if let Some(copy) = copies.get(&ino) {
+            return *copy;
         }
 
-        if !self.exists(src) {
-            return Err(format!("cannot stat '{}': No such file or directory", src));
-        }
+        let mut node = self.inode(ino).clone();
+        let children = std::mem::take(&mut node.children);
+        node.nlink = 0;
+        node.ino = self.next_ino;
+        self.next_ino += 1;
+        let copy = node.ino;
+        self.used += node.allocated();
+        self.inodes.insert(copy, node);
+        copies.insert(ino, copy);
 
-        if self.exists(dst) {
-            return Err(format!("cannot move to '{}': File exists", dst));
+        for (name, child) in children {
+            let child_copy = self.clone_tree(child, copies);
+            self.attach(copy, &name, child_copy);
         }
+        copy
+    }
 
-        let parent =
-            Self::parent_path(dst).ok_or_else(|| format!("cannot move to '{}': Invalid destination", dst))?;
-        if !self.exists(&parent) || !self.is_dir(&parent) {
-            return Err(format!("cannot move to '{}': No such directory", dst));
-        }
+    /// Copies a file or directory recursively to a new path
+    ///
+    /// A symlink given as `src` is followed; links inside a copied tree are
+    /// copied as links. Copies never share inodes with the original, so the
+    /// whole tree must fit in the remaining capacity (`NoSpace`).
+    pub fn copy_path(&mut self, src: &str, dst: &str) -> Result<(), FsError> {
+        let source = self.lookup(src, true)?;
+        let entry = self.vacant_entry(dst)?;
 
-        if self.is_dir(src) && Self::has_path_prefix(dst, src) {
-            return Err("cannot move a directory into itself".to_string());
+        if self.inode(source.ino).ftype == FileType::Directory
+            && Self::has_path_prefix(&entry.path(), &source.path)
+        {
+            return Err(FsError::IntoItself);
         }
 
-        let entries: Vec<(String, FileNode)> = self
-            .nodes
-            .iter()
-            .filter(|(path, _)| Self::has_path_prefix(path, src))
-            .map(|(path, node)| (path.clone(), node.clone()))
-            .collect();
+        self.reserve(self.tree_allocated(source.ino, &mut HashSet::new()), 0)?;
+        let copy = self.clone_tree(source.ino, &mut HashMap::new());
+        self.attach(entry.parent.ino, &entry.name, copy);
+        Ok(())
+    }
+
+    /// Moves (renames) a file or directory to a new path
+    ///
+    /// Only the two parent directories change. A symlink given as `src` is
+    /// renamed itself, not its target.
+    pub fn move_path(&mut self, src: &str, dst: &str) -> Result<(), FsError> {
+        let from = self.lookup_entry(src)?;
+        let ino = self.entry_ino(&from).ok_or(FsError::NotFound)?;
+        let to = self.vacant_entry(dst)?;
 
-        for (old_path, _) in &entries {
-            self.nodes.remove(old_path);
+        let from_path = from.path();
+        let to_path = to.path();
+        if self.inode(ino).ftype == FileType::Directory
+            && Self::has_path_prefix(&to_path, &from_path)
+        {
+            return Err(FsError::IntoItself);
         }
 
-        for (old_path, node) in entries {
-            let new_path = Self::remap_path(&old_path, src, dst);
-            self.nodes.insert(new_path, node);
+        if let Some(dir) = self.inodes.get_mut(&from.parent.ino) {
+            dir.children.remove(&from.name);
+        }
+        if let Some(dir) = self.inodes.get_mut(&to.parent.ino) {
+            dir.children.insert(to.name.clone(), ino);
         }
 
-        if Self::has_path_prefix(&self.current_path, src) {
-            self.current_path = Self::remap_path(&self.current_path, src, dst);
+        for moved in [from_path.as_str(), src] {
+            if Self::has_path_prefix(&self.current_path, moved) && moved != "/" {
+                self.current_path = Self::remap_path(&self.current_path, moved, &to_path);
+                break;
+            }
         }
 
         Ok(())
@@ -318,3 +1103,157 @@ impl Default for FileSystem {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player() -> Credentials {
+        Credentials {
+            uid: PLAYER_UID,
+            gid: PLAYER_GID,
+            groups: Vec::new(),
+        }
+    }
+
+    fn other() -> Credentials {
+        Credentials {
+            uid: 1001,
+            gid: 1001,
+            groups: Vec::new(),
+        }
+    }
+
+    /// A directory owned by `owner` with the given mode
+    fn dir(fs: &mut FileSystem, path: &str, owner: &Credentials, mode: u16) {
+        fs.create_dir(path, 0.0).unwrap();
+        fs.set_owner(path, Some(owner.uid), Some(owner.gid)).unwrap();
+        fs.set_mode(path, mode).unwrap();
+    }
+
+    /// A file owned by `owner` with the given mode
+    fn file(fs: &mut FileSystem, path: &str, owner: &Credentials, mode: u16) {
+        fs.write_file(path, b"data", Cipher::Plain, 0.0).unwrap();
+        fs.set_owner(path, Some(owner.uid), Some(owner.gid)).unwrap();
+        fs.set_mode(path, mode).unwrap();
+    }
+
+    /// A default filesystem with `free` bytes left
+    fn nearly_full(free: u64) -> FileSystem {
+        let mut fs = FileSystem::new();
+        fs.capacity = fs.usage() + free;
+        fs
+    }
+
+    #[test]
+    fn writes_beyond_capacity_fail_with_no_space() {
+        let mut fs = nearly_full(2 * BLOCK_SIZE);
+        let used = fs.usage();
+        let block = vec![b'x'; BLOCK_SIZE as usize];
+
+        fs.write_file("/tmp/a", &block, Cipher::Plain, 0.0).unwrap();
+        let big = vec![b'x'; BLOCK_SIZE as usize + 1];
+        assert_eq!(fs.write_file("/tmp/b", &big, Cipher::Plain, 0.0), Err(FsError::NoSpace));
+        assert!(!fs.lexists("/tmp/b"));
+        assert_eq!(fs.usage(), used + BLOCK_SIZE);
+
+        // Growing a file only needs room for the difference
+        assert_eq!(fs.write_file("/tmp/a", &big, Cipher::Plain, 0.0), Ok(()));
+        assert_eq!(fs.usage(), used + 2 * BLOCK_SIZE);
+        assert_eq!(fs.create_dir("/tmp/d", 0.0), Err(FsError::NoSpace));
+    }
+
+    #[test]
+    fn freed_space_can_be_reused() {
+        let mut fs = nearly_full(BLOCK_SIZE);
+        let block = vec![b'x'; BLOCK_SIZE as usize];
+        fs.write_file("/tmp/a", &block, Cipher::Plain, 0.0).unwrap();
+        assert_eq!(fs.write_file("/tmp/b", b"x", Cipher::Plain, 0.0), Err(FsError::NoSpace));
+
+        // Shrinking and overwriting never need more room
+        fs.write_file("/tmp/a", b"", Cipher::Plain, 0.0).unwrap();
+        fs.write_file("/tmp/b", &block, Cipher::Plain, 0.0).unwrap();
+        fs.delete("/tmp/b").unwrap();
+        assert_eq!(fs.create_dir("/tmp/d", 0.0), Ok(()));
+    }
+
+    #[test]
+    fn copies_need_room_for_the_whole_tree() {
+        let mut fs = nearly_full(3 * BLOCK_SIZE);
+        fs.create_dir("/tmp/d", 0.0).unwrap();
+        fs.write_file("/tmp/d/f", b"data", Cipher::Plain, 0.0).unwrap();
+        assert_eq!(fs.copy_path("/tmp/d", "/tmp/e"), Err(FsError::NoSpace));
+        assert!(!fs.lexists("/tmp/e"));
+
+        // Renames and hard links allocate nothing
+        fs.create_hard_link("/tmp/d/f", "/tmp/g").unwrap();
+        fs.move_path("/tmp/d", "/tmp/e").unwrap();
+        assert_eq!(fs.copy_path("/tmp/g", "/tmp/h"), Ok(()));
+    }
+
+    #[test]
+    fn access_uses_the_owner_group_or_other_bits() {
+        let mut fs = FileSystem::new();
+        file(&mut fs, "/tmp/f", &player(), 0o640);
+
+        assert_eq!(fs.access("/tmp/f", &player(), PERM_READ | PERM_WRITE), Ok(()));
+        assert_eq!(fs.access("/tmp/f", &player(), PERM_EXEC), Err(FsError::PermissionDenied));
+        assert_eq!(fs.access("/tmp/f", &other(), PERM_READ), Err(FsError::PermissionDenied));
+
+        let member = Credentials {
+            groups: vec![PLAYER_GID],
+            ..other()
+        };
+        assert_eq!(fs.access("/tmp/f", &member, PERM_READ), Ok(()));
+        assert_eq!(fs.access("/tmp/f", &member, PERM_WRITE), Err(FsError::PermissionDenied));
+        assert_eq!(fs.access("/tmp/f", &Credentials::root(), PERM_WRITE), Ok(()));
+    }
+
+    #[test]
+    fn access_needs_search_on_every_directory() {
+        let mut fs = FileSystem::new();
+        dir(&mut fs, "/tmp/d", &player(), 0o600);
+        file(&mut fs, "/tmp/d/f", &player(), 0o644);
+
+        assert_eq!(fs.access("/tmp/d/f", &player(), PERM_READ), Err(FsError::PermissionDenied));
+        assert_eq!(fs.access("/tmp/d", &player(), PERM_READ), Ok(()));
+        assert_eq!(fs.access("/tmp/d/f", &Credentials::root(), PERM_READ), Ok(()));
+
+        fs.set_mode("/tmp/d", 0o100).unwrap();
+        assert_eq!(fs.access("/tmp/d/f", &player(), PERM_READ), Ok(()));
+    }
+
+    #[test]
+    fn access_entry_needs_write_and_search_on_the_parent() {
+        let mut fs = FileSystem::new();
+        dir(&mut fs, "/tmp/d", &player(), 0o755);
+        file(&mut fs, "/tmp/d/f", &player(), 0o000);
+
+        // The entry's own mode does not matter, only its directory's
+        assert_eq!(fs.access_entry("/tmp/d/f", &player()), Ok(()));
+        assert_eq!(fs.access_entry("/tmp/d/new", &player()), Ok(()));
+        assert_eq!(fs.access_entry("/tmp/d/f", &other()), Err(FsError::PermissionDenied));
+
+        fs.set_mode("/tmp/d", 0o655).unwrap();
+        assert_eq!(fs.access_entry("/tmp/d/f", &player()), Err(FsError::PermissionDenied));
+    }
+
+    #[test]
+    fn sticky_directory_limits_removal_to_owners() {
+        let mut fs = FileSystem::new();
+        dir(&mut fs, "/tmp/shared", &other(), 0o1777);
+        file(&mut fs, "/tmp/shared/mine", &player(), 0o644);
+        file(&mut fs, "/tmp/shared/theirs", &Credentials::root(), 0o666);
+
+        assert_eq!(fs.access_entry("/tmp/shared/mine", &player()), Ok(()));
+        assert_eq!(fs.access_entry("/tmp/shared/theirs", &player()), Err(FsError::NotPermitted));
+        // New names are still free for anyone who can write the directory
+        assert_eq!(fs.access_entry("/tmp/shared/new", &player()), Ok(()));
+        // The directory's owner and root may remove anything
+        assert_eq!(fs.access_entry("/tmp/shared/mine", &other()), Ok(()));
+        assert_eq!(fs.access_entry("/tmp/shared/mine", &Credentials::root()), Ok(()));
+
+        fs.set_mode("/tmp/shared", 0o777).unwrap();
+        assert_eq!(fs.access_entry("/tmp/shared/theirs", &player()), Ok(()));
+    }
+}
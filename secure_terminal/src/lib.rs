@@ -1,70 +1,469 @@
-/// Secure Terminal - WebAssembly-based encrypted terminal emulator
-///
-/// This module provides a virtual filesystem with encryption capabilities,
-/// exposed as WebAssembly functions for use in web applications.
+//! Secure Terminal - WebAssembly-based encrypted terminal emulator
+//!
+//! This module provides a virtual filesystem with encryption capabilities,
+//! exposed as WebAssembly functions for use in web applications (`wasm`
+//! feature) and as a native library for the `secure-terminal` REPL.
 
+mod challenge;
+mod clock;
 mod commands;
+mod diff;
 mod encryption;
 mod filesystem;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod pack;
+mod regex;
+mod response;
+mod scoring;
+mod shell;
+mod snapshot;
+mod trash;
+mod users;
 
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
-use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[macro_use]
 extern crate lazy_static;
 
-use filesystem::FileSystem;
+use commands::{ParkedFrame, PendingRemoval};
+use filesystem::Credentials;
+use scoring::Progress;
+use users::{PendingAuth, Session};
+
+pub use clock::{Clock, FixedClock, SystemClock};
+pub use encryption::{base64_decode, base64_encode, hash_string, random_salt, Cipher};
+pub use filesystem::{FileNode, FileSystem, FileType, FsError};
+pub use response::{CommandResponse, Effect};
+
+/// Sudo policy - session caching and lockout rules
+#[derive(Clone)]
+pub struct SudoConfig {
+    /// Minutes an authentication stays valid
+    pub timeout_minutes: f64,
+    /// Commands an authentication stays valid for (`None` = unlimited)
+    pub max_commands: Option<u32>,
+    /// Failed attempts before the account is locked
+    pub max_attempts: u32,
+    pub lockout_minutes: f64,
+}
+
+impl Default for SudoConfig {
+    fn default() -> Self {
+        SudoConfig {
+            timeout_minutes: 5.0,
+            max_commands: None,
+            max_attempts: 3,
+            lockout_minutes: 1.0,
+        }
+    }
+}
 
 /// Sudo session state - tracks authentication and password prompts
+///
+/// Snapshots keep the cached session and lockout; the policy and any
+/// unanswered password prompt come from the restoring terminal.
+#[derive(Default, Serialize, Deserialize)]
 pub struct SudoState {
+    #[serde(skip)]
+    pub config: SudoConfig,
     pub authenticated: bool,
+    pub authenticated_at: f64,
+    pub commands_left: Option<u32>,
+    pub failed_attempts: u32,
+    pub locked_until: Option<f64>,
+    #[serde(skip)]
     pub waiting_for_password: bool,
-    pub pending_command: Option<String>, // The command waiting for password confirmation
+    #[serde(skip)]
+    pub pending_command: Option<Vec<String>>, // The command waiting for password confirmation
+    #[serde(skip)]
+    pub pending_stdin: Option<String>, // Its piped or `<` input
+    #[serde(skip)]
+    pub elevated: bool, // True while a command runs through sudo
 }
 
 impl SudoState {
     pub fn new() -> Self {
+        SudoState::with_config(SudoConfig::default())
+    }
+
+    pub fn with_config(config: SudoConfig) -> Self {
         SudoState {
+            config,
             authenticated: false,
+            authenticated_at: 0.0,
+            commands_left: None,
+            failed_attempts: 0,
+            locked_until: None,
             waiting_for_password: false,
             pending_command: None,
+            pending_stdin: None,
+            elevated: false,
         }
     }
+
+    /// Checks whether a cached authentication can still be used at `now` (ms)
+    pub fn session_valid(&self, now: f64) -> bool {
+        self.authenticated
+            && now - self.authenticated_at < self.config.timeout_minutes * 60_000.0
+            && self.commands_left != Some(0)
+    }
+
+    /// Starts a new cached session after a successful password check
+    pub fn authenticate(&mut self, now: f64) {
+        self.authenticated = true;
+        self.authenticated_at = now;
+        self.commands_left = self.config.max_commands;
+        self.failed_attempts = 0;
+        self.locked_until = None;
+    }
+
+    /// Drops cached credentials (`sudo -k`)
+    pub fn invalidate(&mut self) {
+        self.authenticated = false;
+        self.commands_left = None;
+    }
+
+    /// Returns remaining lockout time in ms, if the account is locked at `now`
+    pub fn lockout_remaining(&self, now: f64) -> Option<f64> {
+        self.locked_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+}
+
+/// Settings for a new terminal, e.g. `{"hostname": "victim", "sudo_timeout_minutes": 1}`
+///
+/// Missing fields keep their defaults; unknown fields are rejected.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerminalConfig {
+    /// Host shown in the prompt (`user@host:/path$`)
+    pub hostname: String,
+    /// Initial color theme
+    pub theme: String,
+    pub sudo_timeout_minutes: f64,
+    pub sudo_max_commands: Option<u32>,
+    pub sudo_max_attempts: u32,
+    pub sudo_lockout_minutes: f64,
+    /// Size of the virtual disk in bytes, reported by `df` and enforced on writes
+    pub disk_capacity: u64,
 }
 
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        let sudo = SudoConfig::default();
+        TerminalConfig {
+            hostname: "admin".to_string(),
+            theme: "matrix".to_string(),
+            sudo_timeout_minutes: sudo.timeout_minutes,
+            sudo_max_commands: sudo.max_commands,
+            sudo_max_attempts: sudo.max_attempts,
+            sudo_lockout_minutes: sudo.lockout_minutes,
+            disk_capacity: filesystem::DEFAULT_CAPACITY,
+        }
+    }
+}
+
+impl TerminalConfig {
+    /// Parses a JSON configuration object
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|err| format!("invalid terminal config: {}", err))
+    }
+
+    /// Sudo policy with this terminal's timeouts and limits
+    pub fn sudo_config(&self) -> SudoConfig {
+        SudoConfig {
+            timeout_minutes: self.sudo_timeout_minutes,
+            max_commands: self.sudo_max_commands,
+            max_attempts: self.sudo_max_attempts,
+            lockout_minutes: self.sudo_lockout_minutes,
+        }
+    }
+}
+
+/// Terminal width assumed until the frontend reports one
+const DEFAULT_COLUMNS: usize = 80;
+
 /// Terminal session state for backend-managed commands
+#[derive(Serialize, Deserialize)]
 pub struct TerminalState {
     pub history: Vec<String>,
     pub theme: String,
+    pub env: HashMap<String, String>, // Shell variables
+    pub last_status: i32,              // Exit status of the last command ($?)
+    pub user: Credentials,             // Active identity for permission checks
+    pub umask: u16,
+    pub sessions: Vec<Session>,        // Identities stacked by `su`, popped by `exit`
+    #[serde(default)]
+    pub progress: Progress,            // Solved challenges and score
+    #[serde(skip)]
+    pub pending_auth: Option<PendingAuth>, // su/login/passwd waiting for a password
+    #[serde(skip)]
+    pub pending_removal: Option<PendingRemoval>, // `rm -i` waiting for a yes/no answer
+    #[serde(skip)]
+    pub parked: Vec<ParkedFrame>,      // Rest of a command line waiting on a prompt
+    #[serde(skip)]
+    pub config: TerminalConfig,        // Kept so `reboot` restores the same setup
+    #[serde(skip)]
+    pub columns: usize,                // Width reported by the frontend, in character cells
+    #[serde(skip)]
+    pub stdout_redirected: bool,       // Set while output feeds a pipe or file, not the screen
 }
 
 impl TerminalState {
     pub fn new() -> Self {
+        TerminalState::with_config(TerminalConfig::default())
+    }
+
+    pub fn with_config(config: TerminalConfig) -> Self {
+        let player = users::player_name();
+        let env = [
+            ("HOME", "/home"),
+            ("PATH", "/bin"),
+            ("SHELL", "/bin/sh"),
+            ("USER", player.as_str()),
+            ("LOGNAME", player.as_str()),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
         TerminalState {
             history: Vec::new(),
-            theme: "matrix".to_string(),
+            theme: config.theme.clone(),
+            env,
+            last_status: 0,
+            user: users::player_credentials(),
+            umask: 0o022,
+            sessions: Vec::new(),
+            progress: Progress::default(),
+            pending_auth: None,
+            pending_removal: None,
+            parked: Vec::new(),
+            config,
+            columns: DEFAULT_COLUMNS,
+            stdout_redirected: false,
         }
     }
 }
 
-lazy_static! {
-    static ref FS: Mutex<FileSystem> = Mutex::new(FileSystem::new());
-    static ref SUDO: Mutex<SudoState> = Mutex::new(SudoState::new());
-    static ref TERM: Mutex<TerminalState> = Mutex::new(TerminalState::new());
+impl Default for TerminalState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-/// Main WebAssembly entry point
-/// Executes a command on the virtual filesystem and returns output
+/// An independent terminal: its own filesystem, sudo state and shell session
 ///
-/// # Arguments
-/// * `input` - Command string to execute
-///
-/// # Returns
-/// Command output as a string
-#[wasm_bindgen]
-pub fn run_command(input: &str) -> String {
-    let mut fs = FS.lock().unwrap();
-    let mut sudo = SUDO.lock().unwrap();
-    let mut term = TERM.lock().unwrap();
-    commands::execute_command(&mut fs, &mut sudo, &mut term, input)
+/// A page can host several of these side by side (for example an
+/// "attacker" and a "victim" box); nothing is shared between them.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct Terminal {
+    fs: FileSystem,
+    sudo: SudoState,
+    term: TerminalState,
+    clock: Box<dyn Clock>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Terminal {
+    /// Creates a terminal with the default configuration
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> Terminal {
+        Terminal::with_config(TerminalConfig::default())
+    }
+
+    /// Creates a terminal from a JSON `TerminalConfig`
+    ///
+    /// # Arguments
+    /// * `json` - Configuration object; missing fields keep their defaults
+    #[cfg(feature = "wasm")]
+    pub fn with_config_json(json: &str) -> Result<Terminal, JsError> {
+        TerminalConfig::from_json(json)
+            .map(Terminal::with_config)
+            .map_err(|err| JsError::new(&err))
+    }
+
+    /// Executes a command and returns a structured response as JSON
+    ///
+    /// # Arguments
+    /// * `input` - Command string to execute
+    ///
+    /// # Returns
+    /// JSON object `{ stdout, stderr, status, effects }` where each effect is
+    /// tagged by `type`: `clear`, `theme`, `download`, `password_prompt`, `reboot`
+    pub fn run_command_json(&mut self, input: &str) -> String {
+        self.execute(input).to_json()
+    }
+
+    /// Executes a command and returns output in the legacy string format
+    ///
+    /// Side effects are reported as sentinel strings ("CLEARED", "REBOOT",
+    /// "THEME:<name>", "DOWNLOAD:<file>:<b64>", "[sudo] password: "), which
+    /// ordinary output can imitate. Kept for old frontends only; use
+    /// `run_command_json` and its typed effects instead.
+    ///
+    /// # Arguments
+    /// * `input` - Command string to execute
+    ///
+    /// # Returns
+    /// Command output as a string
+    pub fn run_command(&mut self, input: &str) -> String {
+        self.execute(input).to_legacy_string()
+    }
+
+    /// Prompt for the next input line, e.g. `CSE23@admin:/home$`, or the
+    /// question of a pending `rm -i`
+    pub fn prompt(&self) -> String {
+        commands::prompt(&self.fs, &self.sudo, &self.term)
+    }
+
+    /// Names in the working directory for tab completion (directories end in `/`)
+    pub fn completions(&self) -> Vec<String> {
+        commands::completions(&self.fs, &self.sudo, &self.term)
+    }
+
+    /// Cancels a pending password prompt or `rm -i` question, like Ctrl-C,
+    /// and sets `$?` to 130
+    pub fn interrupt(&mut self) {
+        commands::interrupt(&mut self.fs, &mut self.sudo, &mut self.term);
+    }
+
+    /// Records the width of the frontend's terminal, which `ls` fills with columns
+    ///
+    /// # Arguments
+    /// * `columns` - Characters per line; 0 restores the default of 80
+    pub fn set_terminal_width(&mut self, columns: u32) {
+        self.term.columns = match columns {
+            0 => DEFAULT_COLUMNS,
+            columns => columns as usize,
+        };
+    }
+
+    /// Saves the filesystem, shell session and sudo state
+    ///
+    /// # Returns
+    /// A versioned JSON snapshot for `import_state`
+    pub fn export_state(&self) -> String {
+        snapshot::export(&self.fs, &self.sudo, &self.term)
+    }
+
+    /// Replaces this terminal's state with a snapshot from `export_state`
+    ///
+    /// The terminal keeps its own configuration. On error the current state
+    /// is left untouched.
+    ///
+    /// # Arguments
+    /// * `snapshot` - JSON produced by `export_state`
+    #[cfg(feature = "wasm")]
+    pub fn import_state(&mut self, snapshot: &str) -> Result<(), JsError> {
+        self.restore(snapshot).map_err(|err| JsError::new(&err))
+    }
+}
+
+impl Terminal {
+    /// Creates a terminal with the given configuration
+    pub fn with_config(config: TerminalConfig) -> Terminal {
+        let mut fs = FileSystem::new();
+        fs.capacity = config.disk_capacity;
+        Terminal {
+            fs,
+            sudo: SudoState::with_config(config.sudo_config()),
+            term: TerminalState::with_config(config),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Replaces the time source (e.g. a `FixedClock` for reproducible sessions)
+    pub fn with_clock(mut self, clock: Box<dyn Clock>) -> Terminal {
+        self.clock = clock;
+        self
+    }
+
+    /// Executes a command and returns the structured response
+    pub fn execute(&mut self, input: &str) -> CommandResponse {
+        commands::execute_command(
+            &mut self.fs,
+            &mut self.sudo,
+            &mut self.term,
+            self.clock.as_ref(),
+            input,
+        )
+    }
+
+    /// Restores a snapshot from `export_state`, keeping this terminal's configuration
+    ///
+    /// # Returns
+    /// Why the snapshot was rejected; the current state is then left untouched
+    pub fn restore(&mut self, snapshot: &str) -> Result<(), String> {
+        let (mut fs, mut sudo, mut term) = snapshot::import(snapshot)?;
+        fs.capacity = self.fs.capacity;
+        sudo.config = self.sudo.config.clone();
+        term.config = self.term.config.clone();
+        term.columns = self.term.columns;
+
+        self.fs = fs;
+        self.sudo = sudo;
+        self.term = term;
+        Ok(())
+    }
+
+    /// Shell session state (identity, `$?`, nested `su` sessions)
+    pub fn state(&self) -> &TerminalState {
+        &self.term
+    }
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: f64 = 1_700_000_000_000.0;
+
+    /// A terminal on a fixed clock whose player password is "pw"
+    fn terminal(json: &str) -> Terminal {
+        let config = TerminalConfig::from_json(json).unwrap();
+        let mut terminal = Terminal::with_config(config).with_clock(Box::new(FixedClock(NOW)));
+        users::set_password(&mut terminal.fs, &users::player_name(), "pw", NOW).unwrap();
+        terminal
+    }
+
+    fn asks_password(response: &CommandResponse) -> bool {
+        matches!(response.effects[..], [Effect::PasswordPrompt { .. }])
+    }
+
+    #[test]
+    fn terminals_share_no_state() {
+        let mut attacker = terminal(r#"{"hostname": "attacker"}"#);
+        let mut victim = terminal(r#"{"hostname": "victim", "sudo_max_attempts": 1}"#);
+        assert!(attacker.prompt().contains("@attacker:"));
+        assert!(victim.prompt().contains("@victim:"));
+
+        attacker.execute("echo loot > /tmp/x; cd /tmp");
+        assert_eq!(attacker.execute("cat x").stdout, "loot");
+        assert!(victim.execute("cat /tmp/x").stderr.contains("No such file"));
+        assert_eq!(victim.execute("pwd").stdout, "/home");
+
+        // A sudo session on one box is not a session on the other
+        attacker.execute("sudo true");
+        attacker.execute("pw");
+        assert_eq!(attacker.execute("sudo whoami").stdout, "root");
+        assert!(asks_password(&victim.execute("sudo whoami")));
+
+        // Nor is a lockout, which follows each box's own policy
+        assert_eq!(victim.execute("wrong").stderr, "sudo: 1 incorrect password attempts");
+        assert!(victim.execute("sudo whoami").stderr.contains("account locked"));
+        attacker.execute("sudo -k");
+        assert!(asks_password(&attacker.execute("sudo whoami")));
+        assert_eq!(attacker.execute("pw").stdout, "root");
+    }
 }
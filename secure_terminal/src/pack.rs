@@ -0,0 +1,305 @@
+//! Challenge pack module
+//! Parses and validates challenge manifests (TOML). Shared by `build.rs`,
+//! which embeds the default pack, and the `ctf-author` tool
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::encryption::{is_hash_string, Cipher};
+
+/// Manifest compiled when `SECURE_TERMINAL_CHALLENGE` is not set
+pub const DEFAULT_MANIFEST: &str = "challenges/default.toml";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default)]
+    dir: Vec<DirEntry>,
+    #[serde(default)]
+    file: Vec<FileEntry>,
+    #[serde(default)]
+    symlink: Vec<SymlinkEntry>,
+    #[serde(default)]
+    challenge: Vec<ChallengeEntry>,
+    player: PlayerEntry,
+}
+
+/// `[[dir]]`: a directory to create (or re-own and chmod if it exists)
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DirEntry {
+    pub path: String,
+    #[serde(default = "default_dir_mode")]
+    pub mode: u16,
+    #[serde(default)]
+    pub uid: u32,
+    #[serde(default)]
+    pub gid: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileEntry {
+    path: String,
+    content: Option<String>,
+    source: Option<String>,
+    #[serde(default = "default_cipher")]
+    cipher: String,
+    #[serde(default = "default_file_mode")]
+    mode: u16,
+    #[serde(default)]
+    uid: u32,
+    #[serde(default)]
+    gid: u32,
+}
+
+/// `[[symlink]]`: a symbolic link
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SymlinkEntry {
+    pub path: String,
+    pub target: String,
+    #[serde(default)]
+    pub uid: u32,
+    #[serde(default)]
+    pub gid: u32,
+}
+
+/// `[player]`: the player's account, seeded into /etc/shadow by the user database
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlayerEntry {
+    /// Hash of the login password, which also unlocks sudo, as printed by
+    /// `ctf-author hash-flag`
+    pub password_hash: String,
+}
+
+/// `[[challenge]]`: a flag players can `submit`, stored only as a salted hash
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChallengeEntry {
+    pub id: String,
+    pub name: String,
+    pub points: u32,
+    /// `$pbkdf2-sha256$<salt>$<hex>`, as printed by `ctf-author hash-flag`
+    pub flag_hash: String,
+    #[serde(default)]
+    pub description: String,
+    /// Tiered hints, revealed in order
+    #[serde(default, rename = "hint")]
+    pub hints: Vec<HintEntry>,
+}
+
+/// `[[challenge.hint]]`: one hint tier, encrypted into the build like file content
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HintEntry {
+    pub text: String,
+    /// Points deducted from the score when revealed
+    #[serde(default)]
+    pub cost: u32,
+    /// Minutes after the session starts before the hint can be revealed
+    #[serde(default)]
+    pub delay_minutes: f64,
+}
+
+/// `[[file]]` with its plaintext loaded and its cipher resolved
+#[derive(Clone, Debug)]
+pub struct PackFile {
+    pub path: String,
+    pub cipher: Cipher,
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    pub plaintext: Vec<u8>,
+}
+
+/// A validated challenge pack
+#[derive(Clone, Debug, Default)]
+pub struct Pack {
+    pub dirs: Vec<DirEntry>,
+    pub files: Vec<PackFile>,
+    pub symlinks: Vec<SymlinkEntry>,
+    pub challenges: Vec<ChallengeEntry>,
+    pub player: PlayerEntry,
+    /// Files read through `source`, so builds can track them
+    pub sources: Vec<PathBuf>,
+}
+
+fn default_dir_mode() -> u16 {
+    0o755
+}
+
+fn default_file_mode() -> u16 {
+    0o644
+}
+
+fn default_cipher() -> String {
+    "aes".to_string()
+}
+
+/// Parses a cipher name as written in manifests
+pub fn parse_cipher(name: &str) -> Option<Cipher> {
+    match name {
+        "aes" => Some(Cipher::Aes),
+        "xor" => Some(Cipher::Xor),
+        "plain" => Some(Cipher::Plain),
+        _ => None,
+    }
+}
+
+/// Reads and validates a manifest file
+///
+/// # Arguments
+/// * `manifest` - Path to the TOML manifest; `source` paths are relative to its directory
+///
+/// # Returns
+/// The pack, or an error message prefixed with the manifest path
+pub fn load(manifest: &Path) -> Result<Pack, String> {
+    let text = fs::read_to_string(manifest).map_err(|err| err.to_string());
+    let base = manifest.parent().unwrap_or(Path::new("."));
+    text.and_then(|text| parse(&text, base))
+        .map_err(|err| format!("{}: {}", manifest.display(), err))
+}
+
+/// Parses and validates manifest text
+///
+/// Entries are created in order: every directory's parent must be `/` or
+/// an earlier directory, and files and symlinks must live in a declared
+/// directory. Paths must be absolute, normalized and unique.
+pub fn parse(text: &str, base: &Path) -> Result<Pack, String> {
+    let manifest: Manifest = toml::from_str(text).map_err(|err| err.to_string())?;
+
+    let mut pack = Pack::default();
+    let mut dirs: HashSet<&str> = HashSet::from(["/"]);
+    let mut seen: HashSet<&str> = HashSet::new();
+
+    for dir in &manifest.dir {
+        check_entry(&dir.path, &dirs, &mut seen)?;
+        check_mode(&dir.path, dir.mode)?;
+        dirs.insert(&dir.path);
+        pack.dirs.push(dir.clone());
+    }
+
+    for file in &manifest.file {
+        check_entry(&file.path, &dirs, &mut seen)?;
+        check_mode(&file.path, file.mode)?;
+        let cipher = parse_cipher(&file.cipher)
+            .ok_or_else(|| format!("{}: unknown cipher '{}'", file.path, file.cipher))?;
+
+        let plaintext = match (&file.content, &file.source) {
+            (Some(content), None) => content.as_bytes().to_vec(),
+            (None, Some(source)) => {
+                let source = base.join(source);
+                let bytes = fs::read(&source)
+                    .map_err(|err| format!("{}: {}: {}", file.path, source.display(), err))?;
+                pack.sources.push(source);
+                bytes
+            }
+            _ => return Err(format!("{}: set exactly one of content or source", file.path)),
+        };
+
+        pack.files.push(PackFile {
+            path: file.path.clone(),
+            cipher,
+            mode: file.mode,
+            uid: file.uid,
+            gid: file.gid,
+            plaintext,
+        });
+    }
+
+    for link in &manifest.symlink {
+        check_entry(&link.path, &dirs, &mut seen)?;
+        if link.target.is_empty() {
+            return Err(format!("{}: empty symlink target", link.path));
+        }
+        pack.symlinks.push(link.clone());
+    }
+
+    let mut ids = HashSet::new();
+    for challenge in &manifest.challenge {
+        let id_valid = !challenge.id.is_empty()
+            && challenge
+                .id
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if !id_valid {
+            return Err(format!(
+                "challenge '{}': ids use lowercase letters, digits, '-' and '_'",
+                challenge.id
+            ));
+        }
+        if !ids.insert(challenge.id.as_str()) {
+            return Err(format!("challenge {}: declared more than once", challenge.id));
+        }
+        if challenge.name.trim().is_empty() {
+            return Err(format!("challenge {}: empty name", challenge.id));
+        }
+        if !is_hash_string(&challenge.flag_hash) {
+            return Err(format!(
+                "challenge {}: flag_hash must come from `ctf-author hash-flag`",
+                challenge.id
+            ));
+        }
+        for (tier, hint) in challenge.hints.iter().enumerate() {
+            if hint.text.trim().is_empty() {
+                return Err(format!("challenge {}: hint {} is empty", challenge.id, tier + 1));
+            }
+            if !hint.delay_minutes.is_finite() || hint.delay_minutes < 0.0 {
+                return Err(format!(
+                    "challenge {}: hint {} has an invalid delay",
+                    challenge.id,
+                    tier + 1
+                ));
+            }
+        }
+        pack.challenges.push(challenge.clone());
+    }
+
+    if !is_hash_string(&manifest.player.password_hash) {
+        return Err("player: password_hash must come from `ctf-author hash-flag`".to_string());
+    }
+    pack.player = manifest.player.clone();
+
+    Ok(pack)
+}
+
+/// Checks that a path is normalized, unique and inside a declared directory
+fn check_entry<'a>(
+    path: &'a str,
+    dirs: &HashSet<&str>,
+    seen: &mut HashSet<&'a str>,
+) -> Result<(), String> {
+    let normalized = path.starts_with('/')
+        && path != "/"
+        && path[1..]
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..");
+    if !normalized {
+        return Err(format!("'{}' is not a normalized absolute path", path));
+    }
+    if !seen.insert(path) {
+        return Err(format!("{}: declared more than once", path));
+    }
+
+    let parent = match path.rfind('/') {
+        Some(0) => "/",
+        Some(idx) => &path[..idx],
+        None => unreachable!("absolute paths contain '/'"),
+    };
+    if !dirs.contains(parent) {
+        return Err(format!("{}: parent directory {} is not declared before it", path, parent));
+    }
+    Ok(())
+}
+
+fn check_mode(path: &str, mode: u16) -> Result<(), String> {
+    if mode > 0o7777 {
+        return Err(format!("{}: mode {:o} is out of range", path, mode));
+    }
+    Ok(())
+}
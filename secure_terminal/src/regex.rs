@@ -0,0 +1,857 @@
+//! Regular expression module
+//! POSIX basic and extended regular expressions (with the common GNU
+//! extensions) and shell globs, compiled to a Thompson NFA and run by a Pike
+//! VM, so matching is linear in the input and cannot blow up on `(a*)*b`
+
+use std::fmt;
+
+/// Repetition counts above this are rejected (`x{1000}` would copy `x` 1000 times)
+const MAX_REPEAT: u32 = 255;
+
+/// Which POSIX dialect a pattern is written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// BRE (`grep`): `\(`, `\)`, `\{`, `\}`, `\|`, `\+`, `\?` are operators
+    Basic,
+    /// ERE (`grep -E`): `(`, `)`, `{`, `}`, `|`, `+`, `?` are operators
+    Extended,
+}
+
+/// Reasons a pattern fails to compile, worded like GNU grep
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegexError {
+    UnmatchedParen,
+    UnmatchedBracket,
+    UnmatchedBrace,
+    InvalidInterval,
+    InvalidClassName,
+    InvalidRange,
+    TrailingBackslash,
+    TooBig,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RegexError::UnmatchedParen => "Unmatched ( or \\(",
+            RegexError::UnmatchedBracket => "Unmatched [, [^, [:, [., or [=",
+            RegexError::UnmatchedBrace => "Unmatched \\{",
+            RegexError::InvalidInterval => "Invalid content of \\{\\}",
+            RegexError::InvalidClassName => "Invalid character class name",
+            RegexError::InvalidRange => "Invalid range end",
+            RegexError::TrailingBackslash => "Trailing backslash",
+            RegexError::TooBig => "Regular expression too big",
+        })
+    }
+}
+
+impl std::error::Error for RegexError {}
+
+/// `[:name:]` classes, also used for `\w` and `\s`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Named {
+    Alnum,
+    Alpha,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Print,
+    Punct,
+    Space,
+    Upper,
+    Xdigit,
+    Word,
+}
+
+impl Named {
+    fn parse(name: &str) -> Option<Named> {
+        Some(match name {
+            "alnum" => Named::Alnum,
+            "alpha" => Named::Alpha,
+            "blank" => Named::Blank,
+            "cntrl" => Named::Cntrl,
+            "digit" => Named::Digit,
+            "graph" => Named::Graph,
+            "lower" => Named::Lower,
+            "print" => Named::Print,
+            "punct" => Named::Punct,
+            "space" => Named::Space,
+            "upper" => Named::Upper,
+            "xdigit" => Named::Xdigit,
+            _ => return None,
+        })
+    }
+
+    fn contains(self, c: char) -> bool {
+        match self {
+            Named::Alnum => c.is_alphanumeric(),
+            Named::Alpha => c.is_alphabetic(),
+            Named::Blank => c == ' ' || c == '\t',
+            Named::Cntrl => c.is_control(),
+            Named::Digit => c.is_ascii_digit(),
+            Named::Graph => !c.is_whitespace() && !c.is_control(),
+            Named::Lower => c.is_lowercase(),
+            Named::Print => !c.is_control(),
+            Named::Punct => c.is_ascii_punctuation(),
+            Named::Space => c.is_whitespace(),
+            Named::Upper => c.is_uppercase(),
+            Named::Xdigit => c.is_ascii_hexdigit(),
+            Named::Word => is_word_char(c),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Clone, Debug)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Named(Named),
+}
+
+/// A bracket expression (or `\w`-style shorthand)
+#[derive(Clone, Debug)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+impl Class {
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let hit = |c: char| {
+            self.items.iter().any(|item| match *item {
+                ClassItem::Char(x) => x == c,
+                ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+                ClassItem::Named(named) => named.contains(c),
+            })
+        };
+        let found = if ignore_case {
+            hit(c) || c.to_lowercase().any(hit) || c.to_uppercase().any(hit)
+        } else {
+            hit(c)
+        };
+        found != self.negated
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Assertion {
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    NotWordBoundary,
+    WordStart,
+    WordEnd,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Empty,
+    Literal(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+    },
+}
+
+/// Recursive-descent parser over the pattern's characters
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    syntax: Syntax,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn extended(&self) -> bool {
+        self.syntax == Syntax::Extended
+    }
+
+    /// Whether the input continues with `op`, written bare in ERE and escaped in BRE
+    fn at_operator(&self, op: char) -> bool {
+        if self.extended() {
+            self.peek() == Some(op)
+        } else {
+            self.peek() == Some('\\') && self.peek_at(1) == Some(op)
+        }
+    }
+
+    fn eat_operator(&mut self) {
+        self.pos += if self.extended() { 1 } else { 2 };
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_branch()?];
+        while self.at_operator('|') {
+            self.eat_operator();
+            branches.push(self.parse_branch()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap_or(Node::Empty)
+        } else {
+            Node::Alternate(branches)
+        })
+    }
+
+    /// A top-level `)` ends the branch only in BRE, where `new` rejects it as unmatched
+    fn at_branch_end(&self) -> bool {
+        self.peek().is_none()
+            || self.at_operator('|')
+            || ((self.depth > 0 || !self.extended()) && self.at_operator(')'))
+    }
+
+    fn parse_branch(&mut self) -> Result<Node, RegexError> {
+        let mut items: Vec<Node> = Vec::new();
+        while !self.at_branch_end() {
+            let atom = self.parse_atom(items.is_empty())?;
+            let atom = self.parse_postfix(atom)?;
+            items.push(atom);
+        }
+        Ok(match items.len() {
+            0 => Node::Empty,
+            1 => items.pop().unwrap_or(Node::Empty),
+            _ => Node::Concat(items),
+        })
+    }
+
+    /// Parses one atom; `branch_start` makes a leading `*` literal and `^` an anchor in BRE
+    fn parse_atom(&mut self, branch_start: bool) -> Result<Node, RegexError> {
+        let c = self.peek().ok_or(RegexError::TrailingBackslash)?;
+
+        if self.at_operator('(') {
+            self.eat_operator();
+            self.depth += 1;
+            let inner = self.parse_alternation()?;
+            if !self.at_operator(')') {
+                return Err(RegexError::UnmatchedParen);
+            }
+            self.eat_operator();
+            self.depth -= 1;
+            return Ok(inner);
+        }
+
+        self.pos += 1;
+        match c {
+            '.' => Ok(Node::Any),
+            '[' => self.parse_bracket(),
+            '^' if self.extended() || branch_start => Ok(Node::Assert(Assertion::LineStart)),
+            '$' if self.extended() || self.at_branch_end() => Ok(Node::Assert(Assertion::LineEnd)),
+            // A repetition with nothing to repeat is an ordinary character
+            '*' if branch_start => Ok(Node::Literal('*')),
+            '*' | '+' | '?' if self.extended() && branch_start => Ok(Node::Literal(c)),
+            '\\' => self.parse_escape(),
+            _ => Ok(Node::Literal(c)),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, RegexError> {
+        let c = self.peek().ok_or(RegexError::TrailingBackslash)?;
+        self.pos += 1;
+        let shorthand = |named, negated| {
+            Node::Class(Class {
+                negated,
+                items: vec![ClassItem::Named(named)],
+            })
+        };
+        Ok(match c {
+            'w' => shorthand(Named::Word, false),
+            'W' => shorthand(Named::Word, true),
+            's' => shorthand(Named::Space, false),
+            'S' => shorthand(Named::Space, true),
+            'b' => Node::Assert(Assertion::WordBoundary),
+            'B' => Node::Assert(Assertion::NotWordBoundary),
+            '<' => Node::Assert(Assertion::WordStart),
+            '>' => Node::Assert(Assertion::WordEnd),
+            _ => Node::Literal(c),
+        })
+    }
+
+    fn parse_bracket(&mut self) -> Result<Node, RegexError> {
+        let mut class = Class {
+            negated: false,
+            items: Vec::new(),
+        };
+        if self.peek() == Some('^') {
+            class.negated = true;
+            self.pos += 1;
+        }
+
+        let mut first = true;
+        loop {
+            let c = self.peek().ok_or(RegexError::UnmatchedBracket)?;
+            self.pos += 1;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            if c == '[' && self.peek() == Some(':') {
+                let rest: String = self.chars[self.pos + 1..].iter().collect();
+                let end = rest.find(":]").ok_or(RegexError::UnmatchedBracket)?;
+                let named = Named::parse(&rest[..end]).ok_or(RegexError::InvalidClassName)?;
+                self.pos += 1 + rest[..end].chars().count() + 2;
+                class.items.push(ClassItem::Named(named));
+                continue;
+            }
+
+            // `a-z`, but a `-` before the closing `]` is literal
+            if self.peek() == Some('-') && self.peek_at(1).is_some_and(|next| next != ']') {
+                let hi = self.peek_at(1).ok_or(RegexError::UnmatchedBracket)?;
+                self.pos += 2;
+                if hi < c {
+                    return Err(RegexError::InvalidRange);
+                }
+                class.items.push(ClassItem::Range(c, hi));
+            } else {
+                class.items.push(ClassItem::Char(c));
+            }
+        }
+        Ok(Node::Class(class))
+    }
+
+    /// Applies any `*`, `+`, `?` and `{m,n}` operators following an atom
+    fn parse_postfix(&mut self, mut atom: Node) -> Result<Node, RegexError> {
+        loop {
+            let (min, max) = if self.peek() == Some('*') {
+                self.pos += 1;
+                (0, None)
+            } else if self.at_operator('+') {
+                self.eat_operator();
+                (1, None)
+            } else if self.at_operator('?') {
+                self.eat_operator();
+                (0, Some(1))
+            } else if self.at_operator('{') {
+                let start = self.pos;
+                self.eat_operator();
+                match self.parse_interval()? {
+                    Some(bounds) => bounds,
+                    // In ERE a `{` that does not start an interval is literal
+                    None => {
+                        self.pos = start;
+                        return Ok(atom);
+                    }
+                }
+            } else {
+                return Ok(atom);
+            };
+
+            atom = Node::Repeat {
+                node: Box::new(atom),
+                min,
+                max,
+            };
+        }
+    }
+
+    /// Parses `m}`, `m,}`, `m,n}` or `,n}` after the opening brace
+    fn parse_interval(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let number = |parser: &mut Parser| {
+            let start = parser.pos;
+            while parser.peek().is_some_and(|c| c.is_ascii_digit()) {
+                parser.pos += 1;
+            }
+            let digits: String = parser.chars[start..parser.pos].iter().collect();
+            (!digits.is_empty()).then(|| digits.parse::<u32>().unwrap_or(u32::MAX))
+        };
+
+        let min = number(self);
+        let (has_comma, max) = if self.peek() == Some(',') {
+            self.pos += 1;
+            (true, number(self))
+        } else {
+            (false, min)
+        };
+
+        let closed = if self.extended() {
+            self.peek() == Some('}')
+        } else {
+            self.peek() == Some('\\') && self.peek_at(1) == Some('}')
+        };
+        let empty = min.is_none() && (!has_comma || max.is_none());
+        if !closed || empty {
+            if self.extended() {
+                return Ok(None);
+            }
+            return Err(if self.peek().is_none() {
+                RegexError::UnmatchedBrace
+            } else {
+                RegexError::InvalidInterval
+            });
+        }
+        self.eat_operator();
+
+        let min = min.unwrap_or(0);
+        if max.is_some_and(|max| max < min) {
+            return Err(RegexError::InvalidInterval);
+        }
+        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+            return Err(RegexError::TooBig);
+        }
+        Ok(Some((min, max)))
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(usize),
+    Assert(Assertion),
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// Upper bound on compiled program size
+const MAX_PROGRAM: usize = 100_000;
+
+struct Compiler {
+    prog: Vec<Inst>,
+    classes: Vec<Class>,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> Result<usize, RegexError> {
+        if self.prog.len() >= MAX_PROGRAM {
+            return Err(RegexError::TooBig);
+        }
+        self.prog.push(inst);
+        Ok(self.prog.len() - 1)
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), RegexError> {
+        match node {
+            Node::Empty => {}
+            Node::Literal(c) => {
+                self.emit(Inst::Char(*c))?;
+            }
+            Node::Any => {
+                self.emit(Inst::Any)?;
+            }
+            Node::Class(class) => {
+                self.classes.push(class.clone());
+                self.emit(Inst::Class(self.classes.len() - 1))?;
+            }
+            Node::Assert(assertion) => {
+                self.emit(Inst::Assert(*assertion))?;
+            }
+            Node::Concat(items) => {
+                for item in items {
+                    self.compile(item)?;
+                }
+            }
+            Node::Alternate(branches) => {
+                // split L1, next; L1: branch; jmp end; next: split ...
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 < branches.len() {
+                        let split = self.emit(Inst::Split(0, 0))?;
+                        self.compile(branch)?;
+                        jumps.push(self.emit(Inst::Jump(0))?);
+                        let next = self.prog.len();
+                        self.prog[split] = Inst::Split(split + 1, next);
+                    } else {
+                        self.compile(branch)?;
+                    }
+                }
+                let end = self.prog.len();
+                for jump in jumps {
+                    self.prog[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat { node, min, max } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                match max {
+                    None => {
+                        // loop: split body, end; body; jmp loop
+                        let split = self.emit(Inst::Split(0, 0))?;
+                        self.compile(node)?;
+                        self.emit(Inst::Jump(split))?;
+                        let end = self.prog.len();
+                        self.prog[split] = Inst::Split(split + 1, end);
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Split(0, 0))?);
+                            self.compile(node)?;
+                        }
+                        let end = self.prog.len();
+                        for split in splits {
+                            self.prog[split] = Inst::Split(split + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A compiled regular expression
+#[derive(Clone, Debug)]
+pub struct Regex {
+    prog: Vec<Inst>,
+    classes: Vec<Class>,
+    ignore_case: bool,
+}
+
+impl Regex {
+    /// Compiles a pattern
+    ///
+    /// # Arguments
+    /// * `pattern` - Pattern text
+    /// * `syntax` - `Basic` (grep) or `Extended` (grep -E)
+    /// * `ignore_case` - Match letters regardless of case
+    pub fn new(pattern: &str, syntax: Syntax, ignore_case: bool) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            syntax,
+            depth: 0,
+        };
+        let mut node = parser.parse_alternation()?;
+        // Only an unbalanced `)` can stop the top-level parse early; in ERE it is literal
+        while parser.pos < parser.chars.len() {
+            if parser.extended() {
+                parser.pos += 1;
+                let rest = parser.parse_alternation()?;
+                node = Node::Concat(vec![node, Node::Literal(')'), rest]);
+            } else {
+                return Err(RegexError::UnmatchedParen);
+            }
+        }
+
+        Regex::from_node(&node, ignore_case)
+    }
+
+    /// Compiles a shell glob (`*`, `?`, `[...]`, `[!...]`) that must match the whole text
+    ///
+    /// Like `fnmatch` without `FNM_PERIOD`, `*` also matches a leading dot; a `[`
+    /// without a closing `]` is literal.
+    pub fn glob(pattern: &str, ignore_case: bool) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            syntax: Syntax::Basic,
+            depth: 0,
+        };
+        let mut items = vec![Node::Assert(Assertion::LineStart)];
+        while let Some(c) = parser.peek() {
+            parser.pos += 1;
+            items.push(match c {
+                '*' => Node::Repeat {
+                    node: Box::new(Node::Any),
+                    min: 0,
+                    max: None,
+                },
+                '?' => Node::Any,
+                '[' => {
+                    let start = parser.pos;
+                    let negated = parser.peek() == Some('!');
+                    if negated {
+                        parser.chars[start] = '^';
+                    }
+                    match parser.parse_bracket() {
+                        Ok(class) => class,
+                        Err(_) => {
+                            if negated {
+                                parser.chars[start] = '!';
+                            }
+                            parser.pos = start;
+                            Node::Literal('[')
+                        }
+                    }
+                }
+                '\\' => match parser.peek() {
+                    Some(escaped) => {
+                        parser.pos += 1;
+                        Node::Literal(escaped)
+                    }
+                    None => Node::Literal('\\'),
+                },
+                _ => Node::Literal(c),
+            });
+        }
+        items.push(Node::Assert(Assertion::LineEnd));
+        Regex::from_node(&Node::Concat(items), ignore_case)
+    }
+
+    fn from_node(node: &Node, ignore_case: bool) -> Result<Regex, RegexError> {
+        let mut compiler = Compiler {
+            prog: Vec::new(),
+            classes: Vec::new(),
+        };
+        compiler.compile(node)?;
+        compiler.emit(Inst::Match)?;
+        Ok(Regex {
+            prog: compiler.prog,
+            classes: compiler.classes,
+            ignore_case,
+        })
+    }
+
+    fn char_matches(&self, want: char, c: char) -> bool {
+        want == c
+            || (self.ignore_case
+                && (want.to_lowercase().eq(c.to_lowercase())
+                    || want.to_uppercase().eq(c.to_uppercase())))
+    }
+
+    fn assertion_holds(assertion: Assertion, chars: &[char], pos: usize) -> bool {
+        let before = pos > 0 && is_word_char(chars[pos - 1]);
+        let after = pos < chars.len() && is_word_char(chars[pos]);
+        match assertion {
+            Assertion::LineStart => pos == 0,
+            Assertion::LineEnd => pos == chars.len(),
+            Assertion::WordBoundary => before != after,
+            Assertion::NotWordBoundary => before == after,
+            Assertion::WordStart => !before && after,
+            Assertion::WordEnd => before && !after,
+        }
+    }
+
+    /// Adds `pc` and everything reachable through jumps, splits and holding
+    /// assertions to `list`
+    ///
+    /// # Returns
+    /// Whether a `Match` instruction was reached
+    fn add_thread(
+        &self,
+        list: &mut Vec<usize>,
+        marks: &mut [usize],
+        generation: usize,
+        pc: usize,
+        chars: &[char],
+        pos: usize,
+    ) -> bool {
+        let mut stack = vec![pc];
+        let mut matched = false;
+        while let Some(pc) = stack.pop() {
+            if marks[pc] == generation {
+                continue;
+            }
+            marks[pc] = generation;
+            match self.prog[pc] {
+                Inst::Jump(to) => stack.push(to),
+                Inst::Split(first, second) => {
+                    stack.push(second);
+                    stack.push(first);
+                }
+                Inst::Assert(assertion) => {
+                    if Self::assertion_holds(assertion, chars, pos) {
+                        stack.push(pc + 1);
+                    }
+                }
+                Inst::Match => matched = true,
+                _ => list.push(pc),
+            }
+        }
+        matched
+    }
+
+    /// Whether the pattern matches anywhere in `text` (one line)
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let mut marks = vec![usize::MAX; self.prog.len()];
+        let mut current: Vec<usize> = Vec::new();
+        let mut next: Vec<usize> = Vec::new();
+
+        for pos in 0..=chars.len() {
+            // Unanchored search: a new thread starts at every position
+            if self.add_thread(&mut current, &mut marks, pos, 0, &chars, pos) {
+                return true;
+            }
+            let Some(&c) = chars.get(pos) else {
+                break;
+            };
+
+            for &pc in &current {
+                let advances = match self.prog[pc] {
+                    Inst::Char(want) => self.char_matches(want, c),
+                    Inst::Any => true,
+                    Inst::Class(index) => self.classes[index].matches(c, self.ignore_case),
+                    _ => false,
+                };
+                let generation = pos + 1;
+                if advances
+                    && self.add_thread(&mut next, &mut marks, generation, pc + 1, &chars, pos + 1)
+                {
+                    return true;
+                }
+            }
+            current.clear();
+            std::mem::swap(&mut current, &mut next);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bre(pattern: &str) -> Regex {
+        Regex::new(pattern, Syntax::Basic, false).expect("valid BRE")
+    }
+
+    fn ere(pattern: &str) -> Regex {
+        Regex::new(pattern, Syntax::Extended, false).expect("valid ERE")
+    }
+
+    #[test]
+    fn alternation() {
+        let re = ere("cat|dog");
+        assert!(re.is_match("hotdog"));
+        assert!(re.is_match("concatenate"));
+        assert!(!re.is_match("cow"));
+
+        let re = ere("^(ab|cd)+$");
+        assert!(re.is_match("abcdab"));
+        assert!(!re.is_match("abc"));
+
+        // An empty branch matches the empty string
+        assert!(ere("x|").is_match("anything"));
+        assert!(bre("cat\\|dog").is_match("dog"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(bre("^abc").is_match("abcdef"));
+        assert!(!bre("^abc").is_match("xabc"));
+        assert!(bre("abc$").is_match("xabc"));
+        assert!(!bre("abc$").is_match("abcx"));
+        assert!(bre("^$").is_match(""));
+        assert!(!bre("^$").is_match(" "));
+
+        // In BRE `^` and `$` are only anchors at the ends of a branch
+        assert!(bre("a^b").is_match("a^b"));
+        assert!(bre("a$b").is_match("a$b"));
+        assert!(!ere("a^b").is_match("a^b"));
+
+        assert!(bre("\\<is\\>").is_match("this is it"));
+        assert!(!bre("\\<is\\>").is_match("this"));
+        assert!(bre("\\bfoo\\b").is_match("a foo."));
+        assert!(bre("o\\B").is_match("foo"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(bre("[abc]").is_match("xbx"));
+        assert!(!bre("^[^abc]*$").is_match("xbx"));
+        assert!(bre("^[a-z0-9_]*$").is_match("user_42"));
+        assert!(bre("[[:digit:]]").is_match("v2"));
+        assert!(!bre("[[:space:]]").is_match("nospace"));
+        assert!(bre("\\w\\s\\W").is_match("a .b"));
+
+        // `]` first and `-` last are literal
+        assert!(bre("[]x]").is_match("]"));
+        assert!(bre("^[a-]$").is_match("-"));
+
+        assert!(Regex::new("[A-Z]", Syntax::Basic, true).expect("valid").is_match("q"));
+        assert_eq!(bre_error("[[:bogus:]]"), RegexError::InvalidClassName);
+        assert_eq!(bre_error("[z-a]"), RegexError::InvalidRange);
+        assert_eq!(bre_error("[abc"), RegexError::UnmatchedBracket);
+    }
+
+    #[test]
+    fn intervals() {
+        let re = ere("^a{2,3}$");
+        assert!(!re.is_match("a"));
+        assert!(re.is_match("aa"));
+        assert!(re.is_match("aaa"));
+        assert!(!re.is_match("aaaa"));
+
+        assert!(ere("^a{2}$").is_match("aa"));
+        assert!(ere("^a{2,}$").is_match("aaaaa"));
+        assert!(ere("^a{,2}$").is_match(""));
+        assert!(bre("^a\\{2\\}$").is_match("aa"));
+
+        // In ERE a brace that does not start an interval is literal
+        assert!(ere("a{x").is_match("a{x"));
+        assert_eq!(bre_error("a\\{2"), RegexError::UnmatchedBrace);
+        assert_eq!(bre_error("a\\{3,1\\}"), RegexError::InvalidInterval);
+        assert_eq!(bre_error("a\\{256\\}"), RegexError::TooBig);
+    }
+
+    #[test]
+    fn backtracking_edge_cases() {
+        // Nested stars must not blow up, and must still fail when there is no `b`
+        let text = "a".repeat(40);
+        assert!(!ere("(a*)*b").is_match(&text));
+        assert!(ere("(a*)*b").is_match(&format!("{}b", text)));
+        assert!(!ere("^(a|aa)+$").is_match(&format!("{}c", text)));
+
+        // The second alternative has to be tried after the first one fails later on
+        assert!(ere("^(a|ab)c$").is_match("abc"));
+        assert!(ere("^(a*)ab$").is_match("aaab"));
+        assert!(ere("^(x?)*y$").is_match("y"));
+        assert!(bre("^.*.*=.*$").is_match("a=b"));
+    }
+
+    #[test]
+    fn basic_versus_extended() {
+        // Bare operators are literal in BRE
+        assert!(bre("a+").is_match("a+"));
+        assert!(!bre("a+").is_match("aa"));
+        assert!(bre("(a)").is_match("(a)"));
+        assert!(bre("a|b").is_match("a|b"));
+        assert!(!bre("a|b").is_match("a"));
+
+        // ...and escaped operators are operators
+        assert!(bre("^a\\+$").is_match("aaa"));
+        assert!(bre("^\\(ab\\)*$").is_match("abab"));
+        assert!(bre("^colou\\?r$").is_match("color"));
+
+        assert!(ere("^a+$").is_match("aaa"));
+        assert!(ere("^(ab)*$").is_match("abab"));
+
+        // A leading `*` has nothing to repeat and is literal in both
+        assert!(bre("*a").is_match("*a"));
+        assert!(ere("*a").is_match("*a"));
+
+        assert_eq!(bre_error("\\(a"), RegexError::UnmatchedParen);
+        assert_eq!(bre_error("a\\)"), RegexError::UnmatchedParen);
+        assert!(ere("a)").is_match("a)"));
+        assert_eq!(bre_error("a\\"), RegexError::TrailingBackslash);
+    }
+
+    #[test]
+    fn globs() {
+        let glob = |pattern| Regex::glob(pattern, false).expect("valid glob");
+        assert!(glob("*.txt").is_match(".notes.txt"));
+        assert!(!glob("*.txt").is_match("notes.txt.bak"));
+        assert!(glob("file?").is_match("file1"));
+        assert!(glob("[!a]*").is_match("bcd"));
+        assert!(!glob("[!a]*").is_match("abc"));
+        assert!(glob("[ab").is_match("[ab"));
+        assert!(glob("\\*").is_match("*"));
+        assert!(!glob("\\*").is_match("x"));
+    }
+
+    fn bre_error(pattern: &str) -> RegexError {
+        Regex::new(pattern, Syntax::Basic, false).expect_err("invalid BRE")
+    }
+}
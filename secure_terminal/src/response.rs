@@ -0,0 +1,115 @@
+//! Command response module
+//! Structured result of a command: output streams, exit status and side effects
+
+use serde::Serialize;
+
+/// A side effect the frontend must apply after a command runs
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Effect {
+    /// Clear the terminal output
+    Clear,
+    /// Switch to the named color theme
+    Theme { name: String },
+    /// Offer a file download (content is Base64 encoded)
+    Download { filename: String, data: String },
+    /// Read the next line as a hidden password
+    PasswordPrompt { prompt: String },
+    /// Terminal state was reset
+    Reboot,
+}
+
+/// Result of executing a single command line
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CommandResponse {
+    pub stdout: String,
+    pub stderr: String,
+    pub status: i32,
+    pub effects: Vec<Effect>,
+}
+
+impl CommandResponse {
+    /// Successful response with no output
+    pub fn empty() -> Self {
+        CommandResponse::default()
+    }
+
+    /// Successful response writing `text` to stdout
+    pub fn ok(text: impl Into<String>) -> Self {
+        CommandResponse {
+            stdout: text.into(),
+            ..CommandResponse::default()
+        }
+    }
+
+    /// Failed response (status 1) writing `text` to stderr
+    pub fn error(text: impl Into<String>) -> Self {
+        CommandResponse {
+            stderr: text.into(),
+            status: 1,
+            ..CommandResponse::default()
+        }
+    }
+
+    /// Successful response carrying a single side effect
+    pub fn effect(effect: Effect) -> Self {
+        CommandResponse::empty().with_effect(effect)
+    }
+
+    /// Appends a side effect to the response
+    pub fn with_effect(mut self, effect: Effect) -> Self {
+        self.effects.push(effect);
+        self
+    }
+
+    /// Appends another command's output, effects and status to this response
+    ///
+    /// Output chunks are separated by a newline unless one is already present.
+    pub fn append(&mut self, other: CommandResponse) {
+        push_chunk(&mut self.stdout, &other.stdout);
+        push_chunk(&mut self.stderr, &other.stderr);
+        self.effects.extend(other.effects);
+        self.status = other.status;
+    }
+
+    /// Serializes the response as a JSON object
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    /// Renders the response in the legacy single-string format
+    ///
+    /// Effects are encoded as the sentinel strings older frontends match on
+    /// ("CLEARED", "REBOOT", "THEME:<name>", "DOWNLOAD:<file>:<b64>", the
+    /// password prompt and "[sudo] Sorry, try again."). Otherwise stdout and
+    /// stderr are joined by a newline.
+    pub fn to_legacy_string(&self) -> String {
+        if let Some(effect) = self.effects.first() {
+            return match effect {
+                Effect::Clear => "CLEARED".to_string(),
+                Effect::Reboot => "REBOOT".to_string(),
+                Effect::Theme { name } => format!("THEME:{}", name),
+                Effect::Download { filename, data } => format!("DOWNLOAD:{}:{}", filename, data),
+                // Older frontends expect "[sudo] <reason>" when a retry is requested
+                Effect::PasswordPrompt { prompt } if self.stderr.is_empty() => prompt.clone(),
+                Effect::PasswordPrompt { .. } => format!("[sudo] {}", self.stderr),
+            };
+        }
+
+        match (self.stdout.is_empty(), self.stderr.is_empty()) {
+            (_, true) => self.stdout.clone(),
+            (true, false) => self.stderr.clone(),
+            (false, false) => format!("{}\n{}", self.stdout, self.stderr),
+        }
+    }
+}
+
+fn push_chunk(buffer: &mut String, chunk: &str) {
+    if chunk.is_empty() {
+        return;
+    }
+    if !buffer.is_empty() && !buffer.ends_with('\n') {
+        buffer.push('\n');
+    }
+    buffer.push_str(chunk);
+}
@@ -0,0 +1,159 @@
+//! Scoring module
+//! Checks submitted flags against the challenge registry's salted hashes
+//! and tracks the player's solves, revealed hints, points and solve times
+
+use serde::{Deserialize, Serialize};
+
+use crate::challenge::{ChallengeSpec, CHALLENGES};
+use crate::encryption::verify_hash_string;
+
+/// A solved challenge
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Solve {
+    pub id: String,
+    pub points: u32,
+    pub solved_at: f64, // ms since the epoch
+}
+
+/// A revealed hint tier and the points it cost
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HintUse {
+    pub id: String,
+    pub cost: u32,
+}
+
+/// Solves and hints for one player, kept in `TerminalState` (and its snapshots)
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Progress {
+    /// First command of the session; solve times and hint delays count from here
+    pub started_at: Option<f64>,
+    pub solves: Vec<Solve>,
+    #[serde(default)]
+    pub hints: Vec<HintUse>,
+}
+
+/// Outcome of `Progress::submit`
+pub enum Submission {
+    Solved(&'static ChallengeSpec),
+    AlreadySolved(&'static ChallengeSpec),
+    Incorrect,
+}
+
+/// Outcome of `Progress::reveal_hint`
+pub enum HintReveal {
+    /// The next tier was revealed
+    Revealed,
+    /// Every tier is already revealed
+    Exhausted,
+    /// The next tier unlocks after this many ms
+    Locked(f64),
+    /// The next tier costs points and was not bought
+    NeedsPurchase(u32),
+}
+
+impl Progress {
+    /// Marks the start of the session if it has not started yet
+    pub fn start(&mut self, now: f64) {
+        self.started_at.get_or_insert(now);
+    }
+
+    pub fn is_solved(&self, id: &str) -> bool {
+        self.solves.iter().any(|solve| solve.id == id)
+    }
+
+    pub fn solve(&self, id: &str) -> Option<&Solve> {
+        self.solves.iter().find(|solve| solve.id == id)
+    }
+
+    /// Points earned so far, minus points spent on hints
+    pub fn score(&self) -> i64 {
+        let earned: i64 = self.solves.iter().map(|solve| i64::from(solve.points)).sum();
+        let spent: i64 = self.hints.iter().map(|hint| i64::from(hint.cost)).sum();
+        earned - spent
+    }
+
+    /// Number of hint tiers revealed for a challenge
+    pub fn hints_revealed(&self, id: &str) -> usize {
+        self.hints.iter().filter(|hint| hint.id == id).count()
+    }
+
+    /// Reveals a challenge's next hint tier if its delay has passed
+    ///
+    /// # Arguments
+    /// * `challenge` - Challenge whose next tier to reveal
+    /// * `buy` - Whether the player agreed to pay the tier's point cost
+    /// * `now` - Current time (ms)
+    pub fn reveal_hint(&mut self, challenge: &ChallengeSpec, buy: bool, now: f64) -> HintReveal {
+        let Some(hint) = challenge.hints.get(self.hints_revealed(challenge.id)) else {
+            return HintReveal::Exhausted;
+        };
+
+        self.start(now);
+        let unlocks_at = self.started_at.unwrap_or(now) + hint.delay_minutes * 60_000.0;
+        if now < unlocks_at {
+            return HintReveal::Locked(unlocks_at - now);
+        }
+        if hint.cost > 0 && !buy {
+            return HintReveal::NeedsPurchase(hint.cost);
+        }
+
+        self.hints.push(HintUse {
+            id: challenge.id.to_string(),
+            cost: hint.cost,
+        });
+        HintReveal::Revealed
+    }
+
+    /// Time from the session start to a solve, in ms
+    pub fn solve_time(&self, solve: &Solve) -> f64 {
+        (solve.solved_at - self.started_at.unwrap_or(solve.solved_at)).max(0.0)
+    }
+
+    /// Checks a flag against every challenge and records a new solve
+    ///
+    /// # Arguments
+    /// * `flag` - Flag exactly as submitted
+    /// * `now` - Current time (ms), recorded as the solve time
+    pub fn submit(&mut self, flag: &str, now: f64) -> Submission {
+        let Some(challenge) = CHALLENGES
+            .iter()
+            .find(|challenge| verify_hash_string(flag, challenge.flag_hash))
+        else {
+            return Submission::Incorrect;
+        };
+
+        if self.is_solved(challenge.id) {
+            return Submission::AlreadySolved(challenge);
+        }
+        self.start(now);
+        self.solves.push(Solve {
+            id: challenge.id.to_string(),
+            points: challenge.points,
+            solved_at: now,
+        });
+        Submission::Solved(challenge)
+    }
+}
+
+/// Looks up a challenge by id
+pub fn find_challenge(id: &str) -> Option<&'static ChallengeSpec> {
+    CHALLENGES.iter().find(|challenge| challenge.id == id)
+}
+
+/// Total points available in the registry
+pub fn max_score() -> u32 {
+    CHALLENGES.iter().map(|challenge| challenge.points).sum()
+}
+
+/// Formats a duration in ms as `1h 02m 03s`, `4m 05s` or `6s`
+pub fn format_duration(ms: f64) -> String {
+    let secs = (ms / 1000.0).floor() as u64;
+    let (hours, minutes, seconds) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
@@ -0,0 +1,746 @@
+//! Shell parser module
+//! Turns a command line into an AST, handling quotes, escapes, variables,
+//! comments, pipes, redirections, `;`/`&&`/`||` lists and `( ... )` groups
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A piece of a word: literal text or a variable to expand at run time
+#[derive(Clone, Debug, PartialEq)]
+pub enum WordPart {
+    Literal(String),
+    /// `$NAME`, `${NAME}` or `$?` (stored as `"?"`)
+    Variable(String),
+}
+
+/// A single shell word, possibly built from several quoted and unquoted parts
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+impl Word {
+    /// Expands variables using `lookup`; unknown variables expand to ""
+    pub fn expand(&self, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                WordPart::Literal(text) => text.clone(),
+                WordPart::Variable(name) => lookup(name).unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Splits a leading `NAME=` off the word, if it is a valid assignment
+    fn split_assignment(&self) -> Option<(String, Word)> {
+        let Some(WordPart::Literal(first)) = self.parts.first() else {
+            return None;
+        };
+        let (name, rest) = first.split_once('=')?;
+        if !is_valid_name(name) {
+            return None;
+        }
+
+        let mut parts = Vec::new();
+        if !rest.is_empty() {
+            parts.push(WordPart::Literal(rest.to_string()));
+        }
+        parts.extend(self.parts[1..].iter().cloned());
+        Some((name.to_string(), Word { parts }))
+    }
+}
+
+/// Redirection operators
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RedirectKind {
+    /// `< file`
+    Input,
+    /// `> file`
+    Output,
+    /// `>> file`
+    Append,
+    /// `2> file`
+    Error,
+    /// `2>> file`
+    ErrorAppend,
+}
+
+impl fmt::Display for RedirectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            RedirectKind::Input => "<",
+            RedirectKind::Output => ">",
+            RedirectKind::Append => ">>",
+            RedirectKind::Error => "2>",
+            RedirectKind::ErrorAppend => "2>>",
+        };
+        f.write_str(op)
+    }
+}
+
+/// A redirection attached to a command, e.g. `> note.txt`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Redirect {
+    pub kind: RedirectKind,
+    pub target: Word,
+}
+
+/// A simple command: `[NAME=value ...] [program args ...] [redirects ...]`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SimpleCommand {
+    pub assignments: Vec<(String, Word)>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+impl SimpleCommand {
+    fn is_empty(&self) -> bool {
+        self.assignments.is_empty() && self.words.is_empty() && self.redirects.is_empty()
+    }
+
+    fn push_word(&mut self, word: Word) {
+        if self.words.is_empty()
+            && let Some(assignment) = word.split_assignment()
+        {
+            self.assignments.push(assignment);
+        } else {
+            self.words.push(word);
+        }
+    }
+}
+
+/// A pipeline element: a simple command or a `( ... )` group
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// Runs in a subshell: `cd` and variable changes do not leak out
+    Group {
+        body: Box<List>,
+        redirects: Vec<Redirect>,
+    },
+}
+
+/// Commands connected by `|`, each one's stdout feeding the next one's stdin
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+}
+
+/// Operator joining two pipelines in a list
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Connector {
+    /// `;` - always run the next pipeline
+    Sequence,
+    /// `&&` - run the next pipeline only if the previous one succeeded
+    And,
+    /// `||` - run the next pipeline only if the previous one failed
+    Or,
+}
+
+/// Pipelines joined by `;`, `&&` and `||`, evaluated left to right
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct List {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+}
+
+/// Errors produced while parsing a command line
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    UnterminatedQuote(char),
+    UnterminatedBrace,
+    BadSubstitution(String),
+    TrailingBackslash,
+    UnexpectedToken(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnterminatedQuote(q) => {
+                write!(f, "syntax error: unexpected end of input while looking for matching `{}'", q)
+            }
+            ParseError::UnterminatedBrace => {
+                write!(f, "syntax error: unexpected end of input while looking for matching `}}'")
+            }
+            ParseError::BadSubstitution(text) => write!(f, "${{{}}}: bad substitution", text),
+            ParseError::TrailingBackslash => write!(f, "syntax error: unexpected end of input after `\\'"),
+            ParseError::UnexpectedToken(token) => {
+                write!(f, "syntax error near unexpected token `{}'", token)
+            }
+        }
+    }
+}
+
+/// Checks whether `name` is a valid variable name
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+/// Lexical tokens: words and shell operators
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(Word),
+    Pipe,
+    Redirect(RedirectKind),
+    Connector(Connector),
+    OpenParen,
+    CloseParen,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word.expand(&|_| None)),
+            Token::Pipe => f.write_str("|"),
+            Token::Redirect(kind) => write!(f, "{}", kind),
+            Token::Connector(Connector::Sequence) => f.write_str(";"),
+            Token::Connector(Connector::And) => f.write_str("&&"),
+            Token::Connector(Connector::Or) => f.write_str("||"),
+            Token::OpenParen => f.write_str("("),
+            Token::CloseParen => f.write_str(")"),
+        }
+    }
+}
+
+/// Parses a command line into a list of pipelines
+///
+/// # Arguments
+/// * `input` - Raw command line
+///
+/// # Returns
+/// `None` for blank lines and comments, otherwise the parsed list
+pub fn parse(input: &str) -> Result<Option<List>, ParseError> {
+    let tokens = lex(input)?;
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+
+    let mut parser = Parser { tokens, pos: 0 };
+    let list = parser.list()?;
+    match parser.next() {
+        None => Ok(Some(list)),
+        Some(token) => Err(ParseError::UnexpectedToken(token.to_string())),
+    }
+}
+
+/// Recursive-descent parser over lexed tokens
+///
+/// ```text
+/// list     := pipeline (connector pipeline)* [";"]
+/// pipeline := command ("|" command)*
+/// command  := "(" list ")" redirect* | (word | redirect)+
+/// ```
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn unexpected(&self) -> ParseError {
+        let token = self
+            .peek()
+            .map(Token::to_string)
+            .unwrap_or_else(|| "newline".to_string());
+        ParseError::UnexpectedToken(token)
+    }
+
+    fn list(&mut self) -> Result<List, ParseError> {
+        let mut list = List {
+            first: self.pipeline()?,
+            rest: Vec::new(),
+        };
+
+        while let Some(Token::Connector(connector)) = self.peek() {
+            let connector = *connector;
+            self.pos += 1;
+
+            // A trailing `;` may end a list
+            if connector == Connector::Sequence
+                && matches!(self.peek(), None | Some(Token::CloseParen))
+            {
+                break;
+            }
+            list.rest.push((connector, self.pipeline()?));
+        }
+
+        Ok(list)
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let mut pipeline = Pipeline {
+            commands: vec![self.command()?],
+        };
+        while self.peek() == Some(&Token::Pipe) {
+            self.pos += 1;
+            pipeline.commands.push(self.command()?);
+        }
+        Ok(pipeline)
+    }
+
+    fn command(&mut self) -> Result<Command, ParseError> {
+        if self.peek() == Some(&Token::OpenParen) {
+            self.pos += 1;
+            let body = self.list()?;
+            if self.peek() != Some(&Token::CloseParen) {
+                return Err(self.unexpected());
+            }
+            self.pos += 1;
+
+            let mut redirects = Vec::new();
+            while let Some(Token::Redirect(kind)) = self.peek() {
+                let kind = *kind;
+                self.pos += 1;
+                redirects.push(self.redirect(kind)?);
+            }
+            return Ok(Command::Group {
+                body: Box::new(body),
+                redirects,
+            });
+        }
+
+        let mut command = SimpleCommand::default();
+        loop {
+            match self.peek() {
+                Some(Token::Word(word)) => {
+                    let word = word.clone();
+                    self.pos += 1;
+                    command.push_word(word);
+                }
+                Some(Token::Redirect(kind)) => {
+                    let kind = *kind;
+                    self.pos += 1;
+                    command.redirects.push(self.redirect(kind)?);
+                }
+                _ => break,
+            }
+        }
+
+        if command.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(Command::Simple(command))
+    }
+
+    fn redirect(&mut self, kind: RedirectKind) -> Result<Redirect, ParseError> {
+        match self.peek() {
+            Some(Token::Word(target)) => {
+                let target = target.clone();
+                self.pos += 1;
+                Ok(Redirect { kind, target })
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+}
+
+/// Accumulates the parts of the word currently being lexed
+#[derive(Default)]
+struct WordBuilder {
+    parts: Vec<WordPart>,
+    literal: String,
+    started: bool,
+    quoted: bool,
+}
+
+impl WordBuilder {
+    /// Checks whether the word so far is exactly `text`, with no quoting
+    fn is_bare(&self, text: &str) -> bool {
+        !self.quoted && self.parts.is_empty() && self.literal == text
+    }
+
+    fn push(&mut self, c: char) {
+        self.literal.push(c);
+        self.started = true;
+    }
+
+    fn push_variable(&mut self, name: String) {
+        self.flush_literal();
+        self.parts.push(WordPart::Variable(name));
+        self.started = true;
+    }
+
+    fn flush_literal(&mut self) {
+        if !self.literal.is_empty() {
+            self.parts.push(WordPart::Literal(std::mem::take(&mut self.literal)));
+        }
+    }
+
+    fn finish(&mut self, tokens: &mut Vec<Token>) {
+        self.flush_literal();
+        if self.started {
+            tokens.push(Token::Word(Word {
+                parts: std::mem::take(&mut self.parts),
+            }));
+        }
+        self.started = false;
+        self.quoted = false;
+    }
+
+    fn discard(&mut self) {
+        *self = WordBuilder::default();
+    }
+}
+
+fn lex(input: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut word = WordBuilder::default();
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' | '\r' => word.finish(&mut tokens),
+            // Comments only start at the beginning of a word
+            '#' if !word.started => break,
+            '|' => {
+                word.finish(&mut tokens);
+                if chars.next_if_eq(&'|').is_some() {
+                    tokens.push(Token::Connector(Connector::Or));
+                } else {
+                    tokens.push(Token::Pipe);
+                }
+            }
+            '&' => {
+                word.finish(&mut tokens);
+                // Background jobs are not supported, only `&&`
+                if chars.next_if_eq(&'&').is_none() {
+                    return Err(ParseError::UnexpectedToken("&".to_string()));
+                }
+                tokens.push(Token::Connector(Connector::And));
+            }
+            ';' => {
+                word.finish(&mut tokens);
+                tokens.push(Token::Connector(Connector::Sequence));
+            }
+            '(' => {
+                word.finish(&mut tokens);
+                tokens.push(Token::OpenParen);
+            }
+            ')' => {
+                word.finish(&mut tokens);
+                tokens.push(Token::CloseParen);
+            }
+            '<' => {
+                word.finish(&mut tokens);
+                tokens.push(Token::Redirect(RedirectKind::Input));
+            }
+            '>' => {
+                let append = chars.next_if_eq(&'>').is_some();
+                let kind = if word.is_bare("2") {
+                    word.discard();
+                    if append { RedirectKind::ErrorAppend } else { RedirectKind::Error }
+                } else {
+                    word.finish(&mut tokens);
+                    if append { RedirectKind::Append } else { RedirectKind::Output }
+                };
+                tokens.push(Token::Redirect(kind));
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(escaped) => {
+                    word.quoted = true;
+                    word.push(escaped);
+                }
+                None => return Err(ParseError::TrailingBackslash),
+            },
+            '\'' => {
+                word.started = true;
+                word.quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(ch) => word.push(ch),
+                        None => return Err(ParseError::UnterminatedQuote('\'')),
+                    }
+                }
+            }
+            '"' => {
+                word.started = true;
+                word.quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.peek() {
+                            Some('$' | '`' | '"' | '\\') => {
+                                let escaped = chars.next().unwrap_or('\\');
+                                word.push(escaped);
+                            }
+                            Some('\n') => {
+                                chars.next();
+                            }
+                            _ => word.push('\\'),
+                        },
+                        Some('$') => lex_variable(&mut chars, &mut word)?,
+                        Some(ch) => word.push(ch),
+                        None => return Err(ParseError::UnterminatedQuote('"')),
+                    }
+                }
+            }
+            '$' => lex_variable(&mut chars, &mut word)?,
+            _ => word.push(c),
+        }
+    }
+
+    word.finish(&mut tokens);
+    Ok(tokens)
+}
+
+/// Lexes the variable reference following a `$`
+fn lex_variable(chars: &mut Peekable<Chars<'_>>, word: &mut WordBuilder) -> Result<(), ParseError> {
+    match chars.peek() {
+        Some('?') => {
+            chars.next();
+            word.push_variable("?".to_string());
+        }
+        Some('{') => {
+            chars.next();
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(ch) => name.push(ch),
+                    None => return Err(ParseError::UnterminatedBrace),
+                }
+            }
+            if name != "?" && !is_valid_name(&name) {
+                return Err(ParseError::BadSubstitution(name));
+            }
+            word.push_variable(name);
+        }
+        Some(&c) if c == '_' || c.is_ascii_alphabetic() => {
+            let mut name = String::new();
+            while let Some(&ch) = chars.peek() {
+                if ch == '_' || ch.is_ascii_alphanumeric() {
+                    name.push(ch);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            word.push_variable(name);
+        }
+        _ => word.push('$'),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit(text: &str) -> WordPart {
+        WordPart::Literal(text.to_string())
+    }
+
+    fn var(name: &str) -> WordPart {
+        WordPart::Variable(name.to_string())
+    }
+
+    fn word(text: &str) -> Word {
+        Word {
+            parts: vec![lit(text)],
+        }
+    }
+
+    fn simple(words: &[&str]) -> Command {
+        Command::Simple(SimpleCommand {
+            words: words.iter().map(|text| word(text)).collect(),
+            ..SimpleCommand::default()
+        })
+    }
+
+    fn pipeline(commands: Vec<Command>) -> Pipeline {
+        Pipeline { commands }
+    }
+
+    fn parsed(input: &str) -> List {
+        parse(input).expect("parses").expect("not blank")
+    }
+
+    /// The only simple command of a one-command line
+    fn single(input: &str) -> SimpleCommand {
+        let list = parsed(input);
+        assert!(list.rest.is_empty());
+        match <[Command; 1]>::try_from(list.first.commands) {
+            Ok([Command::Simple(command)]) => command,
+            other => panic!("not a single simple command: {:?}", other),
+        }
+    }
+
+    fn error(input: &str) -> String {
+        parse(input).expect_err("syntax error").to_string()
+    }
+
+    #[test]
+    fn blank_lines_and_comments() {
+        assert_eq!(parse(""), Ok(None));
+        assert_eq!(parse("   \t"), Ok(None));
+        assert_eq!(parse("# just a comment"), Ok(None));
+        assert_eq!(single("echo a#b # note").words, [word("echo"), word("a#b")]);
+    }
+
+    #[test]
+    fn quoting_and_escapes() {
+        assert_eq!(
+            single("echo 'a  b' \"c  d\" e\\ f").words,
+            [word("echo"), word("a  b"), word("c  d"), word("e f")]
+        );
+        // Adjacent quoted and bare pieces form one word
+        assert_eq!(single("echo ab'cd'\"ef\"").words, [word("echo"), word("abcdef")]);
+        // Empty quotes are still a word
+        assert_eq!(single("echo '' \"\"").words, [word("echo"), Word::default(), Word::default()]);
+
+        // Single quotes keep everything; double quotes only escape $ ` " and \
+        assert_eq!(single(r"echo '\$x'").words[1], word(r"\$x"));
+        assert_eq!(single(r#"echo "\$x \n \"""#).words[1], word(r#"$x \n ""#));
+        assert_eq!(single("echo a\\\nb").words[1], word("ab"));
+
+        // Quoted operators are ordinary text
+        assert_eq!(single(r"echo '|;&&>' \;").words, [word("echo"), word("|;&&>"), word(";")]);
+    }
+
+    #[test]
+    fn variable_expansion() {
+        let command = single("echo $HOME/x \"${USER}!\" '$PATH' $? $ a$");
+        assert_eq!(command.words[1].parts, [var("HOME"), lit("/x")]);
+        assert_eq!(command.words[2].parts, [var("USER"), lit("!")]);
+        assert_eq!(command.words[3], word("$PATH"));
+        assert_eq!(command.words[4].parts, [var("?")]);
+        assert_eq!(command.words[5], word("$"));
+        assert_eq!(command.words[6], word("a$"));
+
+        let lookup = |name: &str| (name == "HOME").then(|| "/home/user".to_string());
+        assert_eq!(command.words[1].expand(&lookup), "/home/user/x");
+        assert_eq!(command.words[2].expand(&lookup), "!");
+    }
+
+    #[test]
+    fn assignments() {
+        let command = single("A=1 B=\"x y\" env C=2");
+        assert_eq!(
+            command.assignments,
+            [("A".to_string(), word("1")), ("B".to_string(), word("x y"))]
+        );
+        assert_eq!(command.words, [word("env"), word("C=2")]);
+
+        let command = single("A= 1x=2");
+        assert_eq!(command.assignments, [("A".to_string(), Word::default())]);
+        assert_eq!(command.words, [word("1x=2")]);
+    }
+
+    #[test]
+    fn lists_and_pipelines() {
+        assert_eq!(
+            parsed("a | b x; c && d || e;"),
+            List {
+                first: pipeline(vec![simple(&["a"]), simple(&["b", "x"])]),
+                rest: vec![
+                    (Connector::Sequence, pipeline(vec![simple(&["c"])])),
+                    (Connector::And, pipeline(vec![simple(&["d"])])),
+                    (Connector::Or, pipeline(vec![simple(&["e"])])),
+                ],
+            }
+        );
+        // Operators need no surrounding spaces
+        assert_eq!(parsed("a&&b"), parsed("a && b"));
+        assert_eq!(parsed("a|b;c"), parsed("a | b ; c"));
+    }
+
+    #[test]
+    fn subshells() {
+        let list = parsed("(cd /tmp; ls) > out && (a || (b))");
+        let [Command::Group { body, redirects }] = &list.first.commands[..] else {
+            panic!("not a group: {:?}", list.first);
+        };
+        assert_eq!(
+            **body,
+            List {
+                first: pipeline(vec![simple(&["cd", "/tmp"])]),
+                rest: vec![(Connector::Sequence, pipeline(vec![simple(&["ls"])]))],
+            }
+        );
+        assert_eq!(
+            redirects,
+            &[Redirect {
+                kind: RedirectKind::Output,
+                target: word("out"),
+            }]
+        );
+
+        let (Connector::And, inner) = &list.rest[0] else {
+            panic!("not joined by &&: {:?}", list.rest);
+        };
+        let [Command::Group { body, .. }] = &inner.commands[..] else {
+            panic!("not a group: {:?}", inner);
+        };
+        assert_eq!(body.rest[0].0, Connector::Or);
+        assert!(matches!(body.rest[0].1.commands[..], [Command::Group { .. }]));
+    }
+
+    #[test]
+    fn redirects() {
+        let command = single("sort <in >out 2>err x >>log 2>>errlog");
+        let kinds: Vec<(RedirectKind, Word)> = command
+            .redirects
+            .into_iter()
+            .map(|redirect| (redirect.kind, redirect.target))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (RedirectKind::Input, word("in")),
+                (RedirectKind::Output, word("out")),
+                (RedirectKind::Error, word("err")),
+                (RedirectKind::Append, word("log")),
+                (RedirectKind::ErrorAppend, word("errlog")),
+            ]
+        );
+        assert_eq!(command.words, [word("sort"), word("x")]);
+
+        // Only a bare `2` names stderr
+        let command = single("echo a2>f '2'>g");
+        assert_eq!(command.words, [word("echo"), word("a2"), word("2")]);
+        assert!(command.redirects.iter().all(|r| r.kind == RedirectKind::Output));
+
+        // A redirect alone is a command
+        assert_eq!(single("> empty").redirects[0].target, word("empty"));
+        assert_eq!(single("cat > \"$F\"").redirects[0].target.parts, [var("F")]);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let eof = "syntax error: unexpected end of input while looking for matching";
+        assert_eq!(error("echo 'abc"), format!("{} `''", eof));
+        assert_eq!(error("echo \"abc"), format!("{} `\"'", eof));
+        assert_eq!(error("echo ${HOME"), format!("{} `}}'", eof));
+        assert_eq!(error("echo \\"), "syntax error: unexpected end of input after `\\'");
+        assert_eq!(error("echo ${1x}"), "${1x}: bad substitution");
+
+        let near = |token: &str| format!("syntax error near unexpected token `{}'", token);
+        assert_eq!(error("ls &&"), near("newline"));
+        assert_eq!(error("ls ||"), near("newline"));
+        assert_eq!(error("ls |"), near("newline"));
+        assert_eq!(error("echo hi >"), near("newline"));
+        assert_eq!(error("echo hi > | cat"), near("|"));
+        assert_eq!(error("&& ls"), near("&&"));
+        assert_eq!(error("ls ;;"), near(";"));
+        assert_eq!(error("ls &"), near("&"));
+        assert_eq!(error("(ls"), near("newline"));
+        assert_eq!(error("ls)"), near(")"));
+        assert_eq!(error("()"), near(")"));
+        assert_eq!(error("(ls) x"), near("x"));
+    }
+}
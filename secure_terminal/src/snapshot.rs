@@ -0,0 +1,100 @@
+//! Snapshot module
+//! Saves a terminal's filesystem, shell session and sudo state as a versioned
+//! JSON document and restores it, so a browser tab or REPL can resume later
+
+use serde::{Deserialize, Serialize};
+
+use crate::filesystem::FileSystem;
+use crate::{SudoState, TerminalState};
+
+/// Identifies snapshot documents
+pub const SNAPSHOT_FORMAT: &str = "secure-terminal-snapshot";
+
+/// Bumped whenever the snapshot layout changes incompatibly
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Fields checked before the rest of the document is trusted
+#[derive(Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+}
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    format: &'static str,
+    version: u32,
+    fs: &'a FileSystem,
+    term: &'a TerminalState,
+    sudo: &'a SudoState,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    fs: FileSystem,
+    term: TerminalState,
+    sudo: SudoState,
+}
+
+/// Serializes the state of one terminal
+///
+/// File contents stay encrypted and are stored as base64. Configuration and
+/// half-finished password prompts are not part of the snapshot.
+///
+/// # Returns
+/// JSON object `{ format, version, fs, term, sudo }`
+pub fn export(fs: &FileSystem, sudo: &SudoState, term: &TerminalState) -> String {
+    let snapshot = SnapshotRef {
+        format: SNAPSHOT_FORMAT,
+        version: SNAPSHOT_VERSION,
+        fs,
+        term,
+        sudo,
+    };
+    serde_json::to_string(&snapshot).unwrap_or_default()
+}
+
+/// Parses and validates a snapshot produced by `export`
+///
+/// # Returns
+/// The restored state, or a message explaining why the snapshot was rejected
+pub fn import(json: &str) -> Result<(FileSystem, SudoState, TerminalState), String> {
+    let header: Header =
+        serde_json::from_str(json).map_err(|err| format!("invalid snapshot: {}", err))?;
+    if header.format != SNAPSHOT_FORMAT {
+        return Err(format!("invalid snapshot: unknown format '{}'", header.format));
+    }
+    if header.version != SNAPSHOT_VERSION {
+        return Err(format!(
+            "unsupported snapshot version {} (expected {})",
+            header.version, SNAPSHOT_VERSION
+        ));
+    }
+
+    let mut snapshot: Snapshot =
+        serde_json::from_str(json).map_err(|err| format!("invalid snapshot: {}", err))?;
+    snapshot
+        .fs
+        .check_integrity()
+        .map_err(|err| format!("corrupt snapshot: {}", err))?;
+    snapshot.fs.recount();
+
+    Ok((snapshot.fs, snapshot.sudo, snapshot.term))
+}
+
+/// Serde adapter storing byte buffers as base64 strings
+pub mod base64_bytes {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::encryption::{base64_decode, base64_encode};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64_encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        base64_decode(&text).ok_or_else(|| D::Error::custom("invalid base64 content"))
+    }
+}
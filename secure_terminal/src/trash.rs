@@ -0,0 +1,188 @@
+//! Trash module
+//! Per-user recycle bin: `rm` renames nodes into `~/.trash` instead of
+//! freeing them, so a mistaken removal can be undone with `trash restore`
+
+use crate::filesystem::{Credentials, FileSystem, FileType, FsError};
+use crate::users;
+
+/// Name of the trash directory inside a user's home
+const TRASH_DIR: &str = ".trash";
+
+/// A node waiting in the trash
+#[derive(Clone, Debug)]
+pub struct TrashEntry {
+    /// Name inside the trash directory
+    pub name: String,
+    /// Absolute path the node was removed from
+    pub original: String,
+    /// Removal time (the node's change time), ms since the epoch
+    pub deleted_at: f64,
+    pub is_dir: bool,
+}
+
+/// Returns the trash directory of a user, or `None` without a usable home
+pub fn trash_dir(fs: &FileSystem, uid: u32) -> Option<String> {
+    let home = users::account_by_uid(fs, uid)?.home;
+    if !fs.is_dir(&home) {
+        return None;
+    }
+    Some(if home == "/" {
+        format!("/{}", TRASH_DIR)
+    } else {
+        format!("{}/{}", home.trim_end_matches('/'), TRASH_DIR)
+    })
+}
+
+/// Whether a physical path is a user's trash directory or lies inside it
+pub fn contains(fs: &FileSystem, uid: u32, path: &str) -> bool {
+    trash_dir(fs, uid).is_some_and(|dir| path == dir || path.starts_with(&format!("{}/", dir)))
+}
+
+/// Encodes an absolute path as a single file name (`/tmp/a` → `%2Ftmp%2Fa`)
+fn encode(path: &str) -> String {
+    path.replace('%', "%25").replace('/', "%2F").replace('~', "%7E")
+}
+
+/// Recovers the original path from a trash name, ignoring a `~N` suffix
+fn decode(name: &str) -> Option<String> {
+    let encoded = name.split('~').next()?;
+    let mut path = String::new();
+    let mut rest = encoded;
+    while let Some(idx) = rest.find('%') {
+        path.push_str(&rest[..idx]);
+        let escape = rest.get(idx + 1..idx + 3)?;
+        path.push(u8::from_str_radix(escape, 16).ok()? as char);
+        rest = &rest[idx + 3..];
+    }
+    path.push_str(rest);
+    path.starts_with('/').then_some(path)
+}
+
+/// Moves a node into a user's trash, creating the trash directory (mode 700,
+/// owned by the user) if needed
+///
+/// # Arguments
+/// * `creds` - Identity whose trash receives the node
+/// * `path` - Absolute path of the node; a symlink is moved itself
+/// * `now` - Removal time, recorded in the node's change time
+///
+/// # Returns
+/// `NotFound` when the user has no home, `NoSpace` when the trash directory
+/// does not fit on the disk
+pub fn put(fs: &mut FileSystem, creds: &Credentials, path: &str, now: f64) -> Result<(), FsError> {
+    let dir = trash_dir(fs, creds.uid).ok_or(FsError::NotFound)?;
+    if !fs.is_dir(&dir) {
+        fs.create_dir(&dir, now)?;
+        fs.set_mode(&dir, 0o700)?;
+        fs.set_owner(&dir, Some(creds.uid), Some(creds.gid))?;
+    }
+
+    let base = encode(path);
+    let mut name = base.clone();
+    let mut copy = 1;
+    while fs.lexists(&format!("{}/{}", dir, name)) {
+        copy += 1;
+        name = format!("{}~{}", base, copy);
+    }
+
+    let dest = format!("{}/{}", dir, name);
+    fs.move_path(path, &dest)?;
+    fs.mark_changed(&dest, false, now)
+}
+
+/// Lists a user's trash, oldest removal first
+pub fn entries(fs: &FileSystem, uid: u32) -> Vec<TrashEntry> {
+    let Some(dir) = trash_dir(fs, uid) else {
+        return Vec::new();
+    };
+    let names = fs.list_directory(&dir).unwrap_or_default();
+
+    let mut entries: Vec<TrashEntry> = names
+        .into_iter()
+        .filter_map(|listed| {
+            let name = listed.trim_end_matches('/').to_string();
+            let node = fs.symlink_metadata(&format!("{}/{}", dir, name)).ok()?;
+            Some(TrashEntry {
+                original: decode(&name)?,
+                deleted_at: node.ctime,
+                is_dir: node.ftype == FileType::Directory,
+                name,
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| a.deleted_at.total_cmp(&b.deleted_at).then(a.name.cmp(&b.name)));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::Cipher;
+
+    #[test]
+    fn names_round_trip_to_paths() {
+        for path in ["/tmp/a", "/home/100%/x~y", "/"] {
+            assert_eq!(decode(&encode(path)).as_deref(), Some(path));
+        }
+        assert_eq!(encode("/tmp/a"), "%2Ftmp%2Fa");
+        assert_eq!(decode("%2Ftmp%2Fa~3").as_deref(), Some("/tmp/a"));
+        assert_eq!(decode("relative"), None);
+        assert_eq!(decode("%2"), None);
+    }
+
+    #[test]
+    fn put_moves_nodes_into_a_private_trash() {
+        let mut fs = FileSystem::new();
+        let creds = users::player_credentials();
+        fs.write_file("/tmp/a", b"first", Cipher::Plain, 1.0).unwrap();
+        put(&mut fs, &creds, "/tmp/a", 5.0).unwrap();
+
+        assert!(!fs.lexists("/tmp/a"));
+        let dir = trash_dir(&fs, creds.uid).unwrap();
+        assert_eq!(dir, "/home/.trash");
+        let node = fs.metadata(&dir).unwrap();
+        assert_eq!((node.mode, node.uid, node.gid), (0o700, creds.uid, creds.gid));
+        assert!(contains(&fs, creds.uid, "/home/.trash/%2Ftmp%2Fa"));
+        assert!(!contains(&fs, creds.uid, "/home/.trashy"));
+
+        let trashed = entries(&fs, creds.uid);
+        assert_eq!(trashed.len(), 1);
+        assert_eq!(trashed[0].original, "/tmp/a");
+        assert_eq!(trashed[0].deleted_at, 5.0);
+        assert!(!trashed[0].is_dir);
+    }
+
+    #[test]
+    fn repeated_removals_keep_every_copy() {
+        let mut fs = FileSystem::new();
+        let creds = users::player_credentials();
+        for (content, now) in [(&b"old"[..], 1.0), (&b"new"[..], 2.0)] {
+            fs.write_file("/tmp/a", content, Cipher::Plain, now).unwrap();
+            put(&mut fs, &creds, "/tmp/a", now).unwrap();
+        }
+        fs.create_dir("/tmp/d", 3.0).unwrap();
+        put(&mut fs, &creds, "/tmp/d", 3.0).unwrap();
+
+        let trashed = entries(&fs, creds.uid);
+        let names: Vec<&str> = trashed.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["%2Ftmp%2Fa", "%2Ftmp%2Fa~2", "%2Ftmp%2Fd"]);
+        assert!(trashed[2].is_dir);
+        let newest = fs.read_file("/home/.trash/%2Ftmp%2Fa~2").unwrap();
+        assert_eq!(newest, b"new");
+    }
+
+    #[test]
+    fn users_without_a_home_have_no_trash() {
+        let mut fs = FileSystem::new();
+        let nobody = Credentials {
+            uid: 65534,
+            gid: 65534,
+            groups: Vec::new(),
+        };
+        fs.write_file("/tmp/a", b"", Cipher::Plain, 1.0).unwrap();
+        assert_eq!(trash_dir(&fs, nobody.uid), None);
+        assert_eq!(put(&mut fs, &nobody, "/tmp/a", 2.0), Err(FsError::NotFound));
+        assert!(fs.lexists("/tmp/a"));
+        assert!(entries(&fs, nobody.uid).is_empty());
+    }
+}
@@ -0,0 +1,338 @@
+//! User database module
+//! Accounts, groups and password hashes stored in /etc/passwd, /etc/group
+//! and /etc/shadow inside the virtual filesystem
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::challenge;
+use crate::encryption::{hash_string, random_salt, verify_hash_string, Cipher};
+use crate::filesystem::{Credentials, FileSystem, FsError, PLAYER_GID, PLAYER_UID};
+
+pub const PASSWD_PATH: &str = "/etc/passwd";
+pub const GROUP_PATH: &str = "/etc/group";
+pub const SHADOW_PATH: &str = "/etc/shadow";
+
+/// Supplementary group allowed to use sudo
+pub const SUDO_GID: u32 = 27;
+
+/// A line of /etc/passwd
+#[derive(Clone, Debug, PartialEq)]
+pub struct Account {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub gecos: String,
+    pub home: String,
+    pub shell: String,
+}
+
+impl Account {
+    /// Parses `name:x:uid:gid:gecos:home:shell`
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() != 7 {
+            return None;
+        }
+        Some(Account {
+            name: fields[0].to_string(),
+            uid: fields[2].parse().ok()?,
+            gid: fields[3].parse().ok()?,
+            gecos: fields[4].to_string(),
+            home: fields[5].to_string(),
+            shell: fields[6].to_string(),
+        })
+    }
+
+    /// Whether the account's shell permits interactive sessions
+    pub fn can_login(&self) -> bool {
+        !matches!(self.shell.as_str(), "/bin/false" | "/usr/sbin/nologin" | "/sbin/nologin")
+    }
+}
+
+/// A line of /etc/group
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+    pub name: String,
+    pub gid: u32,
+    pub members: Vec<String>,
+}
+
+impl Group {
+    /// Parses `name:x:gid:member,member`
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() != 4 {
+            return None;
+        }
+        Some(Group {
+            name: fields[0].to_string(),
+            gid: fields[2].parse().ok()?,
+            members: fields[3]
+                .split(',')
+                .filter(|member| !member.is_empty())
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+}
+
+/// Identity saved by `su`, restored by `exit`/`logout`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub user: Credentials,
+    pub cwd: String,
+    pub env: HashMap<String, String>,
+}
+
+/// Step of the `passwd` dialogue
+#[derive(Clone, Debug, PartialEq)]
+pub enum PasswdStage {
+    Current,
+    New,
+    Retype(String), // Holds the first entry of the new password
+}
+
+/// A command waiting for a password typed at a hidden prompt
+#[derive(Clone, Debug, PartialEq)]
+pub enum PendingAuth {
+    Su { account: String, login: bool },
+    Login { account: String },
+    Passwd { account: String, stage: PasswdStage },
+}
+
+/// Player account name, kept XOR-obfuscated like the other puzzle strings
+pub fn player_name() -> String {
+    Cipher::Xor.decode(&[185, 142, 159, 140, 18])
+}
+
+/// Identity the terminal starts with
+pub fn player_credentials() -> Credentials {
+    Credentials {
+        uid: PLAYER_UID,
+        gid: PLAYER_GID,
+        groups: vec![SUDO_GID],
+    }
+}
+
+/// Writes the initial user database (called by `FileSystem::new`)
+///
+/// # Arguments
+/// * `fs` - Filesystem to seed; /etc and /root must already exist
+pub fn seed(fs: &mut FileSystem) -> Result<(), FsError> {
+    let player = player_name();
+
+    let passwd = [
+        "root:x:0:0:root:/root:/bin/sh".to_string(),
+        format!("{0}:x:{1}:{2}:{0}:/home:/bin/sh", player, PLAYER_UID, PLAYER_GID),
+        "nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin".to_string(),
+    ];
+    let group = [
+        "root:x:0:".to_string(),
+        format!("sudo:x:{}:{}", SUDO_GID, player),
+        format!("{}:x:{}:", player, PLAYER_GID),
+        "nogroup:x:65534:".to_string(),
+    ];
+    // Root is locked (`!`): become root through sudo, then `su` needs no password
+    let shadow = [
+        "root:!:19700:0:99999:7:::".to_string(),
+        format!("{}:{}:19700:0:99999:7:::", player, challenge::PLAYER_PASSWORD_HASH),
+        "nobody:*:19700:0:99999:7:::".to_string(),
+    ];
+
+    write_table(fs, PASSWD_PATH, &passwd, 0.0)?;
+    write_table(fs, GROUP_PATH, &group, 0.0)?;
+    write_table(fs, SHADOW_PATH, &shadow, 0.0)?;
+    fs.set_mode(SHADOW_PATH, 0o600)
+}
+
+fn read_table(fs: &FileSystem, path: &str) -> Vec<String> {
+    fs.read_file(path)
+        .map(|content| {
+            String::from_utf8_lossy(&content)
+                .lines()
+                .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn write_table(
+    fs: &mut FileSystem,
+    path: &str,
+    lines: &[String],
+    now: f64,
+) -> Result<(), FsError> {
+    let mut content = lines.join("\n");
+    content.push('\n');
+    let cipher = fs.file_cipher(path).unwrap_or_default();
+    fs.write_file(path, content.as_bytes(), cipher, now)
+}
+
+/// All accounts in /etc/passwd
+pub fn accounts(fs: &FileSystem) -> Vec<Account> {
+    read_table(fs, PASSWD_PATH)
+        .iter()
+        .filter_map(|line| Account::parse(line))
+        .collect()
+}
+
+/// All groups in /etc/group
+pub fn groups(fs: &FileSystem) -> Vec<Group> {
+    read_table(fs, GROUP_PATH)
+        .iter()
+        .filter_map(|line| Group::parse(line))
+        .collect()
+}
+
+/// Looks up an account by name
+pub fn find_account(fs: &FileSystem, name: &str) -> Option<Account> {
+    accounts(fs).into_iter().find(|account| account.name == name)
+}
+
+/// Looks up an account by uid
+pub fn account_by_uid(fs: &FileSystem, uid: u32) -> Option<Account> {
+    accounts(fs).into_iter().find(|account| account.uid == uid)
+}
+
+/// Maps a uid to its account name, falling back to the number
+pub fn user_name(fs: &FileSystem, uid: u32) -> String {
+    account_by_uid(fs, uid)
+        .map(|account| account.name)
+        .unwrap_or_else(|| uid.to_string())
+}
+
+/// Maps a gid to its group name, falling back to the number
+pub fn group_name(fs: &FileSystem, gid: u32) -> String {
+    groups(fs)
+        .into_iter()
+        .find(|group| group.gid == gid)
+        .map(|group| group.name)
+        .unwrap_or_else(|| gid.to_string())
+}
+
+/// Owner and group names read once, for commands that print them per file
+pub struct Names {
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+}
+
+impl Names {
+    /// Reads /etc/passwd and /etc/group; the first line for an id wins
+    pub fn load(fs: &FileSystem) -> Self {
+        let mut users = HashMap::new();
+        for account in accounts(fs) {
+            users.entry(account.uid).or_insert(account.name);
+        }
+        let mut groups = HashMap::new();
+        for group in self::groups(fs) {
+            groups.entry(group.gid).or_insert(group.name);
+        }
+        Names { users, groups }
+    }
+
+    /// Like `user_name`, without re-reading /etc/passwd
+    pub fn user(&self, uid: u32) -> String {
+        self.users.get(&uid).cloned().unwrap_or_else(|| uid.to_string())
+    }
+
+    /// Like `group_name`, without re-reading /etc/group
+    pub fn group(&self, gid: u32) -> String {
+        self.groups.get(&gid).cloned().unwrap_or_else(|| gid.to_string())
+    }
+}
+
+/// Resolves an account name or numeric uid
+pub fn resolve_user(fs: &FileSystem, spec: &str) -> Option<u32> {
+    find_account(fs, spec)
+        .map(|account| account.uid)
+        .or_else(|| spec.parse().ok())
+}
+
+/// Resolves a group name or numeric gid
+pub fn resolve_group(fs: &FileSystem, spec: &str) -> Option<u32> {
+    groups(fs)
+        .into_iter()
+        .find(|group| group.name == spec)
+        .map(|group| group.gid)
+        .or_else(|| spec.parse().ok())
+}
+
+/// Builds the credentials of a session running as `account`
+///
+/// Supplementary groups are every group listing the account as a member.
+pub fn credentials_for(fs: &FileSystem, account: &Account) -> Credentials {
+    let groups = groups(fs)
+        .into_iter()
+        .filter(|group| group.gid != account.gid && group.members.contains(&account.name))
+        .map(|group| group.gid)
+        .collect();
+
+    Credentials {
+        uid: account.uid,
+        gid: account.gid,
+        groups,
+    }
+}
+
+/// Password field of an account's /etc/shadow entry
+fn shadow_hash(fs: &FileSystem, name: &str) -> Option<String> {
+    read_table(fs, SHADOW_PATH).iter().find_map(|line| {
+        let mut fields = line.split(':');
+        (fields.next() == Some(name)).then(|| fields.next().unwrap_or("").to_string())
+    })
+}
+
+/// Whether logging in as `name` requires a password (empty shadow field = no)
+pub fn requires_password(fs: &FileSystem, name: &str) -> bool {
+    shadow_hash(fs, name).is_none_or(|hash| !hash.is_empty())
+}
+
+/// Checks a password against the account's /etc/shadow entry
+///
+/// Locked (`!`, `*`) or malformed entries never match.
+pub fn check_password(fs: &FileSystem, name: &str, password: &str) -> bool {
+    let Some(hash) = shadow_hash(fs, name) else {
+        return false;
+    };
+    if hash.is_empty() {
+        return true;
+    }
+
+    verify_hash_string(password, &hash)
+}
+
+/// Replaces an account's password hash with a freshly salted one
+///
+/// # Returns
+/// `FsError::NotFound` if the account has no /etc/shadow entry
+pub fn set_password(
+    fs: &mut FileSystem,
+    name: &str,
+    password: &str,
+    now: f64,
+) -> Result<(), FsError> {
+    let field = hash_string(password, &random_salt());
+
+    let mut found = false;
+    let lines: Vec<String> = read_table(fs, SHADOW_PATH)
+        .into_iter()
+        .map(|line| {
+            let mut fields: Vec<&str> = line.split(':').collect();
+            if fields.first() == Some(&name) && fields.len() > 1 {
+                found = true;
+                fields[1] = &field;
+                fields.join(":")
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    if !found {
+        return Err(FsError::NotFound);
+    }
+    write_table(fs, SHADOW_PATH, &lines, now)
+}